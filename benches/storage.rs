@@ -0,0 +1,43 @@
+//! Compare `allocate_by_size` across storages on a fragmented set.
+//!
+//! Run with `cargo bench --features soa` to include `SoaStorage`.
+
+use region_alloc::{BTreeStorage, RegionAllocator, RegionStorage, SizeClassStorage, VecStorage};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const REGIONS: usize = 4096;
+const ROUNDS: usize = 200;
+
+/// Build a set of small fragments followed by a single large region at the top.
+fn fragmented<S: RegionStorage>(storage: S) -> RegionAllocator<S> {
+    let mut alloc = RegionAllocator::with_storage(storage);
+    for i in 0..REGIONS {
+        alloc.add(i * 0x1000, 0x800);
+    }
+    alloc.add(REGIONS * 0x1000, 0x100_0000);
+    alloc
+}
+
+fn bench<S: RegionStorage + Default>(name: &str) {
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        let mut alloc = fragmented(S::default());
+        let start = Instant::now();
+        for _ in 0..16 {
+            let _ = black_box(alloc.allocate_by_size(0x1000, 0x1000));
+            let _ = black_box(alloc.allocate_by_size(0x10, 0x10));
+        }
+        elapsed += start.elapsed();
+    }
+    let per_round = elapsed / ROUNDS as u32;
+    println!("{:<16} {:>10.1?} per round", name, per_round);
+}
+
+fn main() {
+    bench::<BTreeStorage>("BTreeStorage");
+    bench::<VecStorage>("VecStorage");
+    bench::<SizeClassStorage>("SizeClassStorage");
+    #[cfg(feature = "soa")]
+    bench::<region_alloc::SoaStorage>("SoaStorage");
+}
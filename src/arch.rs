@@ -0,0 +1,7 @@
+//! Paging helpers for particular architectures, and adapters to the paging interfaces
+//! of architecture crates.
+
+pub mod aarch64;
+pub mod riscv;
+#[cfg(feature = "x86_64")]
+mod x86_64;
@@ -0,0 +1,145 @@
+//! Frame allocation for the AArch64 translation granules.
+
+use crate::{RegionAllocator, RegionError, RegionStorage};
+
+/// An AArch64 translation granule, the page size of a translation regime.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Granule {
+    K4,
+    K16,
+    K64,
+}
+
+impl Granule {
+    /// Return the granule size in bytes, which is also the size of a translation table.
+    pub const fn size(self) -> usize {
+        match self {
+            Granule::K4 => 0x1000,
+            Granule::K16 => 0x4000,
+            Granule::K64 => 0x10000,
+        }
+    }
+    /// Return the size a descriptor at `level` maps: a page at level 3, a block above.
+    ///
+    /// With 48-bit output addresses, blocks exist at levels 1 and 2 for the 4 KiB granule
+    /// and at level 2 for the others. Return `None` for the levels without, or if the size
+    /// does not fit in `usize`.
+    pub const fn block_size(self, level: u32) -> Option<usize> {
+        let max = match self {
+            Granule::K4 => 2,
+            Granule::K16 | Granule::K64 => 1,
+        };
+        if level > 3 || 3 - level > max {
+            return None;
+        }
+        // Each level resolves as many bits as a table has descriptors
+        let bits = self.size().trailing_zeros() - 3;
+        self.size().checked_shl(bits * (3 - level))
+    }
+    /// Round `addr` down to a granule boundary.
+    pub const fn align_down(self, addr: usize) -> usize {
+        addr & !(self.size() - 1)
+    }
+    /// Round `size` up to whole granules, or return `None` on overflow.
+    pub const fn align_up(self, size: usize) -> Option<usize> {
+        match size.checked_add(self.size() - 1) {
+            Some(size) => Some(self.align_down(size)),
+            None => None,
+        }
+    }
+}
+
+/// A [`RegionAllocator`] handing out memory in whole granules, and naturally aligned
+/// blocks for block mappings.
+///
+/// Sizes given to it are rounded up to the granule, so callers need no rounding of
+/// their own.
+#[derive(Clone, Debug)]
+pub struct GranuleAllocator<S: RegionStorage> {
+    regions: RegionAllocator<S>,
+    granule: Granule,
+}
+
+impl<S: RegionStorage> GranuleAllocator<S> {
+    /// Wrap `regions` holding the free physical memory.
+    pub fn new(regions: RegionAllocator<S>, granule: Granule) -> Self {
+        GranuleAllocator { regions, granule }
+    }
+    pub fn granule(&self) -> Granule {
+        self.granule
+    }
+    /// Return the free physical memory.
+    pub fn regions(&self) -> &RegionAllocator<S> {
+        &self.regions
+    }
+    pub fn into_inner(self) -> RegionAllocator<S> {
+        self.regions
+    }
+    /// Allocate `size` bytes rounded up to whole granules, aligned to the granule, and
+    /// return the base and the rounded size.
+    pub fn allocate_pages(&mut self, size: usize) -> Result<(usize, usize), RegionError> {
+        let size = self.granule.align_up(size).ok_or(RegionError::Overflow)?;
+        self.regions.allocate_by_size(size, self.granule.size())
+    }
+    /// Allocate a memory block for a descriptor at `level`, sized and aligned to what it
+    /// maps, and return its base.
+    ///
+    /// Fail with [`RegionError::InvalidAlignment`] if the granule has no blocks at
+    /// `level`.
+    pub fn allocate_block(&mut self, level: u32) -> Result<usize, RegionError> {
+        let size = self
+            .granule
+            .block_size(level)
+            .ok_or(RegionError::InvalidAlignment)?;
+        self.regions
+            .allocate_by_size(size, size)
+            .map(|(base, _)| base)
+    }
+    /// Give back `size` bytes at `base`, both rounded out to whole granules, as returned
+    /// by [`GranuleAllocator::allocate_pages`] or [`GranuleAllocator::allocate_block`].
+    ///
+    /// Fail with [`RegionError::Overlapping`] if any of it is already free.
+    pub fn deallocate(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        let start = self.granule.align_down(base);
+        let end = base.checked_add(size).ok_or(RegionError::Overflow)?;
+        let end = self.granule.align_up(end).ok_or(RegionError::Overflow)?;
+        self.regions.add_checked(start, end - start)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::{Granule, GranuleAllocator};
+    use crate::{RegionAllocator, RegionError};
+
+    #[test]
+    fn granule_test() {
+        assert_eq!(Granule::K4.block_size(3), Some(0x1000));
+        assert_eq!(Granule::K4.block_size(2), Some(0x20_0000));
+        assert_eq!(Granule::K4.block_size(1), Some(0x4000_0000));
+        assert_eq!(Granule::K4.block_size(0), None);
+        assert_eq!(Granule::K16.block_size(2), Some(0x200_0000));
+        assert_eq!(Granule::K16.block_size(1), None);
+        assert_eq!(Granule::K64.block_size(2), Some(0x2000_0000));
+        assert_eq!(Granule::K64.align_up(1), Some(0x10000));
+        assert_eq!(Granule::K64.align_up(usize::MAX), None);
+    }
+
+    #[test]
+    fn granule_allocator_test() {
+        let mut ram = RegionAllocator::new();
+        ram.add(0x4000_0000, 0x400_0000);
+        let mut frames = GranuleAllocator::new(ram, Granule::K16);
+        assert_eq!(frames.allocate_pages(0x100), Ok((0x4000_0000, 0x4000)));
+        // The block skips the partly used first 32 MiB
+        assert_eq!(frames.allocate_block(2), Ok(0x4200_0000));
+        assert_eq!(frames.allocate_block(2), Err(RegionError::NoFit));
+        assert_eq!(frames.allocate_block(1), Err(RegionError::InvalidAlignment));
+        assert_eq!(frames.deallocate(0x4000_0010, 0x100), Ok(()));
+        assert_eq!(
+            frames.deallocate(0x4000_0000, 1),
+            Err(RegionError::Overlapping)
+        );
+        assert!(frames.regions().check_region(0x4000_0000, 0x200_0000));
+    }
+}
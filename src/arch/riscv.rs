@@ -0,0 +1,128 @@
+//! Frame allocation for the RISC-V Sv39 and Sv48 paging modes.
+
+use crate::{RegionAllocator, RegionError, RegionStorage};
+
+/// The size of a base page, and of a page table.
+pub const PAGE_SIZE: usize = 0x1000;
+
+/// Return the physical page number a page table entry holds for `addr`.
+pub const fn ppn(addr: usize) -> usize {
+    addr / PAGE_SIZE
+}
+
+/// A RISC-V virtual memory mode, which bounds the largest leaf page.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Mode {
+    /// Three levels, with leaves of 4 KiB, 2 MiB and 1 GiB.
+    Sv39,
+    /// Four levels, adding 512 GiB leaves.
+    Sv48,
+}
+
+impl Mode {
+    /// Return the number of page table levels.
+    pub const fn levels(self) -> u32 {
+        match self {
+            Mode::Sv39 => 3,
+            Mode::Sv48 => 4,
+        }
+    }
+}
+
+/// Return the size of a leaf page at `level`, where level 0 holds 4 KiB pages, or `None`
+/// if it does not fit in `usize`.
+pub const fn leaf_size(level: u32) -> Option<usize> {
+    match level.checked_mul(9) {
+        Some(shift) => PAGE_SIZE.checked_shl(shift),
+        None => None,
+    }
+}
+
+/// A [`RegionAllocator`] handing out naturally aligned frames for the leaves of one
+/// paging mode.
+///
+/// Frames are placed as [`RegionAllocator::allocate_by_size`] places them, skipping the
+/// parts of regions too small or misaligned for a whole frame.
+#[derive(Clone, Debug)]
+pub struct SvFrameAllocator<S: RegionStorage> {
+    regions: RegionAllocator<S>,
+    mode: Mode,
+}
+
+impl<S: RegionStorage> SvFrameAllocator<S> {
+    /// Wrap `regions` holding the free physical memory.
+    pub fn new(regions: RegionAllocator<S>, mode: Mode) -> Self {
+        SvFrameAllocator { regions, mode }
+    }
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+    /// Return the free physical memory.
+    pub fn regions(&self) -> &RegionAllocator<S> {
+        &self.regions
+    }
+    pub fn into_inner(self) -> RegionAllocator<S> {
+        self.regions
+    }
+    /// Return the leaf size at `level`, or [`RegionError::InvalidAlignment`] if the mode
+    /// has no leaves there.
+    fn level_size(&self, level: u32) -> Result<usize, RegionError> {
+        match leaf_size(level) {
+            Some(size) if level < self.mode.levels() => Ok(size),
+            _ => Err(RegionError::InvalidAlignment),
+        }
+    }
+    /// Allocate a frame for a leaf at `level`, sized and aligned to it, and return its
+    /// base.
+    pub fn allocate_level(&mut self, level: u32) -> Result<usize, RegionError> {
+        let size = self.level_size(level)?;
+        self.regions
+            .allocate_by_size(size, size)
+            .map(|(base, _)| base)
+    }
+    /// Allocate a 4 KiB frame, for a page or a page table.
+    pub fn allocate_page(&mut self) -> Result<usize, RegionError> {
+        self.allocate_level(0)
+    }
+    /// Allocate a 2 MiB megapage.
+    pub fn allocate_megapage(&mut self) -> Result<usize, RegionError> {
+        self.allocate_level(1)
+    }
+    /// Allocate a 1 GiB gigapage.
+    pub fn allocate_gigapage(&mut self) -> Result<usize, RegionError> {
+        self.allocate_level(2)
+    }
+    /// Give back a frame allocated for a leaf at `level`.
+    ///
+    /// Fail with [`RegionError::Overlapping`] if any of it is already free.
+    pub fn deallocate_level(&mut self, base: usize, level: u32) -> Result<(), RegionError> {
+        let size = self.level_size(level)?;
+        self.regions.add_checked(base, size)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::{leaf_size, ppn, Mode, SvFrameAllocator};
+    use crate::{RegionAllocator, RegionError};
+
+    #[test]
+    fn riscv_test() {
+        let mut ram = RegionAllocator::new();
+        ram.add(0x8000_0000, 0x40_0000);
+        let mut frames = SvFrameAllocator::new(ram, Mode::Sv39);
+        let page = frames.allocate_page().unwrap();
+        assert_eq!(ppn(page), 0x80000);
+        // The megapage skips the partly used first 2 MiB
+        assert_eq!(frames.allocate_megapage(), Ok(0x8020_0000));
+        assert_eq!(frames.allocate_gigapage(), Err(RegionError::NoFit));
+        assert_eq!(frames.allocate_level(3), Err(RegionError::InvalidAlignment));
+        assert_eq!(frames.deallocate_level(0x8020_0000, 1), Ok(()));
+        assert_eq!(
+            frames.deallocate_level(page + 0x1000, 0),
+            Err(RegionError::Overlapping)
+        );
+        assert!(frames.regions().check_region(0x8000_1000, 0x3f_f000));
+        assert_eq!(leaf_size(2), Some(0x4000_0000));
+    }
+}
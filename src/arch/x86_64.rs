@@ -0,0 +1,120 @@
+//! The frame allocator traits and address types of the `x86_64` crate.
+
+use crate::{Address, RegionAllocator, RegionError, RegionStorage};
+use ::x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PageSize, PhysFrame};
+use ::x86_64::{PhysAddr, VirtAddr};
+use core::convert::TryFrom;
+
+/// Hand out frames of any page size, aligned to it, as
+/// [`RegionAllocator::allocate_by_size`] places them.
+///
+/// Parts of regions too small or misaligned to hold a whole frame are skipped rather
+/// than handed out, so a set holding byte-granular ranges only loses the slivers. A
+/// region above the 52-bit physical address space is put back and not handed out.
+///
+/// This is sound as long as the set only holds memory nothing else uses.
+unsafe impl<S: RegionStorage, P: PageSize> FrameAllocator<P> for RegionAllocator<S> {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<P>> {
+        let size = usize::try_from(P::SIZE).ok()?;
+        let frame = self.allocate_with(size, size, |base, _| {
+            let start = PhysAddr::try_new(base as u64).map_err(|_| RegionError::NoFit)?;
+            Ok(PhysFrame::containing_address(start))
+        });
+        frame.ok().map(|(_, frame)| frame)
+    }
+}
+
+/// Put frames back into the set, merging them with their neighbours.
+///
+/// # Panics
+///
+/// Panics if the frame is already in the set, which means it was freed twice, or if the
+/// storage is out of capacity.
+impl<S: RegionStorage, P: PageSize> FrameDeallocator<P> for RegionAllocator<S> {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<P>) {
+        let base = frame.start_address().as_u64() as usize;
+        if let Err(e) = self.add_checked(base, P::SIZE as usize) {
+            panic!("cannot free frame at {:#x}: {}", base, e);
+        }
+    }
+}
+
+impl Address for PhysAddr {
+    fn to_usize(self) -> usize {
+        self.as_u64() as usize
+    }
+    /// # Panics
+    ///
+    /// Panics if `addr` has bits set above bit 51.
+    fn from_usize(addr: usize) -> Self {
+        PhysAddr::new(addr as u64)
+    }
+}
+
+impl Address for VirtAddr {
+    fn to_usize(self) -> usize {
+        self.as_u64() as usize
+    }
+    /// # Panics
+    ///
+    /// Panics if `addr` is not canonical, which a set holding only canonical ranges never
+    /// yields.
+    fn from_usize(addr: usize) -> Self {
+        VirtAddr::new(addr as u64)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use crate::{RegionAllocator, TypedRegionAllocator};
+    use x86_64::structures::paging::{
+        FrameAllocator, FrameDeallocator, PhysFrame, Size2MiB, Size4KiB,
+    };
+    use x86_64::{PhysAddr, VirtAddr};
+
+    #[test]
+    fn x86_64_test() {
+        let mut frames = RegionAllocator::new();
+        frames.add(0x1800, 0x2000);
+        frames.add(0x200000, 0x201000);
+        let frame: PhysFrame<Size4KiB> = frames.allocate_frame().unwrap();
+        assert_eq!(frame.start_address(), PhysAddr::new(0x2000));
+        // The slivers around it stay in the set, unused by frames
+        assert!(frames.check_region(0x1800, 0x800) && frames.check_region(0x3000, 0x800));
+        let huge: PhysFrame<Size2MiB> = frames.allocate_frame().unwrap();
+        assert_eq!(huge.start_address(), PhysAddr::new(0x200000));
+        let none: Option<PhysFrame<Size2MiB>> = frames.allocate_frame();
+        assert!(none.is_none());
+        unsafe {
+            frames.deallocate_frame(huge);
+            frames.deallocate_frame(frame);
+        }
+        assert!(frames.check_region(0x1800, 0x2000) && frames.check_region(0x200000, 0x201000));
+    }
+
+    #[test]
+    fn x86_64_address_test() {
+        let mut phys = TypedRegionAllocator::<PhysAddr>::new();
+        phys.add(PhysAddr::new(0x1000), 0x1000);
+        assert_eq!(
+            phys.allocate_by_size(0x1000, 0x1000),
+            Ok((PhysAddr::new(0x1000), 0x1000))
+        );
+        let mut virt = TypedRegionAllocator::<VirtAddr>::new();
+        virt.add(VirtAddr::new(0xffff_8000_0000_0000), 0x1000);
+        assert!(virt.check_point(VirtAddr::new(0xffff_8000_0000_0fff)));
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot free frame at 0x1000")]
+    fn x86_64_double_free_test() {
+        let mut frames = RegionAllocator::new();
+        frames.add(0x1000, 0x1000);
+        unsafe {
+            FrameDeallocator::<Size4KiB>::deallocate_frame(
+                &mut frames,
+                PhysFrame::containing_address(PhysAddr::new(0x1000)),
+            )
+        };
+    }
+}
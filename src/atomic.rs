@@ -0,0 +1,162 @@
+//! A lock-free frame allocator for a small fixed address window.
+
+use crate::{RegionAllocator, RegionStorage};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+const BITS: usize = usize::BITS as usize;
+
+/// A frame allocator over up to `W * usize::BITS` frames, usable concurrently without locks.
+///
+/// Like a [`BitmapAllocator`](crate::BitmapAllocator), a set bit means the frame is free,
+/// but every update is a compare-and-swap on one word, so [`AtomicFrameAllocator::allocate`]
+/// and [`AtomicFrameAllocator::deallocate`] can run from interrupt handlers while other
+/// cores do the same. Only single frames are handed out.
+#[derive(Debug)]
+pub struct AtomicFrameAllocator<const W: usize> {
+    base: usize,
+    frame_shift: u32,
+    frames: usize,
+    bits: [AtomicUsize; W],
+}
+
+impl<const W: usize> AtomicFrameAllocator<W> {
+    /// Create an [`AtomicFrameAllocator`] for `frames` frames of `frame_size` bytes starting
+    /// at `base`, with every frame allocated.
+    ///
+    /// Return `None` if `frame_size` is not a power of 2, `base` is not aligned to it,
+    /// the window overflows, or `frames` exceeds `W * usize::BITS`.
+    pub const fn new(base: usize, frame_size: usize, frames: usize) -> Option<Self> {
+        if !frame_size.is_power_of_two() || base & (frame_size - 1) != 0 || frames > W * BITS {
+            return None;
+        }
+        match frames.checked_mul(frame_size) {
+            Some(size) if base.checked_add(size).is_some() => {}
+            _ => return None,
+        }
+        Some(AtomicFrameAllocator {
+            base,
+            frame_shift: frame_size.trailing_zeros(),
+            frames,
+            bits: [const { AtomicUsize::new(0) }; W],
+        })
+    }
+    /// Return size of a frame.
+    pub fn frame_size(&self) -> usize {
+        1 << self.frame_shift
+    }
+    /// Return the window `(base, size)` covered by the allocator.
+    pub fn window(&self) -> (usize, usize) {
+        (self.base, self.frames << self.frame_shift)
+    }
+    /// Return number of free frames, which may be stale by the time it is read.
+    pub fn free_frames(&self) -> usize {
+        let words = self.bits.iter();
+        words
+            .map(|w| w.load(Ordering::Relaxed).count_ones() as usize)
+            .sum()
+    }
+    /// Check whether the frame containing `addr` is free.
+    pub fn is_free(&self, addr: usize) -> bool {
+        match self.frame_of(addr) {
+            Some(i) => self.bits[i / BITS].load(Ordering::Relaxed) & (1 << (i % BITS)) != 0,
+            None => false,
+        }
+    }
+    /// Mark every whole frame covered by `regions` inside the window as free.
+    ///
+    /// The frames stay in `regions`; subtract the window from it to hand them over.
+    pub fn populate<S: RegionStorage>(&self, regions: &RegionAllocator<S>) {
+        let (base, size) = self.window();
+        let end = base + size;
+        for r in regions.regions.range(..end) {
+            let start = r.base.max(base);
+            let stop = r.end().min(end);
+            let first = (start - base).div_ceil(self.frame_size());
+            let last = (stop.saturating_sub(base)) >> self.frame_shift;
+            for i in first..last {
+                self.bits[i / BITS].fetch_or(1 << (i % BITS), Ordering::Release);
+            }
+        }
+    }
+    /// Allocate a single frame and return its address.
+    pub fn allocate(&self) -> Option<usize> {
+        for (word, bits) in self.bits.iter().enumerate() {
+            let mut w = bits.load(Ordering::Relaxed);
+            while w != 0 {
+                let bit = w.trailing_zeros() as usize;
+                match bits.compare_exchange_weak(
+                    w,
+                    w & !(1 << bit),
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return Some(self.base + ((word * BITS + bit) << self.frame_shift)),
+                    Err(now) => w = now,
+                }
+            }
+        }
+        None
+    }
+    /// Free the frame containing `addr`.
+    /// Return false if it is outside the window or already free.
+    pub fn deallocate(&self, addr: usize) -> bool {
+        match self.frame_of(addr) {
+            Some(i) => {
+                let bit = 1 << (i % BITS);
+                self.bits[i / BITS].fetch_or(bit, Ordering::Release) & bit == 0
+            }
+            None => false,
+        }
+    }
+
+    fn frame_of(&self, addr: usize) -> Option<usize> {
+        let i = addr.checked_sub(self.base)? >> self.frame_shift;
+        Some(i).filter(|&i| i < self.frames)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    extern crate std;
+
+    use super::AtomicFrameAllocator;
+    use crate::RegionAllocator;
+    use std::vec::Vec;
+
+    static FRAMES: Option<AtomicFrameAllocator<2>> =
+        AtomicFrameAllocator::new(0x10000, 0x1000, 100);
+
+    #[test]
+    fn atomic_test() {
+        assert!(AtomicFrameAllocator::<1>::new(0, 0x1000, 65).is_none());
+        let frames = FRAMES.as_ref().unwrap();
+        let mut regions = RegionAllocator::new();
+        regions.add(0x10800, 0x100000);
+        frames.populate(&regions);
+        assert!(!frames.is_free(0x10000));
+        assert_eq!(frames.free_frames(), 99);
+        // Concurrent allocations never hand out a frame twice
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                std::thread::spawn(move || {
+                    (0..30)
+                        .filter_map(|_| frames.allocate())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut taken: Vec<_> = threads
+            .into_iter()
+            .flat_map(|t| t.join().unwrap())
+            .collect();
+        taken.sort_unstable();
+        taken.dedup();
+        assert_eq!(taken.len(), 99);
+        assert_eq!(frames.allocate(), None);
+        // Double and out-of-window frees are rejected
+        assert!(frames.deallocate(0x11000));
+        assert!(!frames.deallocate(0x11000));
+        assert!(!frames.deallocate(0x80000));
+        assert_eq!(frames.allocate(), Some(0x11000));
+    }
+}
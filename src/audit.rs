@@ -0,0 +1,182 @@
+//! A log of the latest changes to a region set, for crash dumps.
+
+use crate::{Op, Region, RegionAllocator, RegionError, RegionStorage};
+
+/// A source of timestamps for an [`AuditedRegionAllocator`], in whatever unit the caller
+/// counts time, such as TSC ticks or nanoseconds since boot.
+pub trait Clock {
+    /// Return the current time.
+    fn now(&self) -> u64;
+}
+
+impl<F: Fn() -> u64> Clock for F {
+    fn now(&self) -> u64 {
+        self()
+    }
+}
+
+/// A change recorded by an [`AuditedRegionAllocator`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AuditEntry {
+    /// When the change was made.
+    pub time: u64,
+    /// The change. A successful [`Op::AllocateSize`] is recorded as the
+    /// [`Op::AllocateAddr`] of the region it took, and a deallocation as an [`Op::Add`].
+    pub op: Op,
+    /// Whether the change was made, or why it was refused and the set left unchanged.
+    pub result: Result<(), RegionError>,
+}
+
+/// A [`RegionAllocator`] that records its latest `N` changes with the time of each, so
+/// the history leading to a wrong free map can be read from a crash dump.
+///
+/// Only changes made through the wrapper are recorded, which is why the set is not
+/// handed out mutably.
+#[derive(Clone, Debug)]
+pub struct AuditedRegionAllocator<
+    C: Clock,
+    const N: usize,
+    #[cfg(feature = "alloc")] S: RegionStorage = crate::BTreeStorage,
+    #[cfg(not(feature = "alloc"))] S: RegionStorage,
+> {
+    regions: RegionAllocator<S>,
+    clock: C,
+    log: [Option<AuditEntry>; N],
+    head: usize,
+}
+
+impl<C: Clock, const N: usize, S: RegionStorage> AuditedRegionAllocator<C, N, S> {
+    /// Wrap a [`RegionAllocator`], with nothing recorded yet.
+    pub fn new(regions: RegionAllocator<S>, clock: C) -> Self {
+        AuditedRegionAllocator {
+            regions,
+            clock,
+            log: [None; N],
+            head: 0,
+        }
+    }
+    /// Return the set.
+    pub fn regions(&self) -> &RegionAllocator<S> {
+        &self.regions
+    }
+    /// Unwrap the set, dropping the log.
+    pub fn into_inner(self) -> RegionAllocator<S> {
+        self.regions
+    }
+    /// Iterate over the recorded changes, oldest first.
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &AuditEntry> + '_ {
+        let (newer, older) = self.log.split_at(self.head);
+        older.iter().chain(newer).flatten()
+    }
+    /// Forget every recorded change.
+    pub fn clear_log(&mut self) {
+        self.log = [None; N];
+        self.head = 0;
+    }
+    /// See [`RegionAllocator::try_add`].
+    pub fn add(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        let result = self.regions.try_add(base, size);
+        self.record(Op::Add(Region { base, size }), result)
+    }
+    /// See [`RegionAllocator::try_subtract`].
+    pub fn subtract(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        let result = self.regions.try_subtract(base, size);
+        self.record(Op::Subtract(Region { base, size }), result)
+    }
+    /// See [`RegionAllocator::allocate_by_addr`].
+    pub fn allocate_by_addr(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        let result = self.regions.allocate_by_addr(base, size);
+        self.record(Op::AllocateAddr(Region { base, size }), result)
+    }
+    /// See [`RegionAllocator::allocate_by_size`].
+    pub fn allocate_by_size(
+        &mut self,
+        size: usize,
+        alignment: usize,
+    ) -> Result<(usize, usize), RegionError> {
+        let found = self.regions.allocate_by_size(size, alignment);
+        let op = match found {
+            Ok((base, size)) => Op::AllocateAddr(Region { base, size }),
+            Err(_) => Op::AllocateSize { size, alignment },
+        };
+        self.record(op, found.map(drop))?;
+        found
+    }
+    /// See [`RegionAllocator::deallocate`].
+    pub fn deallocate(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        let result = self.regions.deallocate(base, size);
+        self.record(Op::Add(Region { base, size }), result)
+    }
+
+    /// Record a change, overwriting the oldest one once `N` are recorded.
+    fn record(&mut self, op: Op, result: Result<(), RegionError>) -> Result<(), RegionError> {
+        if N > 0 {
+            self.log[self.head] = Some(AuditEntry {
+                time: self.clock.now(),
+                op,
+                result,
+            });
+            self.head = (self.head + 1) % N;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuditEntry, AuditedRegionAllocator};
+    use crate::{ArrayStorage, Op, Region, RegionAllocator, RegionError};
+    use core::cell::Cell;
+
+    #[test]
+    fn audit_test() {
+        let ticks = Cell::new(0);
+        let clock = || {
+            ticks.set(ticks.get() + 10);
+            ticks.get()
+        };
+        let regions = RegionAllocator::with_storage(ArrayStorage::<4>::new());
+        let mut audited = AuditedRegionAllocator::<_, 3, _>::new(regions, clock);
+        let r = |base, size| Region { base, size };
+        audited.add(0x1000, 0x4000).unwrap();
+        assert_eq!(
+            audited.allocate_by_size(0x1000, 0x1000),
+            Ok((0x1000, 0x1000))
+        );
+        assert_eq!(audited.allocate_by_size(0x8000, 1), Err(RegionError::NoFit));
+        // Case 1: changes are recorded oldest first, allocations with the region taken
+        let entries: [AuditEntry; 3] = [
+            AuditEntry {
+                time: 10,
+                op: Op::Add(r(0x1000, 0x4000)),
+                result: Ok(()),
+            },
+            AuditEntry {
+                time: 20,
+                op: Op::AllocateAddr(r(0x1000, 0x1000)),
+                result: Ok(()),
+            },
+            AuditEntry {
+                time: 30,
+                op: Op::AllocateSize {
+                    size: 0x8000,
+                    alignment: 1,
+                },
+                result: Err(RegionError::NoFit),
+            },
+        ];
+        assert!(audited.entries().eq(&entries));
+        // Case 2: the oldest changes are overwritten
+        assert_eq!(
+            audited.deallocate(0x2000, 0x1000),
+            Err(RegionError::Overlapping)
+        );
+        assert_eq!(audited.subtract(0x4000, 0x1000), Ok(()));
+        let last = audited.entries().next_back().unwrap();
+        assert_eq!((last.time, last.op), (50, Op::Subtract(r(0x4000, 0x1000))));
+        assert_eq!(audited.entries().next().unwrap().time, 30);
+        audited.clear_log();
+        assert_eq!(audited.entries().count(), 0);
+        assert_eq!(audited.regions().len(), 1);
+    }
+}
@@ -0,0 +1,204 @@
+//! A bitmap frame allocator for a fixed address window.
+
+use crate::{RegionAllocator, RegionError, RegionStorage};
+
+const BITS: usize = u64::BITS as usize;
+
+/// A frame allocator tracking a fixed window with one bit per frame.
+///
+/// A set bit means the frame is free. The bitmap lives in any `B` viewable as
+/// `[u64]`, such as a `Vec<u64>` or a static array, and needs
+/// `frames.div_ceil(64)` words. For densely populated memory this is far smaller
+/// than one tree node per fragment.
+#[derive(Clone, Debug)]
+pub struct BitmapAllocator<B> {
+    base: usize,
+    frame_shift: u32,
+    frames: usize,
+    bits: B,
+}
+
+impl<B: AsRef<[u64]> + AsMut<[u64]>> BitmapAllocator<B> {
+    /// Create a [`BitmapAllocator`] for `frames` frames of `frame_size` bytes starting
+    /// at `base`, with every frame allocated.
+    ///
+    /// Return `None` if `frame_size` is not a power of 2, `base` is not aligned to it,
+    /// the window overflows, or `bits` is too short.
+    pub fn new(base: usize, frame_size: usize, frames: usize, mut bits: B) -> Option<Self> {
+        if !frame_size.is_power_of_two() || base & (frame_size - 1) != 0 {
+            return None;
+        }
+        frames.checked_mul(frame_size)?.checked_add(base)?;
+        if bits.as_ref().len() < frames.div_ceil(BITS) {
+            return None;
+        }
+        bits.as_mut().iter_mut().for_each(|w| *w = 0);
+        Some(BitmapAllocator {
+            base,
+            frame_shift: frame_size.trailing_zeros(),
+            frames,
+            bits,
+        })
+    }
+    /// Return size of a frame.
+    pub fn frame_size(&self) -> usize {
+        1 << self.frame_shift
+    }
+    /// Return the window `(base, size)` covered by the bitmap.
+    pub fn window(&self) -> (usize, usize) {
+        (self.base, self.frames << self.frame_shift)
+    }
+    /// Return number of free frames.
+    pub fn free_frames(&self) -> usize {
+        let words = self.bits.as_ref().iter();
+        words.map(|w| w.count_ones() as usize).sum()
+    }
+    /// Check whether the frame containing `addr` is free.
+    pub fn is_free(&self, addr: usize) -> bool {
+        match self.frame_of(addr) {
+            Some(i) => self.bit(i),
+            None => false,
+        }
+    }
+    /// Mark every whole frame covered by `regions` inside the window as free.
+    pub fn populate<S: RegionStorage>(&mut self, regions: &RegionAllocator<S>) {
+        let (base, size) = self.window();
+        let end = base + size;
+        for r in regions.regions.range(..end) {
+            let start = r.base.max(base);
+            let stop = r.end().min(end);
+            let first = (start - base).div_ceil(self.frame_size());
+            let last = (stop.saturating_sub(base)) >> self.frame_shift;
+            if first < last {
+                self.set_range(first, last, true);
+            }
+        }
+    }
+    /// Make the window of `regions` match the bitmap: free frames are added and
+    /// everything else in the window, including fragments smaller than a frame,
+    /// is subtracted.
+    pub fn write_back<S: RegionStorage>(
+        &self,
+        regions: &mut RegionAllocator<S>,
+    ) -> Result<(), RegionError> {
+        let (base, size) = self.window();
+        regions.try_subtract(base, size)?;
+        let mut i = 0;
+        while i < self.frames {
+            if !self.bit(i) {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < self.frames && self.bit(i) {
+                i += 1;
+            }
+            regions.try_add(self.addr_of(start), (i - start) << self.frame_shift)?;
+        }
+        Ok(())
+    }
+    /// Allocate a single frame and return its address.
+    pub fn allocate(&mut self) -> Option<usize> {
+        let bits = self.bits.as_mut();
+        let (word, w) = bits.iter_mut().enumerate().find(|(_, w)| **w != 0)?;
+        let bit = w.trailing_zeros() as usize;
+        *w &= !(1 << bit);
+        Some(self.addr_of(word * BITS + bit))
+    }
+    /// Allocate `count` contiguous frames whose first frame number, its address over the
+    /// frame size, is a multiple of `align_frames`, a power of 2. Return the address of
+    /// the first frame.
+    pub fn allocate_contiguous(&mut self, count: usize, align_frames: usize) -> Option<usize> {
+        if count == 0 || count > self.frames || !align_frames.is_power_of_two() {
+            return None;
+        }
+        let off = self.base >> self.frame_shift;
+        let mut first = off.wrapping_neg() & (align_frames - 1);
+        while first <= self.frames - count {
+            match (first..first + count).rev().find(|&i| !self.bit(i)) {
+                None => {
+                    self.set_range(first, first + count, false);
+                    return Some(self.addr_of(first));
+                }
+                Some(used) => {
+                    first = ((off + used).checked_add(align_frames)? & !(align_frames - 1)) - off
+                }
+            }
+        }
+        None
+    }
+    /// Free `count` frames starting at the frame containing `addr`.
+    /// Frames outside the window are ignored.
+    pub fn deallocate(&mut self, addr: usize, count: usize) {
+        if let Some(first) = self.frame_of(addr) {
+            let last = first.saturating_add(count).min(self.frames);
+            self.set_range(first, last, true);
+        }
+    }
+
+    fn frame_of(&self, addr: usize) -> Option<usize> {
+        let i = addr.checked_sub(self.base)? >> self.frame_shift;
+        Some(i).filter(|&i| i < self.frames)
+    }
+    fn addr_of(&self, frame: usize) -> usize {
+        self.base + (frame << self.frame_shift)
+    }
+    fn bit(&self, i: usize) -> bool {
+        self.bits.as_ref()[i / BITS] & (1 << (i % BITS)) != 0
+    }
+    fn set_range(&mut self, first: usize, last: usize, free: bool) {
+        let bits = self.bits.as_mut();
+        for i in first..last {
+            if free {
+                bits[i / BITS] |= 1 << (i % BITS);
+            } else {
+                bits[i / BITS] &= !(1 << (i % BITS));
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::BitmapAllocator;
+    use crate::RegionAllocator;
+    use alloc::vec;
+
+    #[test]
+    fn bitmap_test() {
+        let mut regions = RegionAllocator::new();
+        regions.add(0x800, 0x2000);
+        regions.add(0x10000, 0x100000);
+        let mut bitmap = BitmapAllocator::new(0, 0x1000, 100, vec![0; 2]).unwrap();
+        bitmap.populate(&regions);
+        // Partial frames at both ends of [0x800, 0x2800) are not free
+        assert!(!bitmap.is_free(0x0));
+        assert!(bitmap.is_free(0x1000));
+        assert!(!bitmap.is_free(0x2000));
+        assert_eq!(bitmap.free_frames(), 1 + 100 - 0x10);
+        // Case 1: single frames
+        assert_eq!(bitmap.allocate(), Some(0x1000));
+        assert_eq!(bitmap.allocate(), Some(0x10000));
+        // Case 2: contiguous aligned frames
+        assert_eq!(bitmap.allocate_contiguous(4, 4), Some(0x14000));
+        assert_eq!(bitmap.allocate_contiguous(100, 1), None);
+        // Case 3: syncing back replaces the window only
+        bitmap.deallocate(0x10000, 1);
+        bitmap.write_back(&mut regions).unwrap();
+        assert!(regions.check_region(0x10000, 0x4000));
+        assert!(regions.check_region(0x18000, 0x100000 - 0x8000));
+        assert_eq!(regions.len(), 2);
+        // Case 4: alignment is by address when the window is not aligned
+        let mut bitmap = BitmapAllocator::new(0x1000, 0x1000, 16, vec![0; 1]).unwrap();
+        bitmap.deallocate(0x1000, 16);
+        for _ in 0..5 {
+            bitmap.allocate();
+        }
+        assert_eq!(bitmap.allocate_contiguous(4, 4), Some(0x8000));
+        assert_eq!(bitmap.allocate_contiguous(4, 4), Some(0xc000));
+        // Case 5: runs longer than the window, or aligned past it, do not fit
+        assert_eq!(bitmap.allocate_contiguous(usize::MAX, 1), None);
+        assert_eq!(bitmap.allocate_contiguous(17, 1), None);
+        assert_eq!(bitmap.allocate_contiguous(1, 1 << (usize::BITS - 1)), None);
+    }
+}
@@ -0,0 +1,157 @@
+//! A swiotlb-style pool of bounce buffers for devices that cannot reach all memory.
+
+use crate::{IdAllocator, RegionError};
+use alloc::collections::BTreeMap;
+
+/// The size of a slot, the unit bounce buffers are carved in.
+pub const SLOT_SIZE: usize = 0x800;
+
+/// Pressure on a [`BouncePool`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BounceStats {
+    /// Number of slots in the pool.
+    pub slots: usize,
+    /// Number of slots mapped.
+    pub used: usize,
+    /// Highest number of slots mapped at once.
+    pub peak: usize,
+    /// Number of mappings refused for lack of room.
+    pub failures: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Mapping {
+    original: usize,
+    size: usize,
+}
+
+/// A pool of bounce buffers carved in slots from a region a device can reach, typically
+/// below 4 GiB.
+///
+/// Each mapping takes a run of free slots and remembers the buffer it stands for, so the
+/// DMA layer knows where to copy to or from when it syncs or unmaps it. Slots are
+/// numbered by address, slot `n` starting at `n * SLOT_SIZE`, so aligning a slot
+/// number aligns the buffer.
+#[derive(Clone, Debug)]
+pub struct BouncePool {
+    slots: IdAllocator,
+    mappings: BTreeMap<usize, Mapping>,
+    stats: BounceStats,
+}
+
+impl BouncePool {
+    /// Carve slots from `size` bytes at `base`, which must be aligned to [`SLOT_SIZE`].
+    ///
+    /// Bytes past the last whole slot are left out.
+    pub fn new(base: usize, size: usize) -> Result<Self, RegionError> {
+        if !base.is_multiple_of(SLOT_SIZE) {
+            return Err(RegionError::Unaligned);
+        }
+        base.checked_add(size).ok_or(RegionError::Overflow)?;
+        let (first, count) = (base / SLOT_SIZE, size / SLOT_SIZE);
+        let mut slots = IdAllocator::new();
+        if count > 0 {
+            slots.add_pool(first..first + count)?;
+        }
+        Ok(BouncePool {
+            slots,
+            mappings: BTreeMap::new(),
+            stats: BounceStats {
+                slots: count,
+                ..BounceStats::default()
+            },
+        })
+    }
+    /// Return the pressure on the pool.
+    pub fn stats(&self) -> BounceStats {
+        self.stats
+    }
+    /// Map `size` bytes at `original` to a bounce buffer aligned to `alignment`, or to a
+    /// slot if that is larger, returning the address of the buffer.
+    pub fn map(
+        &mut self,
+        original: usize,
+        size: usize,
+        alignment: usize,
+    ) -> Result<usize, RegionError> {
+        if !alignment.is_power_of_two() {
+            return Err(RegionError::InvalidAlignment);
+        }
+        if size == 0 {
+            return Err(RegionError::EmptyRange);
+        }
+        let count = size.div_ceil(SLOT_SIZE);
+        let slot = match self
+            .slots
+            .allocate_range(count, (alignment / SLOT_SIZE).max(1))
+        {
+            Ok(slot) => slot,
+            Err(e) => {
+                self.stats.failures += 1;
+                return Err(e);
+            }
+        };
+        let addr = slot * SLOT_SIZE;
+        self.mappings.insert(addr, Mapping { original, size });
+        self.stats.used += count;
+        self.stats.peak = self.stats.peak.max(self.stats.used);
+        Ok(addr)
+    }
+    /// Return the original address `addr` inside a bounce buffer stands for.
+    pub fn original(&self, addr: usize) -> Option<usize> {
+        let (&start, mapping) = self.mappings.range(..=addr).next_back()?;
+        (addr - start < mapping.size).then(|| mapping.original + (addr - start))
+    }
+    /// Unmap the bounce buffer at `addr`, returning the original address and size, which
+    /// the caller copies back to before the slots are reused.
+    ///
+    /// Fail with [`RegionError::NotCovered`] if `addr` is not the start of a mapping.
+    pub fn unmap(&mut self, addr: usize) -> Result<(usize, usize), RegionError> {
+        let mapping = self.mappings.remove(&addr).ok_or(RegionError::NotCovered)?;
+        let count = mapping.size.div_ceil(SLOT_SIZE);
+        self.slots.free_range(addr / SLOT_SIZE, count)?;
+        self.stats.used -= count;
+        Ok((mapping.original, mapping.size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BouncePool, BounceStats, SLOT_SIZE};
+    use crate::RegionError;
+
+    #[test]
+    fn bounce_test() {
+        assert_eq!(
+            BouncePool::new(0x100, 0x1000).unwrap_err(),
+            RegionError::Unaligned
+        );
+        let mut pool = BouncePool::new(0x1000_0000, 8 * SLOT_SIZE + 0x10).unwrap();
+        // Case 1: buffers take runs of aligned slots and remember their original
+        assert_eq!(pool.map(0x1_2345_6000, 0x100, 1), Ok(0x1000_0000));
+        assert_eq!(pool.map(0x1_0000_0000, 0x1801, 0x2000), Ok(0x1000_2000));
+        assert_eq!(pool.original(0x1000_2010), Some(0x1_0000_0010));
+        assert_eq!(pool.original(0x1000_0100), None);
+        // Case 2: pressure is counted
+        assert_eq!(pool.map(0, 0x3000, 1), Err(RegionError::NoFit));
+        let stats = BounceStats {
+            slots: 8,
+            used: 5,
+            peak: 5,
+            failures: 1,
+        };
+        assert_eq!(pool.stats(), stats);
+        // Case 3: unmapping gives the slots back
+        assert_eq!(pool.unmap(0x1000_2000), Ok((0x1_0000_0000, 0x1801)));
+        assert_eq!(pool.unmap(0x1000_2000), Err(RegionError::NotCovered));
+        assert_eq!(pool.map(0, 0x3000, 1), Ok(0x1000_0800));
+        assert_eq!(pool.stats().used, 7);
+        assert_eq!(pool.stats().peak, 7);
+        // Case 4: buffers are aligned by address, whatever the alignment of the pool
+        let mut pool = BouncePool::new(SLOT_SIZE, 8 * SLOT_SIZE).unwrap();
+        assert_eq!(pool.map(0, 0x100, 0x2000), Ok(0x2000));
+        assert_eq!(pool.map(0, 0x100, 1), Ok(SLOT_SIZE));
+        assert_eq!(pool.map(0, 0x100, 0x2000), Ok(0x4000));
+        assert_eq!(pool.unmap(0x2000), Ok((0, 0x100)));
+    }
+}
@@ -0,0 +1,179 @@
+//! A buddy allocator fed from a [`RegionAllocator`].
+
+use crate::{RegionAllocator, RegionStorage};
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+
+/// A binary buddy allocator handing out naturally aligned power-of-two blocks.
+///
+/// Blocks range from `min_block` to `max_block` bytes and are kept in one free list
+/// per order. Freed blocks are merged with their buddy whenever it is free as well.
+#[derive(Clone, Debug)]
+pub struct BuddyAllocator {
+    min_shift: u32,
+    free: Vec<BTreeSet<usize>>,
+}
+
+impl BuddyAllocator {
+    /// Create an empty [`BuddyAllocator`].
+    /// Both `min_block` and `max_block` must be powers of 2 and `min_block <= max_block`.
+    pub fn new(min_block: usize, max_block: usize) -> Option<Self> {
+        if !min_block.is_power_of_two() || !max_block.is_power_of_two() || min_block > max_block {
+            return None;
+        }
+        let orders = (max_block.trailing_zeros() - min_block.trailing_zeros()) as usize + 1;
+        Some(BuddyAllocator {
+            min_shift: min_block.trailing_zeros(),
+            free: (0..orders).map(|_| BTreeSet::new()).collect(),
+        })
+    }
+    /// Create a [`BuddyAllocator`] owning every block that can be carved out of `regions`.
+    ///
+    /// Parts of regions not aligned to `min_block` stay in `regions`. The blocks are
+    /// counted as allocated from `regions` until [`BuddyAllocator::release`] gives them back.
+    pub fn from_regions<S: RegionStorage>(
+        regions: &mut RegionAllocator<S>,
+        min_block: usize,
+        max_block: usize,
+    ) -> Option<Self> {
+        let mut buddy = BuddyAllocator::new(min_block, max_block)?;
+        let spans: Vec<_> = regions
+            .regions
+            .range(..)
+            .filter_map(|r| {
+                let base = r.base.checked_add(min_block - 1)? & !(min_block - 1);
+                let end = r.end() & !(min_block - 1);
+                Some((base, end)).filter(|&(base, end)| base < end)
+            })
+            .collect();
+        for (base, end) in spans {
+            if regions.allocate_by_addr(base, end - base).is_ok() {
+                buddy.add_span(base, end);
+            }
+        }
+        Some(buddy)
+    }
+    /// Return size of the smallest block.
+    pub fn min_block(&self) -> usize {
+        1 << self.min_shift
+    }
+    /// Return size of the largest block.
+    pub fn max_block(&self) -> usize {
+        self.block_size(self.free.len() - 1)
+    }
+    /// Return number of free bytes.
+    pub fn free_bytes(&self) -> usize {
+        let lists = self.free.iter().enumerate();
+        lists.map(|(o, l)| l.len() * self.block_size(o)).sum()
+    }
+    /// Allocate a block of at least `size` bytes, aligned to its own size.
+    /// Return the base and size of the block.
+    pub fn allocate(&mut self, size: usize) -> Option<(usize, usize)> {
+        let order = self.order_of(size)?;
+        let from = (order..self.free.len()).find(|&o| !self.free[o].is_empty())?;
+        let base = self.free[from].pop_first()?;
+        for o in (order..from).rev() {
+            let buddy = base + self.block_size(o);
+            self.free[o].insert(buddy);
+        }
+        Some((base, self.block_size(order)))
+    }
+    /// Free a block returned by [`BuddyAllocator::allocate`], or insert any naturally
+    /// aligned block of a supported size.
+    pub fn deallocate(&mut self, base: usize, size: usize) {
+        let order = match self.order_of(size) {
+            Some(order) => order,
+            None => return,
+        };
+        self.insert_block(base, order);
+    }
+    /// Move all free blocks back into `regions`, where adjacent blocks merge.
+    pub fn release<S: RegionStorage>(&mut self, regions: &mut RegionAllocator<S>) {
+        for order in 0..self.free.len() {
+            let size = self.block_size(order);
+            while let Some(base) = self.free[order].first().copied() {
+                if regions.deallocate(base, size).is_err() {
+                    return;
+                }
+                self.free[order].remove(&base);
+            }
+        }
+    }
+
+    fn block_size(&self, order: usize) -> usize {
+        1 << (self.min_shift as usize + order)
+    }
+    fn order_of(&self, size: usize) -> Option<usize> {
+        let shift = size.max(1).checked_next_power_of_two()?.trailing_zeros();
+        let order = shift.saturating_sub(self.min_shift) as usize;
+        Some(order).filter(|&o| o < self.free.len())
+    }
+    /// Split `[base, end)`, aligned to the smallest block, into maximal blocks.
+    fn add_span(&mut self, mut base: usize, end: usize) {
+        while base < end {
+            let mut order = self.free.len() - 1;
+            while base & (self.block_size(order) - 1) != 0
+                || base
+                    .checked_add(self.block_size(order))
+                    .is_none_or(|e| e > end)
+            {
+                order -= 1;
+            }
+            self.insert_block(base, order);
+            base += self.block_size(order);
+        }
+    }
+    fn insert_block(&mut self, mut base: usize, mut order: usize) {
+        while order + 1 < self.free.len() {
+            let buddy = base ^ self.block_size(order);
+            if !self.free[order].remove(&buddy) {
+                break;
+            }
+            base &= !self.block_size(order);
+            order += 1;
+        }
+        self.free[order].insert(base);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BuddyAllocator;
+    use crate::RegionAllocator;
+
+    #[test]
+    fn buddy_test() {
+        let mut regions = RegionAllocator::new();
+        regions.add(0x1000, 0x7000);
+        regions.add(0x10800, 0x1000);
+        let mut buddy = BuddyAllocator::from_regions(&mut regions, 0x1000, 0x4000).unwrap();
+        assert_eq!(buddy.free_bytes(), 0x7000);
+        // Unaligned fragments are left in the region set
+        assert!(regions.check_region(0x10800, 0x1000));
+        assert_eq!(regions.len(), 1);
+        // Case 1: exact order
+        assert_eq!(buddy.allocate(0x4000), Some((0x4000, 0x4000)));
+        assert_eq!(buddy.allocate(0x1000), Some((0x1000, 0x1000)));
+        // Case 2: splitting a larger block
+        assert_eq!(buddy.allocate(0x800), Some((0x2000, 0x1000)));
+        assert_eq!(buddy.allocate(0x2000), None);
+        // Case 3: coalescing frees
+        buddy.deallocate(0x2000, 0x1000);
+        assert_eq!(buddy.allocate(0x2000), Some((0x2000, 0x2000)));
+        buddy.deallocate(0x2000, 0x2000);
+        buddy.deallocate(0x4000, 0x4000);
+        assert_eq!(buddy.allocate(0x5000), None);
+        // Case 4: releasing into the region set merges blocks again
+        buddy.release(&mut regions);
+        assert_eq!(buddy.free_bytes(), 0);
+        assert!(regions.check_region(0x2000, 0x6000));
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions.stats().allocated_bytes, 0x1000);
+        // Case 5: spans up to the end of the address space
+        let mut regions = RegionAllocator::new();
+        regions.add(usize::MAX - 0x3fff, 0x3fff);
+        let mut buddy = BuddyAllocator::from_regions(&mut regions, 0x1000, 0x4000).unwrap();
+        assert_eq!(buddy.free_bytes(), 0x3000);
+        assert_eq!(buddy.allocate(0x2000), Some((usize::MAX - 0x3fff, 0x2000)));
+    }
+}
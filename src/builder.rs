@@ -0,0 +1,175 @@
+//! Step-by-step configuration of a [`RegionAllocator`].
+
+#[cfg(feature = "alloc")]
+use crate::BTreeStorage;
+use crate::{Endpoints, FitPolicy, RegionAllocator, RegionStorage, ZeroSize};
+
+/// A builder collecting the settings of a [`RegionAllocator`] before creating it.
+///
+/// Every setting starts at its default, so only the ones that differ need to be given.
+/// New settings are added here rather than as more constructors. There is no merge
+/// setting: overlapping and adjacent ranges always merge, as every query relies on the
+/// stored regions not touching.
+#[derive(Clone, Debug)]
+pub struct RegionAllocatorBuilder<
+    #[cfg(feature = "alloc")] S = BTreeStorage,
+    #[cfg(not(feature = "alloc"))] S,
+> {
+    storage: S,
+    endpoints: Endpoints,
+    zero_size: ZeroSize,
+    granule: usize,
+    preserve: usize,
+    fit_policy: FitPolicy,
+    region_limit: Option<usize>,
+}
+
+#[cfg(feature = "alloc")]
+impl RegionAllocatorBuilder {
+    /// Start configuring an allocator backed by a `BTreeStorage`.
+    pub const fn new() -> Self {
+        RegionAllocatorBuilder::with_storage(BTreeStorage::new())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Default for RegionAllocatorBuilder {
+    fn default() -> Self {
+        RegionAllocatorBuilder::new()
+    }
+}
+
+impl<S: RegionStorage> RegionAllocatorBuilder<S> {
+    /// Start configuring an allocator on top of a given storage, which is expected to be
+    /// empty, or to hold disjoint and non-adjacent regions.
+    pub const fn with_storage(storage: S) -> Self {
+        RegionAllocatorBuilder {
+            storage,
+            endpoints: Endpoints::HalfOpen,
+            zero_size: ZeroSize::Ignore,
+            granule: 1,
+            preserve: 0,
+            fit_policy: FitPolicy::Next,
+            region_limit: None,
+        }
+    }
+    /// Replace the storage, keeping the other settings.
+    pub fn storage<T: RegionStorage>(self, storage: T) -> RegionAllocatorBuilder<T> {
+        RegionAllocatorBuilder {
+            storage,
+            endpoints: self.endpoints,
+            zero_size: self.zero_size,
+            granule: self.granule,
+            preserve: self.preserve,
+            fit_policy: self.fit_policy,
+            region_limit: self.region_limit,
+        }
+    }
+    /// See [`RegionAllocator::with_endpoints`].
+    pub const fn endpoints(mut self, endpoints: Endpoints) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+    /// See [`RegionAllocator::with_zero_size`].
+    pub const fn zero_size(mut self, zero_size: ZeroSize) -> Self {
+        self.zero_size = zero_size;
+        self
+    }
+    /// See [`RegionAllocator::with_granule`].
+    pub const fn granule(mut self, granule: usize) -> Self {
+        self.granule = granule;
+        self
+    }
+    /// See [`RegionAllocator::with_preserved_spans`].
+    pub const fn preserved_spans(mut self, spans: usize) -> Self {
+        self.preserve = spans;
+        self
+    }
+    /// See [`RegionAllocator::with_fit_policy`].
+    pub const fn fit_policy(mut self, policy: FitPolicy) -> Self {
+        self.fit_policy = policy;
+        self
+    }
+    /// See [`RegionAllocator::with_region_limit`].
+    pub const fn region_limit(mut self, limit: Option<usize>) -> Self {
+        self.region_limit = limit;
+        self
+    }
+    /// Create the [`RegionAllocator`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the granule is not a power of 2.
+    pub fn build(self) -> RegionAllocator<S> {
+        RegionAllocator::with_storage(self.storage)
+            .with_endpoints(self.endpoints)
+            .with_zero_size(self.zero_size)
+            .with_granule(self.granule)
+            .with_preserved_spans(self.preserve)
+            .with_fit_policy(self.fit_policy)
+            .with_region_limit(self.region_limit)
+    }
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Turn the allocator back into a builder holding its storage and settings.
+    pub fn into_builder(self) -> RegionAllocatorBuilder<S> {
+        RegionAllocatorBuilder {
+            granule: self.granule(),
+            preserve: self.preserve,
+            fit_policy: self.fit_policy,
+            region_limit: self.region_limit,
+            storage: self.regions,
+            endpoints: self.endpoints,
+            zero_size: self.zero_size,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::RegionAllocatorBuilder;
+    use crate::{Endpoints, FitPolicy, RegionError, VecStorage, ZeroSize};
+
+    #[test]
+    fn builder_test() {
+        // Case 1: unset settings keep their defaults
+        let mut regions = RegionAllocatorBuilder::new()
+            .zero_size(ZeroSize::Reject)
+            .build();
+        assert_eq!(regions.fit_policy(), FitPolicy::Next);
+        assert_eq!(regions.region_limit(), None);
+        assert_eq!(regions.endpoints(), Endpoints::HalfOpen);
+        assert_eq!(regions.try_add(0x1000, 0), Err(RegionError::EmptyRange));
+        regions.add(0x1000, 0x1000);
+        // Case 2: the storage changes while the settings carry over
+        let mut moved = regions
+            .into_builder()
+            .storage(VecStorage::new())
+            .endpoints(Endpoints::Closed)
+            .granule(0x1000)
+            .build();
+        assert_eq!(moved.zero_size(), ZeroSize::Reject);
+        assert!(moved.is_empty());
+        assert_eq!(moved.try_subtract(0x1000, 0), Err(RegionError::EmptyRange));
+        moved.add(0x1000, 0x1000);
+        assert!(moved.check_point(0x2000));
+        assert_eq!(moved.try_add(0x3000, 1), Err(RegionError::Unaligned));
+        assert_eq!(moved.into_builder().build().granule(), 0x1000);
+        // Case 3: policy and limits
+        let mut best = RegionAllocatorBuilder::new()
+            .fit_policy(FitPolicy::Best)
+            .region_limit(Some(2))
+            .build();
+        best.add(0, 0x4000);
+        best.add(0x8000, 0x1000);
+        assert_eq!(best.allocate_by_size(0x800, 1), Ok((0x8000, 0x800)));
+        assert_eq!(
+            best.try_subtract(0x1000, 0x1000),
+            Err(RegionError::Capacity)
+        );
+        assert_eq!(best.try_add(0x1_0000, 0x1000), Err(RegionError::Capacity));
+        assert_eq!(best.try_subtract(0, 0x1000), Ok(()));
+        assert_eq!(best.into_builder().build().region_limit(), Some(2));
+    }
+}
@@ -0,0 +1,86 @@
+//! A bump sub-allocator carved from a [`RegionAllocator`].
+
+use crate::{RegionAllocator, RegionStorage};
+
+/// A block taken from a [`RegionAllocator`] that hands out increasing sub-allocations.
+///
+/// Sub-allocations are never freed one by one; [`BumpRegion::reset`] reclaims all of
+/// them at once, and dropping the [`BumpRegion`] returns the whole block to the allocator.
+pub struct BumpRegion<'a, S: RegionStorage> {
+    regions: &'a mut RegionAllocator<S>,
+    base: usize,
+    size: usize,
+    next: usize,
+}
+
+impl<'a, S: RegionStorage> BumpRegion<'a, S> {
+    /// Allocate a block of `size` bytes aligned to `alignment` from `regions`.
+    pub fn new(regions: &'a mut RegionAllocator<S>, size: usize, alignment: usize) -> Option<Self> {
+        let (base, size) = regions.allocate_by_size(size, alignment).ok()?;
+        Some(BumpRegion {
+            regions,
+            base,
+            size,
+            next: base,
+        })
+    }
+    /// Return the block `(base, size)` owned by this [`BumpRegion`].
+    pub fn block(&self) -> (usize, usize) {
+        (self.base, self.size)
+    }
+    /// Return number of bytes not handed out yet, ignoring alignment.
+    pub fn remaining(&self) -> usize {
+        self.base + self.size - self.next
+    }
+    /// Hand out `size` bytes aligned to a given power of 2.
+    pub fn allocate(&mut self, size: usize, alignment: usize) -> Option<usize> {
+        if !alignment.is_power_of_two() {
+            return None;
+        }
+        let base = self.next.checked_add(alignment - 1)? & !(alignment - 1);
+        let end = base.checked_add(size)?;
+        if end > self.base + self.size {
+            return None;
+        }
+        self.next = end;
+        Some(base)
+    }
+    /// Reclaim every sub-allocation.
+    pub fn reset(&mut self) {
+        self.next = self.base;
+    }
+}
+
+impl<S: RegionStorage> Drop for BumpRegion<'_, S> {
+    fn drop(&mut self) {
+        // The allocator was borrowed since the block was taken, so putting it back
+        // restores the earlier set, which fitted in the storage.
+        let _ = self.regions.deallocate(self.base, self.size);
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::BumpRegion;
+    use crate::RegionAllocator;
+
+    #[test]
+    fn bump_test() {
+        let mut regions = RegionAllocator::new();
+        regions.add(0x10, 0x1000);
+        {
+            let mut bump = BumpRegion::new(&mut regions, 0x100, 0x100).unwrap();
+            assert_eq!(bump.block(), (0x100, 0x100));
+            assert_eq!(bump.allocate(0x10, 1), Some(0x100));
+            assert_eq!(bump.allocate(0x8, 0x20), Some(0x120));
+            assert_eq!(bump.allocate(0xe0, 1), None);
+            assert_eq!(bump.remaining(), 0xd8);
+            bump.reset();
+            assert_eq!(bump.allocate(0x100, 1), Some(0x100));
+        }
+        // The whole block is back after drop
+        assert!(regions.check_region(0x10, 0x1000));
+        assert_eq!(regions.stats().allocated_bytes, 0);
+        assert!(BumpRegion::new(&mut regions, 0x2000, 1).is_none());
+    }
+}
@@ -0,0 +1,154 @@
+//! Allocation constrained to a page color, for physically indexed caches.
+
+use crate::{Region, RegionAllocator, RegionError, RegionStorage, ZeroSize};
+
+/// Return the lowest base in `r` for `size` bytes aligned to `align + 1` whose page,
+/// of `1 << page_shift` bytes, has color `color` out of `colors`.
+fn colored_fit(
+    r: &Region,
+    size: usize,
+    align: usize,
+    page_shift: u32,
+    colors: usize,
+    color: usize,
+) -> Option<usize> {
+    let page = 1usize.checked_shl(page_shift)?;
+    let mut base = r.fit(size, align)?;
+    // Page colors repeat every `colors` pages, so as many steps visit every color
+    for _ in 0..colors {
+        let index = base >> page_shift;
+        if index % colors == color {
+            return Some(base);
+        }
+        base = match align < page {
+            // Jump to the start of the next page of the color, which is aligned
+            true => {
+                let steps = (color + colors - index % colors) % colors;
+                index.checked_add(steps)?.checked_mul(page)?
+            }
+            false => base.checked_add(align + 1)?,
+        };
+        if base.checked_add(size)? > r.end() {
+            return None;
+        }
+    }
+    None
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Allocate a region like [`RegionAllocator::allocate_by_size`] whose base lies in a
+    /// page of a given color, that is with `(base >> page_shift) % colors == color`.
+    ///
+    /// The lowest such placement is taken. Fail with [`RegionError::NoFit`] if there is
+    /// none, including when `color` is not below `colors`.
+    pub fn allocate_colored(
+        &mut self,
+        size: usize,
+        alignment: usize,
+        page_shift: u32,
+        colors: usize,
+        color: usize,
+    ) -> Result<(usize, usize), RegionError> {
+        if size == 0 && alignment.is_power_of_two() && self.zero_size == ZeroSize::Ignore {
+            // Nothing is taken, so the lowest base of the color will do
+            let align = (alignment - 1) | self.granule;
+            let space = Region {
+                base: 0,
+                size: usize::MAX,
+            };
+            if let Some(base) = colored_fit(&space, 0, align, page_shift, colors, color) {
+                return Ok((base, 0));
+            }
+        }
+        let found = self.take_colored_fit(size, alignment, page_shift, colors, color);
+        self.record(size, found.map(|(base, size)| Region { base, size }));
+        #[cfg(feature = "log")]
+        if let Err(e) = &found {
+            log::warn!(
+                "cannot allocate {:#x} bytes of color {}: {}",
+                size,
+                color,
+                e
+            );
+        }
+        #[cfg(feature = "tracing")]
+        match &found {
+            Ok((base, size)) => tracing::debug!(base, size, color, "allocated"),
+            Err(e) => tracing::debug!(size, alignment, color, error = %e, "allocation failed"),
+        }
+        found
+    }
+    fn take_colored_fit(
+        &mut self,
+        size: usize,
+        alignment: usize,
+        page_shift: u32,
+        colors: usize,
+        color: usize,
+    ) -> Result<(usize, usize), RegionError> {
+        if !alignment.is_power_of_two() {
+            return Err(RegionError::InvalidAlignment);
+        }
+        if size == 0 {
+            self.empty_range()?;
+        }
+        self.check_granule(0, size)?;
+        if color >= colors {
+            return Err(RegionError::NoFit);
+        }
+        let align = (alignment - 1) | self.granule;
+        let base = self
+            .regions
+            .range(..)
+            .find_map(|r| colored_fit(&r, size, align, page_shift, colors, color))
+            .ok_or(RegionError::NoFit)?;
+        self.try_subtract(base, size)?;
+        Ok((base, size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{RegionError, StaticRegionAllocator};
+
+    #[test]
+    fn colored_test() {
+        let mut alloc = StaticRegionAllocator::<4>::default();
+        alloc.add(0x1800, 0x10000);
+        // Case 1: pages of 4 KiB in 4 colors
+        assert_eq!(
+            alloc.allocate_colored(0x1000, 0x1000, 12, 4, 1),
+            Ok((0x5000, 0x1000))
+        );
+        assert_eq!(
+            alloc.allocate_colored(0x800, 0x800, 12, 4, 1),
+            Ok((0x1800, 0x800))
+        );
+        assert_eq!(
+            alloc.allocate_colored(0x1000, 0x1000, 12, 4, 2),
+            Ok((0x2000, 0x1000))
+        );
+        // Case 2: alignment above the page size steps by the alignment
+        assert_eq!(
+            alloc.allocate_colored(0x1000, 0x2000, 12, 4, 2),
+            Ok((0x6000, 0x1000))
+        );
+        assert_eq!(
+            alloc.allocate_colored(0x1000, 0x2000, 12, 4, 1),
+            Err(RegionError::NoFit)
+        );
+        // Case 3: no page of the color left, or no such color
+        assert_eq!(
+            alloc.allocate_colored(0x1000, 0x1000, 12, 32, 0),
+            Err(RegionError::NoFit)
+        );
+        assert_eq!(
+            alloc.allocate_colored(0x1000, 0x1000, 12, 4, 4),
+            Err(RegionError::NoFit)
+        );
+        assert!(alloc.check_region(0x3000, 0x2000) && alloc.check_region(0x7000, 0xa800));
+        // Case 4: empty allocations get a base of the color without taking anything
+        assert_eq!(alloc.allocate_colored(0, 0x1000, 12, 4, 3), Ok((0x3000, 0)));
+        assert_eq!(alloc.stats().allocations, 4);
+    }
+}
@@ -0,0 +1,160 @@
+//! A compact variable-length encoding of a region set, for boot information structures
+//! where every byte counts.
+//!
+//! The encoding is the region count followed by a `(gap, size)` pair per region in
+//! ascending order, where `gap` is the distance from the end of the previous region, or
+//! from zero for the first one. Every number is an unsigned LEB128 varint, so small gaps
+//! and sizes take a byte or two, and the whole is what `postcard` produces for a
+//! `Vec<(u64, u64)>` of the same pairs.
+
+use crate::{RawError, RegionAllocator, RegionStorage};
+use core::convert::TryFrom;
+
+/// Return the bytes `value` takes as a varint.
+fn varint_len(value: u64) -> usize {
+    (64 - (value | 1).leading_zeros() as usize).div_ceil(7)
+}
+
+fn write_varint(buf: &mut [u8], mut value: u64) -> usize {
+    let mut i = 0;
+    while value >= 0x80 {
+        buf[i] = value as u8 | 0x80;
+        value >>= 7;
+        i += 1;
+    }
+    buf[i] = value as u8;
+    i + 1
+}
+
+/// Read a varint from the front of `bytes`, advancing past it.
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, RawError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first().ok_or(RawError::Truncated)?;
+        *bytes = rest;
+        let bits = u64::from(byte & 0x7f);
+        if bits << shift >> shift != bits {
+            return Err(RawError::Overflow);
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(RawError::Overflow)
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Return the `(gap, size)` pairs of the compact encoding.
+    fn compact_pairs(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        let mut end = 0;
+        self.regions.range(..).map(move |r| {
+            let gap = r.base - end;
+            end = r.end();
+            (gap as u64, r.size as u64)
+        })
+    }
+    /// Return the bytes [`RegionAllocator::encode_compact`] needs.
+    pub fn compact_len(&self) -> usize {
+        let pairs = self.compact_pairs();
+        let pairs: usize = pairs
+            .map(|(gap, size)| varint_len(gap) + varint_len(size))
+            .sum();
+        varint_len(self.regions.len() as u64) + pairs
+    }
+    /// Write the compact encoding of the set to the start of `buf`, returning the bytes
+    /// written. The buffer is left untouched if it is too short.
+    pub fn encode_compact(&self, buf: &mut [u8]) -> Result<usize, RawError> {
+        let needed = self.compact_len();
+        let buf = buf
+            .get_mut(..needed)
+            .ok_or(RawError::BufferTooShort { needed })?;
+        let mut written = write_varint(buf, self.regions.len() as u64);
+        for (gap, size) in self.compact_pairs() {
+            written += write_varint(&mut buf[written..], gap);
+            written += write_varint(&mut buf[written..], size);
+        }
+        Ok(written)
+    }
+}
+
+impl<S: RegionStorage + Default> RegionAllocator<S> {
+    /// Read a set in the compact encoding from the start of `bytes`, ignoring any bytes
+    /// after it.
+    ///
+    /// Pairs with a zero gap touch the previous region and are merged with it, so
+    /// hand-made input still yields a canonical set. Settings start at their defaults.
+    pub fn decode_compact(mut bytes: &[u8]) -> Result<Self, RawError> {
+        let number = |bytes: &mut &[u8]| {
+            usize::try_from(read_varint(bytes)?).map_err(|_| RawError::Overflow)
+        };
+        let count = number(&mut bytes)?;
+        let mut regions = RegionAllocator::default();
+        let mut end = 0usize;
+        for _ in 0..count {
+            let base = end.checked_add(number(&mut bytes)?);
+            let base = base.ok_or(RawError::Overflow)?;
+            let size = number(&mut bytes)?;
+            end = base.checked_add(size).ok_or(RawError::Overflow)?;
+            regions.try_add(base, size).map_err(RawError::Region)?;
+        }
+        Ok(regions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{RawError, StaticRegionAllocator};
+
+    type Regions = StaticRegionAllocator<4>;
+
+    #[test]
+    fn compact_test() {
+        let mut regions = Regions::default();
+        regions.add(0x1000, 0x9e000);
+        regions.add(0x100000, 0x7f00000);
+        regions.add(0x9000000, 0x1000);
+        let mut buf = [0; 32];
+        let len = regions.compact_len();
+        assert_eq!(len, 19);
+        assert_eq!(
+            regions.encode_compact(&mut buf[..len - 1]),
+            Err(RawError::BufferTooShort { needed: len })
+        );
+        assert_eq!(regions.encode_compact(&mut buf), Ok(len));
+        assert_eq!(&buf[..6], [3, 0x80, 0x20, 0x80, 0xc0, 0x27]);
+        let read = Regions::decode_compact(&buf).unwrap();
+        assert!(read == regions);
+        assert!(Regions::decode_compact(&[0]).is_ok_and(|r| r.is_empty()));
+        // Case 2: a zero gap merges, and malformed input is rejected
+        let read = Regions::decode_compact(&[2, 1, 1, 0, 2]).unwrap();
+        assert!(read.check_region(1, 3) && read.len() == 1);
+        assert_eq!(
+            Regions::decode_compact(&[2, 1, 1, 0]).map(drop),
+            Err(RawError::Truncated)
+        );
+        assert_eq!(
+            Regions::decode_compact(&[
+                1, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f
+            ])
+            .map(drop),
+            Err(RawError::Overflow)
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn compact_postcard_test() {
+        use crate::RegionAllocator;
+        use alloc::vec::Vec;
+
+        let mut regions = RegionAllocator::new();
+        regions.add(0x1000, 0x9e000);
+        regions.add(0x100000, 0x7f00000);
+        let mut buf = [0; 16];
+        let len = regions.encode_compact(&mut buf).unwrap();
+        let pairs: Vec<(u64, u64)> = postcard::from_bytes(&buf[..len]).unwrap();
+        assert_eq!(pairs, [(0x1000, 0x9e000), (0x100000 - 0x9f000, 0x7f00000)]);
+        assert_eq!(postcard::to_allocvec(&pairs).unwrap(), &buf[..len]);
+    }
+}
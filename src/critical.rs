@@ -0,0 +1,132 @@
+//! A [`RegionAllocator`] locked through the `critical-section` crate.
+
+#[cfg(feature = "alloc")]
+use crate::BTreeStorage;
+use crate::{RegionAllocator, RegionError, RegionStats, RegionStorage, Watermarks};
+use core::cell::RefCell;
+use critical_section::Mutex;
+
+/// A [`RegionAllocator`] accessed inside a critical section, usable through a shared reference.
+///
+/// What a critical section means is up to the `critical-section` implementation linked into
+/// the final binary: masking interrupts on single-core microcontrollers, or a global spinlock
+/// on multi-core targets. Access is not reentrant; calling back into the same allocator from
+/// within [`CriticalSectionRegionAllocator::with`] panics.
+pub struct CriticalSectionRegionAllocator<
+    #[cfg(feature = "alloc")] S = BTreeStorage,
+    #[cfg(not(feature = "alloc"))] S,
+> {
+    inner: Mutex<RefCell<RegionAllocator<S>>>,
+}
+
+impl<S> CriticalSectionRegionAllocator<S> {
+    /// Wrap a [`RegionAllocator`].
+    pub const fn new(inner: RegionAllocator<S>) -> Self {
+        CriticalSectionRegionAllocator {
+            inner: Mutex::new(RefCell::new(inner)),
+        }
+    }
+    /// Unwrap the [`RegionAllocator`].
+    pub fn into_inner(self) -> RegionAllocator<S> {
+        self.inner.into_inner().into_inner()
+    }
+    /// Run `f` on the [`RegionAllocator`] inside a critical section.
+    pub fn with<R>(&self, f: impl FnOnce(&mut RegionAllocator<S>) -> R) -> R {
+        critical_section::with(|cs| f(&mut self.inner.borrow_ref_mut(cs)))
+    }
+}
+
+impl<S: RegionStorage> CriticalSectionRegionAllocator<S> {
+    /// See [`RegionAllocator::add`].
+    pub fn add(&self, base: usize, size: usize) {
+        self.with(|r| r.add(base, size))
+    }
+    /// See [`RegionAllocator::try_add`].
+    pub fn try_add(&self, base: usize, size: usize) -> Result<(), RegionError> {
+        self.with(|r| r.try_add(base, size))
+    }
+    /// See [`RegionAllocator::subtract`].
+    pub fn subtract(&self, base: usize, size: usize) {
+        self.with(|r| r.subtract(base, size))
+    }
+    /// See [`RegionAllocator::try_subtract`].
+    pub fn try_subtract(&self, base: usize, size: usize) -> Result<(), RegionError> {
+        self.with(|r| r.try_subtract(base, size))
+    }
+    /// See [`RegionAllocator::allocate_by_addr`].
+    pub fn allocate_by_addr(&self, base: usize, size: usize) -> Result<(), RegionError> {
+        self.with(|r| r.allocate_by_addr(base, size))
+    }
+    /// See [`RegionAllocator::allocate_by_size`].
+    pub fn allocate_by_size(
+        &self,
+        size: usize,
+        alignment: usize,
+    ) -> Result<(usize, usize), RegionError> {
+        self.with(|r| r.allocate_by_size(size, alignment))
+    }
+    /// See [`RegionAllocator::deallocate`].
+    pub fn deallocate(&self, base: usize, size: usize) -> Result<(), RegionError> {
+        self.with(|r| r.deallocate(base, size))
+    }
+    /// See [`RegionAllocator::stats`].
+    pub fn stats(&self) -> RegionStats {
+        self.with(|r| r.stats())
+    }
+    /// See [`RegionAllocator::watermarks`].
+    pub fn watermarks(&self) -> Watermarks {
+        self.with(|r| r.watermarks())
+    }
+    /// See [`RegionAllocator::check_region`].
+    pub fn check_region(&self, base: usize, size: usize) -> bool {
+        self.with(|r| r.check_region(base, size))
+    }
+    /// See [`RegionAllocator::check_point`].
+    pub fn check_point(&self, addr: usize) -> bool {
+        self.with(|r| r.check_point(addr))
+    }
+    /// See [`RegionAllocator::len`].
+    pub fn len(&self) -> usize {
+        self.with(|r| r.len())
+    }
+    /// See [`RegionAllocator::is_empty`].
+    pub fn is_empty(&self) -> bool {
+        self.with(|r| r.is_empty())
+    }
+}
+
+impl<S: Default> Default for CriticalSectionRegionAllocator<S> {
+    fn default() -> Self {
+        CriticalSectionRegionAllocator::new(RegionAllocator::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::CriticalSectionRegionAllocator;
+    use crate::{ArrayStorage, RegionAllocator};
+    use std::vec::Vec;
+
+    static ALLOC: CriticalSectionRegionAllocator<ArrayStorage<8>> =
+        CriticalSectionRegionAllocator::new(RegionAllocator::with_storage(ArrayStorage::new()));
+
+    #[test]
+    fn critical_section_test() {
+        ALLOC.add(0, 0x4000);
+        let bases: Vec<_> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| ALLOC.allocate_by_size(0x1000, 0x1000).unwrap().0))
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        assert_eq!(bases.iter().sum::<usize>(), 0x6000);
+        assert!(ALLOC.is_empty());
+        ALLOC.with(|r| {
+            r.add(0x1000, 0x1000);
+            r.add(0x2000, 0x1000);
+        });
+        assert!(ALLOC.check_region(0x1000, 0x2000));
+    }
+}
@@ -0,0 +1,152 @@
+//! Errors reported by region set operations.
+
+use crate::{CapacityError, Region};
+use core::fmt;
+
+/// The reason an operation on a region set failed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RegionError {
+    /// No region has room for the requested size and alignment.
+    NoFit,
+    /// No part of the requested range is in the set.
+    NotCovered,
+    /// Only part of the requested range is in the set; this is its lowest missing sub-range.
+    PartiallyCovered(Region),
+    /// The alignment is not a power of 2.
+    InvalidAlignment,
+    /// The range is empty and [`ZeroSize::Reject`](crate::ZeroSize::Reject) is in effect.
+    EmptyRange,
+    /// The range does not start and end on the granule set by
+    /// [`RegionAllocator::with_granule`](crate::RegionAllocator::with_granule).
+    Unaligned,
+    /// The range overlaps a region already in the set.
+    Overlapping,
+    /// The requested range does not fit in the address space.
+    Overflow,
+    /// The storage has no room for the regions the operation would leave.
+    Capacity,
+    /// The set is locked elsewhere and the operation would have to wait.
+    WouldBlock,
+}
+
+impl fmt::Display for RegionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match *self {
+            RegionError::NoFit => "no region fits the requested size and alignment",
+            RegionError::NotCovered => "range is not in the set",
+            RegionError::PartiallyCovered(gap) => {
+                return write!(
+                    f,
+                    "range is only partially in the set, {} is missing",
+                    Span(gap)
+                );
+            }
+            RegionError::InvalidAlignment => "alignment is not a power of 2",
+            RegionError::EmptyRange => "range is empty",
+            RegionError::Unaligned => "range is not aligned to the granule",
+            RegionError::Overlapping => "range overlaps the set",
+            RegionError::Overflow => "range overflows the address space",
+            RegionError::Capacity => "storage is out of capacity",
+            RegionError::WouldBlock => "region set is locked",
+        };
+        f.write_str(reason)
+    }
+}
+
+impl core::error::Error for RegionError {}
+
+impl From<CapacityError> for RegionError {
+    fn from(_: CapacityError) -> Self {
+        RegionError::Capacity
+    }
+}
+
+/// A broken invariant of a region set, found by [`RegionAllocator::validate`](crate::RegionAllocator::validate).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Violation {
+    /// The region holds no bytes.
+    Empty(Region),
+    /// The region extends past `usize::MAX`.
+    Overflow(Region),
+    /// The second region is stored after the first but starts below it.
+    Unsorted(Region, Region),
+    /// The two consecutive regions intersect.
+    Overlapping(Region, Region),
+    /// The two consecutive regions touch and should have been merged.
+    Adjacent(Region, Region),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Violation::Empty(r) => write!(f, "empty region at {:#x}", r.base),
+            Violation::Overflow(r) => write!(f, "region {} overflows", Span(r)),
+            Violation::Unsorted(a, b) => {
+                write!(f, "region {} is stored after {}", Span(b), Span(a))
+            }
+            Violation::Overlapping(a, b) => {
+                write!(f, "regions {} and {} overlap", Span(a), Span(b))
+            }
+            Violation::Adjacent(a, b) => {
+                write!(f, "regions {} and {} are not merged", Span(a), Span(b))
+            }
+        }
+    }
+}
+
+impl core::error::Error for Violation {}
+
+/// Formats a region as `base..end`, with an end that may lie past `usize::MAX`.
+struct Span(Region);
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let end = self.0.base as u128 + self.0.size as u128;
+        write!(f, "{:#x}..{:#x}", self.0.base, end)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::{RegionError, Violation};
+    use crate::{CapacityError, Region};
+    use alloc::string::ToString;
+
+    #[test]
+    fn display_test() {
+        assert_eq!(RegionError::from(CapacityError), RegionError::Capacity);
+        assert_eq!(RegionError::Capacity.to_string(), CapacityError.to_string());
+        let a = Region {
+            base: 0x1000,
+            size: 0x1000,
+        };
+        let b = Region {
+            base: usize::MAX,
+            size: 2,
+        };
+        assert_eq!(
+            Violation::Adjacent(a, a).to_string(),
+            "regions 0x1000..0x2000 and 0x1000..0x2000 are not merged"
+        );
+        assert_eq!(
+            Violation::Overflow(b).to_string(),
+            alloc::format!(
+                "region {:#x}..{:#x} overflows",
+                usize::MAX,
+                usize::MAX as u128 + 2
+            )
+        );
+        assert_eq!(
+            RegionError::PartiallyCovered(a).to_string(),
+            "range is only partially in the set, 0x1000..0x2000 is missing"
+        );
+        // Usable wherever a standard error is expected
+        let e: &dyn core::error::Error = &RegionError::NoFit;
+        assert_eq!(
+            e.to_string(),
+            "no region fits the requested size and alignment"
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! Memory maps handed over by firmware and bootloaders.
+//!
+//! Each format lives behind a feature of its own name. Entries are given as 64-bit
+//! addresses; on narrower targets the parts above the address space are dropped.
+
+#[cfg(feature = "bootloader_api")]
+mod bootloader;
+#[cfg(feature = "e820")]
+mod e820;
+#[cfg(feature = "fdt")]
+mod fdt;
+#[cfg(feature = "limine")]
+mod limine;
+#[cfg(feature = "multiboot2")]
+mod multiboot2;
+#[cfg(feature = "srat")]
+mod srat;
+#[cfg(feature = "uefi")]
+mod uefi;
+
+#[cfg(feature = "e820")]
+pub use e820::E820Entry;
+#[cfg(feature = "fdt")]
+pub use fdt::Fdt;
+#[cfg(feature = "limine")]
+pub use limine::LimineMemmapEntry;
+#[cfg(feature = "multiboot2")]
+pub use multiboot2::{Multiboot2Info, Multiboot2MemoryEntry};
+#[cfg(feature = "srat")]
+pub use srat::{Srat, SratMemoryAffinity};
+#[cfg(feature = "uefi")]
+pub use uefi::{EfiMemoryDescriptor, UefiMemoryMap};
+
+use crate::{RegionAllocator, RegionError, RegionStorage};
+use core::convert::TryFrom;
+
+/// Convert a firmware range to `(base, size)`, clipped to the address space,
+/// or `None` if nothing of it is left.
+fn clip(base: u64, length: u64) -> Option<(usize, usize)> {
+    let end = base.saturating_add(length);
+    let base = usize::try_from(base).ok()?;
+    let end = usize::try_from(end).unwrap_or(usize::MAX);
+    Some((base, end.checked_sub(base)?)).filter(|&(_, size)| size != 0)
+}
+
+// Each format only needs some of these, depending on the features enabled
+#[allow(dead_code)]
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Add the `(base, length, usable)` entries marked usable, then subtract the others,
+    /// so that a reserved entry wins over a usable one it overlaps.
+    fn extend_usable(
+        &mut self,
+        entries: impl Iterator<Item = (u64, u64, bool)> + Clone,
+    ) -> Result<(), RegionError> {
+        let usable = entries.clone().filter(|e| e.2).map(|(b, l, _)| (b, l));
+        self.extend_ranges(usable)?;
+        self.subtract_ranges(entries.filter(|e| !e.2).map(|(b, l, _)| (b, l)))
+    }
+    /// Add `(base, length)` ranges.
+    fn extend_ranges(
+        &mut self,
+        ranges: impl Iterator<Item = (u64, u64)>,
+    ) -> Result<(), RegionError> {
+        for (base, length) in ranges {
+            if let Some((base, size)) = clip(base, length) {
+                self.try_add(base, size)?;
+            }
+        }
+        Ok(())
+    }
+    /// Subtract `(base, length)` ranges.
+    fn subtract_ranges(
+        &mut self,
+        ranges: impl Iterator<Item = (u64, u64)>,
+    ) -> Result<(), RegionError> {
+        for (base, length) in ranges {
+            if let Some((base, size)) = clip(base, length) {
+                self.try_subtract(base, size)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+//! The memory regions of the `bootloader` crate's `BootInfo`.
+
+use crate::{RegionAllocator, RegionError, RegionStorage};
+use bootloader_api::info::{MemoryRegion, MemoryRegionKind};
+use core::convert::TryFrom;
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Add the usable regions of `BootInfo::memory_regions`, which dereferences to the
+    /// slice taken here.
+    ///
+    /// Usable regions are added and every other one is then subtracted, so the page tables
+    /// and boot information the bootloader placed stay out of the set.
+    pub fn extend_bootloader(&mut self, regions: &[MemoryRegion]) -> Result<(), RegionError> {
+        let entries = regions.iter().map(|r| {
+            let usable = r.kind == MemoryRegionKind::Usable;
+            (r.start, r.end.saturating_sub(r.start), usable)
+        });
+        self.extend_usable(entries)
+    }
+    /// Write the regions into `out` as usable `MemoryRegion`s, in ascending order, and
+    /// return the written part, or `None` if `out` is too short.
+    pub fn to_bootloader_regions<'a>(
+        &self,
+        out: &'a mut [MemoryRegion],
+    ) -> Option<&'a mut [MemoryRegion]> {
+        let out = out.get_mut(..self.regions.len())?;
+        for (slot, r) in out.iter_mut().zip(self.regions.range(..)) {
+            *slot = MemoryRegion {
+                start: u64::try_from(r.base).ok()?,
+                end: u64::try_from(r.end()).ok()?,
+                kind: MemoryRegionKind::Usable,
+            };
+        }
+        Some(out)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use crate::RegionAllocator;
+    use bootloader_api::info::{MemoryRegion, MemoryRegionKind};
+
+    fn region(start: u64, end: u64, kind: MemoryRegionKind) -> MemoryRegion {
+        MemoryRegion { start, end, kind }
+    }
+
+    #[test]
+    fn bootloader_test() {
+        let regions = [
+            region(0, 0x1000, MemoryRegionKind::UnknownBios(2)),
+            region(0x1000, 0x9f000, MemoryRegionKind::Usable),
+            region(0x100000, 0x200000, MemoryRegionKind::Bootloader),
+            region(0x200000, 0x8000000, MemoryRegionKind::Usable),
+            // Touches the usable region above
+            region(0x8000000, 0x8100000, MemoryRegionKind::Usable),
+        ];
+        let mut ram = RegionAllocator::new();
+        ram.extend_bootloader(&regions).unwrap();
+        assert_eq!(ram.len(), 2);
+        assert!(ram.check_region(0x200000, 0x7f00000));
+        // Case 2: back into regions, which only fit a long enough slice
+        let mut out = [MemoryRegion::empty(); 3];
+        assert!(ram.to_bootloader_regions(&mut out[..1]).is_none());
+        let written = ram.to_bootloader_regions(&mut out).unwrap();
+        assert_eq!(
+            written,
+            [
+                region(0x1000, 0x9f000, MemoryRegionKind::Usable),
+                region(0x200000, 0x8100000, MemoryRegionKind::Usable),
+            ]
+        );
+    }
+}
@@ -0,0 +1,91 @@
+//! The BIOS E820 memory map.
+
+use crate::{RegionAllocator, RegionError, RegionStorage};
+
+/// An entry of the memory map returned by `int 0x15, eax=0xe820`.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct E820Entry {
+    pub base: u64,
+    pub length: u64,
+    pub kind: u32,
+}
+
+impl E820Entry {
+    /// RAM available to the operating system.
+    pub const USABLE: u32 = 1;
+    /// Reserved by the firmware or a device.
+    pub const RESERVED: u32 = 2;
+    /// ACPI tables, usable once they have been read.
+    pub const ACPI_RECLAIMABLE: u32 = 3;
+    /// ACPI non-volatile storage, preserved across sleep.
+    pub const ACPI_NVS: u32 = 4;
+    /// RAM found to be faulty.
+    pub const BAD: u32 = 5;
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Add the usable RAM described by an E820 map.
+    ///
+    /// Usable entries are added first and every other entry is then subtracted, so where
+    /// the firmware reports overlapping entries the reserved one wins. Ranges listed in
+    /// any order and touching each other are merged.
+    pub fn extend_e820(&mut self, entries: &[E820Entry]) -> Result<(), RegionError> {
+        let entries = entries
+            .iter()
+            .map(|e| (e.base, e.length, e.kind == E820Entry::USABLE));
+        self.extend_usable(entries)
+    }
+    /// Add the entries of an E820 map whose type satisfies `f`, such as the reserved ones
+    /// with `|kind| kind != E820Entry::USABLE`.
+    pub fn extend_e820_if(
+        &mut self,
+        entries: &[E820Entry],
+        mut f: impl FnMut(u32) -> bool,
+    ) -> Result<(), RegionError> {
+        let entries = entries.iter().filter(|e| f(e.kind));
+        self.extend_ranges(entries.map(|e| (e.base, e.length)))
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::E820Entry;
+    use crate::RegionAllocator;
+
+    fn entry(base: u64, length: u64, kind: u32) -> E820Entry {
+        E820Entry { base, length, kind }
+    }
+
+    #[test]
+    fn e820_test() {
+        let map = [
+            entry(0, 0x9fc00, E820Entry::USABLE),
+            entry(0x9fc00, 0x400, E820Entry::RESERVED),
+            entry(0xf0000, 0x10000, E820Entry::RESERVED),
+            entry(0x100000, 0x7ee0000, E820Entry::USABLE),
+            // Overlaps the usable range above
+            entry(0x7fc0000, 0x40000, E820Entry::ACPI_RECLAIMABLE),
+            entry(0xfffc0000, 0x40000, E820Entry::RESERVED),
+            entry(0x1_0000_0000, 0x1000_0000, E820Entry::USABLE),
+        ];
+        let mut ram = RegionAllocator::new();
+        ram.extend_e820(&map).unwrap();
+        assert!(ram.check_region(0, 0x9fc00));
+        assert!(ram.check_region(0x100000, 0x7ec0000));
+        if usize::BITS == 64 {
+            assert!(ram.check_region(0x1_0000_0000, 0x1000_0000));
+        } else {
+            assert_eq!(ram.len(), 2);
+        }
+        // Recording the reserved ranges apart
+        let mut reserved = RegionAllocator::new();
+        reserved
+            .extend_e820_if(&map, |kind| kind != E820Entry::USABLE)
+            .unwrap();
+        assert!(reserved.check_region(0x9fc00, 0x400));
+        assert!(reserved.check_region(0x7fc0000, 0x40000));
+        assert!(reserved.is_disjoint(&ram));
+        assert_eq!(core::mem::size_of::<E820Entry>(), 20);
+    }
+}
@@ -0,0 +1,331 @@
+//! The flattened devicetree.
+
+use crate::{RegionAllocator, RegionError, RegionStorage};
+use core::convert::TryInto;
+
+const MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+fn be32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+fn be64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let bytes = bytes.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+/// Return the NUL-terminated string at the start of `bytes`.
+fn c_str(bytes: &[u8]) -> Option<&[u8]> {
+    bytes
+        .split(|&b| b == 0)
+        .next()
+        .filter(|_| bytes.contains(&0))
+}
+
+/// The `#address-cells` and `#size-cells` a node gives the `reg` of its children.
+#[derive(Clone, Copy)]
+struct Cells {
+    address: usize,
+    size: usize,
+}
+
+impl Default for Cells {
+    fn default() -> Self {
+        Cells {
+            address: 2,
+            size: 1,
+        }
+    }
+}
+
+/// The nodes whose `reg` describes memory.
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Node {
+    /// A `/memory` node.
+    Memory,
+    /// A child of `/reserved-memory`.
+    Reserved,
+}
+
+/// A flattened devicetree blob, as handed over in `x0` on AArch64 or `a1` on RISC-V.
+#[derive(Clone, Copy, Debug)]
+pub struct Fdt<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Fdt<'a> {
+    /// Wrap a devicetree blob, or return `None` if it has no valid header or its total
+    /// size does not fit in `bytes`.
+    pub fn new(bytes: &'a [u8]) -> Option<Self> {
+        if be32(bytes, 0)? != MAGIC {
+            return None;
+        }
+        let total = be32(bytes, 4)? as usize;
+        Some(Fdt {
+            bytes: bytes.get(..total).filter(|b| b.len() >= 40)?,
+        })
+    }
+    /// Wrap the devicetree blob at `addr`.
+    ///
+    /// # Safety
+    ///
+    /// `addr` must point to a devicetree blob that stays valid and unchanged for `'a`.
+    pub unsafe fn from_ptr(addr: *const u8) -> Option<Self> {
+        let total = u32::from_be((addr.add(4) as *const u32).read_unaligned()) as usize;
+        Fdt::new(core::slice::from_raw_parts(addr, total))
+    }
+    /// Iterate over the `(address, size)` entries of the memory reservation block.
+    pub fn memreserve(&self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        let bytes = self.bytes;
+        let mut offset = be32(bytes, 16).map(|o| o as usize);
+        core::iter::from_fn(move || {
+            let at = offset?;
+            let entry = (be64(bytes, at)?, be64(bytes, at + 8)?);
+            // The block ends with an all-zero entry
+            offset = Some(at + 16).filter(|_| entry != (0, 0));
+            offset.map(|_| entry)
+        })
+    }
+    /// Call `f` with the `(address, size)` entries of every `reg` under a `/memory` node or
+    /// a child of `/reserved-memory`, each decoded with its parent's cell counts.
+    ///
+    /// A malformed structure block ends the walk early; entries wider than 64 bits are
+    /// skipped.
+    fn walk<E>(&self, mut f: impl FnMut(Node, u64, u64) -> Result<(), E>) -> Result<(), E> {
+        let bytes = self.bytes;
+        let (structure, strings) = match (be32(bytes, 8), be32(bytes, 12)) {
+            (Some(s), Some(t)) => (s as usize, t as usize),
+            _ => return Ok(()),
+        };
+        let strings = bytes.get(strings..).unwrap_or(&[]);
+        let mut offset = structure;
+        let mut depth = 0;
+        let (mut root, mut reserved) = (Cells::default(), Cells::default());
+        let (mut memory, mut in_reserved) = (false, false);
+        // The `reg` of the current node at the depths that matter
+        let mut regs: [Option<&[u8]>; 4] = [None; 4];
+        while let Some(token) = be32(bytes, offset) {
+            offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = match bytes.get(offset..).and_then(c_str) {
+                        Some(name) => name,
+                        None => break,
+                    };
+                    offset = (offset + name.len() + 1 + 3) & !3;
+                    depth += 1;
+                    if depth == 2 {
+                        memory = name == b"memory" || name.starts_with(b"memory@");
+                        in_reserved = name == b"reserved-memory";
+                        reserved = Cells::default();
+                    }
+                    if let Some(reg) = regs.get_mut(depth) {
+                        *reg = None;
+                    }
+                }
+                FDT_END_NODE => {
+                    let node = match depth {
+                        2 if memory => Some((Node::Memory, root)),
+                        3 if in_reserved => Some((Node::Reserved, reserved)),
+                        _ => None,
+                    };
+                    let reg = regs.get(depth).copied().flatten();
+                    depth = match depth.checked_sub(1) {
+                        Some(depth) => depth,
+                        None => break,
+                    };
+                    let (node, cells, reg) = match (node, reg) {
+                        (Some((node, cells)), Some(reg)) => (node, cells, reg),
+                        _ => continue,
+                    };
+                    if cells.address > 2 || cells.size > 2 || cells.address + cells.size == 0 {
+                        continue;
+                    }
+                    for entry in reg.chunks_exact(4 * (cells.address + cells.size)) {
+                        let (address, size) = entry.split_at(4 * cells.address);
+                        f(node, cells_value(address), cells_value(size))?;
+                    }
+                }
+                FDT_PROP => {
+                    let (len, name) = match (be32(bytes, offset), be32(bytes, offset + 4)) {
+                        (Some(len), Some(name)) => (len as usize, name as usize),
+                        _ => break,
+                    };
+                    let value = match bytes.get(offset + 8..).and_then(|b| b.get(..len)) {
+                        Some(value) => value,
+                        None => break,
+                    };
+                    offset = (offset + 8 + len + 3) & !3;
+                    let name = strings.get(name..).and_then(c_str).unwrap_or(&[]);
+                    let count = be32(value, 0).map(|c| c as usize);
+                    match (depth, name) {
+                        (1, b"#address-cells") => root.address = count.unwrap_or(2),
+                        (1, b"#size-cells") => root.size = count.unwrap_or(1),
+                        (2, b"#address-cells") if in_reserved => {
+                            reserved.address = count.unwrap_or(2)
+                        }
+                        (2, b"#size-cells") if in_reserved => reserved.size = count.unwrap_or(1),
+                        (2, b"device_type") if value == b"memory\0" => memory = true,
+                        (2..=3, b"reg") => regs[depth] = Some(value),
+                        _ => {}
+                    }
+                }
+                FDT_NOP => {}
+                // `FDT_END`, or a token this walk does not know
+                _ => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decode a big-endian value of at most two cells.
+fn cells_value(cells: &[u8]) -> u64 {
+    cells.iter().fold(0, |v, &b| v << 8 | u64::from(b))
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Add the RAM a devicetree describes: the `reg` of every `/memory` node, then with
+    /// every child of `/reserved-memory` and every memory reservation block entry
+    /// subtracted.
+    ///
+    /// Each `reg` is decoded with the `#address-cells` and `#size-cells` of its parent
+    /// node, which default to 2 and 1. Reserved children given only a `size`, to be placed
+    /// by the operating system, have no address yet and are left in.
+    pub fn extend_fdt(&mut self, fdt: &Fdt) -> Result<(), RegionError> {
+        fdt.walk(|node, base, length| match node {
+            Node::Memory => self.extend_ranges(Some((base, length)).into_iter()),
+            Node::Reserved => Ok(()),
+        })?;
+        fdt.walk(|node, base, length| match node {
+            Node::Memory => Ok(()),
+            Node::Reserved => self.subtract_ranges(Some((base, length)).into_iter()),
+        })?;
+        self.subtract_ranges(fdt.memreserve())
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::Fdt;
+    use crate::RegionAllocator;
+    use alloc::vec::Vec;
+
+    /// A devicetree blob under construction, with its strings block kept apart.
+    #[derive(Default)]
+    struct Blob {
+        structure: Vec<u8>,
+        strings: Vec<u8>,
+    }
+
+    impl Blob {
+        fn token(&mut self, token: u32) {
+            self.structure.extend_from_slice(&token.to_be_bytes());
+        }
+        fn pad(&mut self) {
+            while !self.structure.len().is_multiple_of(4) {
+                self.structure.push(0);
+            }
+        }
+        fn begin(&mut self, name: &str) {
+            self.token(1);
+            self.structure.extend_from_slice(name.as_bytes());
+            self.structure.push(0);
+            self.pad();
+        }
+        fn prop(&mut self, name: &str, value: &[u8]) {
+            self.token(3);
+            self.token(value.len() as u32);
+            self.token(self.strings.len() as u32);
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+            self.structure.extend_from_slice(value);
+            self.pad();
+        }
+        fn cells(&mut self, name: &str, cells: &[u32]) {
+            let value: Vec<u8> = cells.iter().flat_map(|c| c.to_be_bytes()).collect();
+            self.prop(name, &value);
+        }
+        fn finish(mut self, memreserve: &[(u64, u64)]) -> Vec<u8> {
+            self.token(9);
+            let mut rsv = Vec::new();
+            for &(address, size) in memreserve.iter().chain(&[(0, 0)]) {
+                rsv.extend_from_slice(&address.to_be_bytes());
+                rsv.extend_from_slice(&size.to_be_bytes());
+            }
+            let structure = 40 + rsv.len();
+            let strings = structure + self.structure.len();
+            let total = strings + self.strings.len();
+            let mut blob = Vec::new();
+            for field in [0xd00d_feed, total, structure, strings, 40, 17, 16, 0] {
+                blob.extend_from_slice(&(field as u32).to_be_bytes());
+            }
+            blob.extend_from_slice(&(self.strings.len() as u32).to_be_bytes());
+            blob.extend_from_slice(&(self.structure.len() as u32).to_be_bytes());
+            blob.extend(rsv);
+            blob.extend(self.structure);
+            blob.extend(self.strings);
+            blob
+        }
+    }
+
+    #[test]
+    fn fdt_test() {
+        let mut blob = Blob::default();
+        blob.begin("");
+        blob.cells("#address-cells", &[2]);
+        blob.cells("#size-cells", &[2]);
+        blob.begin("chosen");
+        blob.cells("reg", &[0, 0, 0, 0x1000]);
+        blob.token(2);
+        // Two banks in one node, and one found by its device type after its reg
+        blob.begin("memory@80000000");
+        blob.cells(
+            "reg",
+            &[0, 0x8000_0000, 0, 0x10_0000, 0, 0x9000_0000, 0, 0x1000],
+        );
+        blob.prop("device_type", b"memory\0");
+        blob.token(2);
+        blob.begin("ram");
+        blob.cells("reg", &[0, 0x4000_0000, 0, 0x1000]);
+        blob.prop("device_type", b"memory\0");
+        blob.token(2);
+        // Reserved children use their parent's single cells
+        blob.begin("reserved-memory");
+        blob.cells("#address-cells", &[1]);
+        blob.cells("#size-cells", &[1]);
+        blob.begin("opensbi@80000000");
+        blob.cells("reg", &[0x8000_0000, 0x2_0000]);
+        blob.token(2);
+        blob.begin("linux,cma");
+        blob.cells("size", &[0x1000]);
+        blob.token(4);
+        blob.token(2);
+        blob.token(2);
+        blob.token(2);
+        let blob = blob.finish(&[(0x800f_f000, 0x1000)]);
+
+        let fdt = Fdt::new(&blob).unwrap();
+        assert_eq!(
+            fdt.memreserve().collect::<Vec<_>>(),
+            [(0x800f_f000, 0x1000)]
+        );
+        let mut ram = RegionAllocator::new();
+        ram.extend_fdt(&fdt).unwrap();
+        assert_eq!(ram.len(), 3);
+        assert!(ram.check_region(0x4000_0000, 0x1000));
+        assert!(ram.check_region(0x8002_0000, 0xd_f000));
+        assert!(ram.check_region(0x9000_0000, 0x1000));
+        assert!(!ram.check_point(0));
+        // Case 2: a bad magic number or a truncated blob
+        let mut bad = blob.clone();
+        bad[0] = 0;
+        assert!(Fdt::new(&bad).is_none());
+        assert!(Fdt::new(&blob[..blob.len() - 1]).is_none());
+    }
+}
@@ -0,0 +1,92 @@
+//! The Limine boot protocol memory map.
+
+use crate::{RegionAllocator, RegionError, RegionStorage};
+
+/// An entry of the Limine memory map response, which hands over an array of pointers to
+/// these.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LimineMemmapEntry {
+    pub base: u64,
+    pub length: u64,
+    pub kind: u64,
+}
+
+impl LimineMemmapEntry {
+    /// RAM available to the operating system.
+    pub const USABLE: u64 = 0;
+    /// Reserved by the firmware or a device.
+    pub const RESERVED: u64 = 1;
+    /// ACPI tables, usable once they have been read.
+    pub const ACPI_RECLAIMABLE: u64 = 2;
+    /// ACPI non-volatile storage, preserved across sleep.
+    pub const ACPI_NVS: u64 = 3;
+    /// RAM found to be faulty.
+    pub const BAD_MEMORY: u64 = 4;
+    /// Bootloader structures, such as the responses and page tables, usable once the
+    /// kernel no longer needs them.
+    pub const BOOTLOADER_RECLAIMABLE: u64 = 5;
+    /// The kernel image and the loaded modules.
+    pub const EXECUTABLE_AND_MODULES: u64 = 6;
+    /// A framebuffer.
+    pub const FRAMEBUFFER: u64 = 7;
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Add the usable RAM described by the entries of a Limine memory map response.
+    ///
+    /// `entries` has the layout of the response's `entries` pointer array, so it can be
+    /// built with `slice::from_raw_parts(response.entries, response.entry_count)`.
+    /// Usable entries are added and every other entry is then subtracted. Bootloader
+    /// reclaimable memory is left out until added with [`RegionAllocator::extend_limine_if`].
+    pub fn extend_limine(&mut self, entries: &[&LimineMemmapEntry]) -> Result<(), RegionError> {
+        let entries = entries
+            .iter()
+            .map(|e| (e.base, e.length, e.kind == LimineMemmapEntry::USABLE));
+        self.extend_usable(entries)
+    }
+    /// Add the entries of a Limine memory map whose type satisfies `f`, such as
+    /// `|kind| kind == LimineMemmapEntry::BOOTLOADER_RECLAIMABLE` once the bootloader
+    /// structures are done with.
+    pub fn extend_limine_if(
+        &mut self,
+        entries: &[&LimineMemmapEntry],
+        mut f: impl FnMut(u64) -> bool,
+    ) -> Result<(), RegionError> {
+        let entries = entries.iter().filter(|e| f(e.kind));
+        self.extend_ranges(entries.map(|e| (e.base, e.length)))
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::LimineMemmapEntry as E;
+    use crate::RegionAllocator;
+
+    fn entry(base: u64, length: u64, kind: u64) -> E {
+        E { base, length, kind }
+    }
+
+    #[test]
+    fn limine_test() {
+        let map = [
+            entry(0x1000, 0x9e000, E::USABLE),
+            entry(0x9f000, 0x1000, E::RESERVED),
+            entry(0x100000, 0x100000, E::EXECUTABLE_AND_MODULES),
+            entry(0x200000, 0x40000, E::BOOTLOADER_RECLAIMABLE),
+            entry(0x240000, 0x7dc0000, E::USABLE),
+            entry(0xfd000000, 0x300000, E::FRAMEBUFFER),
+        ];
+        let entries: [&E; 6] = [&map[0], &map[1], &map[2], &map[3], &map[4], &map[5]];
+        let mut ram = RegionAllocator::new();
+        ram.extend_limine(&entries).unwrap();
+        assert_eq!(ram.len(), 2);
+        assert!(ram.check_region(0x1000, 0x9e000));
+        assert!(ram.check_region(0x240000, 0x7dc0000));
+        // Reclaiming the bootloader memory merges it with its neighbour
+        ram.extend_limine_if(&entries, |kind| kind == E::BOOTLOADER_RECLAIMABLE)
+            .unwrap();
+        assert!(ram.check_region(0x200000, 0x7e00000));
+        assert_eq!(ram.len(), 2);
+    }
+}
@@ -0,0 +1,252 @@
+//! The Multiboot2 boot information structure.
+
+use crate::{RegionAllocator, RegionError, RegionStorage};
+use core::convert::TryInto;
+
+const TAG_END: u32 = 0;
+const TAG_MODULE: u32 = 3;
+const TAG_MEMORY_MAP: u32 = 6;
+const TAG_ELF_SECTIONS: u32 = 9;
+/// `SHF_ALLOC`: the section occupies memory at run time.
+const SHF_ALLOC: u64 = 2;
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_ne_bytes(bytes.try_into().ok()?))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Option<u64> {
+    let bytes = bytes.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_ne_bytes(bytes.try_into().ok()?))
+}
+
+/// An entry of the Multiboot2 memory map tag.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Multiboot2MemoryEntry {
+    pub base: u64,
+    pub length: u64,
+    pub kind: u32,
+}
+
+impl Multiboot2MemoryEntry {
+    /// RAM available to the operating system.
+    pub const AVAILABLE: u32 = 1;
+    /// Reserved by the firmware or a device.
+    pub const RESERVED: u32 = 2;
+    /// ACPI tables, usable once they have been read.
+    pub const ACPI_RECLAIMABLE: u32 = 3;
+    /// Memory to preserve across hibernation.
+    pub const NVS: u32 = 4;
+    /// RAM found to be faulty.
+    pub const DEFECTIVE: u32 = 5;
+}
+
+/// The boot information structure a Multiboot2 loader passes in `ebx`.
+#[derive(Clone, Copy, Debug)]
+pub struct Multiboot2Info<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Multiboot2Info<'a> {
+    /// Wrap a boot information structure, or return `None` if its total size does not
+    /// fit in `bytes`.
+    pub fn new(bytes: &'a [u8]) -> Option<Self> {
+        let total = u32_at(bytes, 0)? as usize;
+        Some(Multiboot2Info {
+            bytes: bytes.get(..total).filter(|b| b.len() >= 8)?,
+        })
+    }
+    /// Wrap the boot information structure at `addr`.
+    ///
+    /// # Safety
+    ///
+    /// `addr` must point to a boot information structure that stays valid and unchanged
+    /// for `'a`.
+    pub unsafe fn from_ptr(addr: *const u8) -> Option<Self> {
+        let total = (addr as *const u32).read_unaligned() as usize;
+        Multiboot2Info::new(core::slice::from_raw_parts(addr, total))
+    }
+    /// Iterate over the `(type, body)` of each tag, the body following the tag header.
+    fn tags(&self) -> impl Iterator<Item = (u32, &'a [u8])> + 'a {
+        let bytes = self.bytes;
+        let mut offset = 8;
+        core::iter::from_fn(move || {
+            let kind = u32_at(bytes, offset)?;
+            let size = u32_at(bytes, offset + 4)? as usize;
+            let body = bytes.get(offset + 8..offset.checked_add(size)?)?;
+            if kind == TAG_END {
+                return None;
+            }
+            // Tags are padded to 8 bytes
+            offset = offset.checked_add(size)?.checked_add(7)? & !7;
+            Some((kind, body))
+        })
+    }
+    /// Iterate over the memory map entries, if the loader provided a memory map.
+    pub fn memory_map(&self) -> impl Iterator<Item = Multiboot2MemoryEntry> + Clone + 'a {
+        let tag = self.tags().find(|&(kind, _)| kind == TAG_MEMORY_MAP);
+        let (entry_size, entries) = match tag {
+            Some((_, body)) => (u32_at(body, 0).unwrap_or(0) as usize, body.get(8..)),
+            None => (0, None),
+        };
+        // Entries are at least base, length and type
+        let entry_size = Some(entry_size).filter(|&s| s >= 20).unwrap_or(usize::MAX);
+        entries.unwrap_or(&[]).chunks(entry_size).filter_map(|e| {
+            Some(Multiboot2MemoryEntry {
+                base: u64_at(e, 0)?,
+                length: u64_at(e, 8)?,
+                kind: u32_at(e, 16)?,
+            })
+        })
+    }
+    /// Iterate over the `(start, end)` physical ranges of the loaded modules.
+    pub fn modules(&self) -> impl Iterator<Item = (u32, u32)> + 'a {
+        self.tags()
+            .filter(|&(kind, _)| kind == TAG_MODULE)
+            .filter_map(|(_, body)| Some((u32_at(body, 0)?, u32_at(body, 4)?)))
+    }
+    /// Iterate over the `(address, size)` of the kernel ELF sections that occupy memory,
+    /// at the addresses the kernel was linked for.
+    pub fn elf_sections(&self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        let tag = self.tags().find(|&(kind, _)| kind == TAG_ELF_SECTIONS);
+        let (count, entsize, headers) = match tag {
+            Some((_, body)) => (
+                u32_at(body, 0).unwrap_or(0) as usize,
+                u32_at(body, 4).unwrap_or(0) as usize,
+                body.get(12..).unwrap_or(&[]),
+            ),
+            None => (0, 0, &[][..]),
+        };
+        // ELF64 section headers take 64 bytes and ELF32 ones 40
+        let elf64 = entsize >= 64;
+        let headers = match entsize {
+            0 => None,
+            _ => Some(headers.chunks_exact(entsize).take(count)),
+        };
+        headers.into_iter().flatten().filter_map(move |h| {
+            let (flags, addr, size) = match elf64 {
+                true => (u64_at(h, 8)?, u64_at(h, 16)?, u64_at(h, 32)?),
+                false => (
+                    u32_at(h, 8)?.into(),
+                    u32_at(h, 12)?.into(),
+                    u32_at(h, 20)?.into(),
+                ),
+            };
+            Some((addr, size)).filter(|_| flags & SHF_ALLOC != 0)
+        })
+    }
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Add the RAM a Multiboot2 memory map describes as available.
+    ///
+    /// Available entries are added first and every other entry is then subtracted, so where
+    /// entries overlap the reserved one wins.
+    pub fn extend_multiboot2(&mut self, info: &Multiboot2Info) -> Result<(), RegionError> {
+        let entries = info
+            .memory_map()
+            .map(|e| (e.base, e.length, e.kind == Multiboot2MemoryEntry::AVAILABLE));
+        self.extend_usable(entries)
+    }
+    /// Subtract the memory holding the loaded modules, the kernel ELF sections, and the
+    /// boot information structure itself at `info_addr`.
+    ///
+    /// Sections are subtracted at their link address minus `elf_offset`, which for a
+    /// kernel linked to run in the higher half is how far above its physical load
+    /// address it was linked.
+    pub fn subtract_multiboot2_images(
+        &mut self,
+        info: &Multiboot2Info,
+        info_addr: usize,
+        elf_offset: u64,
+    ) -> Result<(), RegionError> {
+        let modules = info
+            .modules()
+            .map(|(start, end)| (u64::from(start), u64::from(end.saturating_sub(start))));
+        let sections = info
+            .elf_sections()
+            .map(|(addr, size)| (addr.wrapping_sub(elf_offset), size));
+        let structure = (info_addr as u64, info.bytes.len() as u64);
+        self.subtract_ranges(modules.chain(sections).chain(Some(structure)))
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::{Multiboot2Info, Multiboot2MemoryEntry as E};
+    use crate::RegionAllocator;
+    use alloc::vec::Vec;
+
+    fn tag(info: &mut Vec<u8>, kind: u32, body: &[u8]) {
+        info.extend_from_slice(&kind.to_ne_bytes());
+        info.extend_from_slice(&(8 + body.len() as u32).to_ne_bytes());
+        info.extend_from_slice(body);
+        while !info.len().is_multiple_of(8) {
+            info.push(0);
+        }
+    }
+
+    fn words(words: &[u64], width: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for w in words {
+            bytes.extend_from_slice(&w.to_ne_bytes()[..width]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn multiboot2_test() {
+        let mut info = Vec::from([0; 8]);
+        // A command line tag to skip, with padding
+        tag(&mut info, 1, b"quiet\0");
+        let mut mmap = words(&[24, 0], 4);
+        for &(base, length, kind) in &[
+            (0, 0x9fc00, E::AVAILABLE),
+            (0x9fc00, 0x400, E::RESERVED),
+            (0x100000, 0x7f00000, E::AVAILABLE),
+            (0x7ff0000, 0x10000, E::ACPI_RECLAIMABLE),
+        ] {
+            mmap.extend(words(&[base, length], 8));
+            mmap.extend(words(&[kind.into(), 0], 4));
+        }
+        tag(&mut info, 6, &mmap);
+        let mut module = words(&[0x400000, 0x480000], 4);
+        module.extend_from_slice(b"initrd\0");
+        tag(&mut info, 3, &module);
+        // Two ELF64 section headers: .text allocated at a higher-half address, .comment not
+        let mut sections = words(&[2, 64, 0], 4);
+        sections.extend(words(
+            &[0, 2 | 4, 0xffff_8000_0020_0000, 0, 0x10_0000, 0, 0, 0],
+            8,
+        ));
+        sections.extend(words(&[0, 0, 0, 0, 0x100, 0, 0, 0], 8));
+        tag(&mut info, 9, &sections);
+        tag(&mut info, 0, &[]);
+        let total = info.len() as u32;
+        info[..4].copy_from_slice(&total.to_ne_bytes());
+
+        let info = Multiboot2Info::new(&info).unwrap();
+        assert_eq!(info.memory_map().count(), 4);
+        assert_eq!(info.modules().collect::<Vec<_>>(), [(0x400000, 0x480000)]);
+        let mut ram = RegionAllocator::new();
+        ram.extend_multiboot2(&info).unwrap();
+        assert!(ram.check_region(0, 0x9fc00));
+        assert!(ram.check_region(0x100000, 0x7ef0000));
+        ram.subtract_multiboot2_images(&info, 0x8000, 0xffff_8000_0000_0000)
+            .unwrap();
+        assert!(ram.check_region(0, 0x8000));
+        assert!(ram.check_region(0x8000 + total as usize, 0x9fc00 - 0x8000 - total as usize));
+        assert!(ram.check_region(0x100000, 0x100000));
+        assert!(ram.check_region(0x300000, 0x100000));
+        assert!(ram.check_region(0x480000, 0x7b70000));
+        // Case 2: truncated structures
+        assert!(Multiboot2Info::new(&[16, 0, 0, 0, 0, 0, 0, 0]).is_none());
+        assert_eq!(
+            Multiboot2Info::new(&[8, 0, 0, 0, 0, 0, 0, 0])
+                .unwrap()
+                .memory_map()
+                .count(),
+            0
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! The ACPI System Resource Affinity Table.
+
+use crate::{RegionAllocator, RegionError, RegionStorage};
+#[cfg(feature = "alloc")]
+use alloc::collections::BTreeMap;
+use core::convert::TryInto;
+
+/// The size of the table header, up to the first affinity structure.
+const HEADER: usize = 48;
+const MEMORY_AFFINITY: u8 = 1;
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// A memory affinity structure, associating a range with a proximity domain.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SratMemoryAffinity {
+    pub domain: u32,
+    pub base: u64,
+    pub length: u64,
+    pub flags: u32,
+}
+
+impl SratMemoryAffinity {
+    /// The entry is in use; firmware lists unpopulated slots with this clear.
+    pub const ENABLED: u32 = 1 << 0;
+    /// The range may be added or removed at run time.
+    pub const HOT_PLUGGABLE: u32 = 1 << 1;
+    /// The range is non-volatile memory.
+    pub const NON_VOLATILE: u32 = 1 << 2;
+
+    pub const fn is_enabled(&self) -> bool {
+        self.flags & Self::ENABLED != 0
+    }
+    pub const fn is_hot_pluggable(&self) -> bool {
+        self.flags & Self::HOT_PLUGGABLE != 0
+    }
+}
+
+/// A System Resource Affinity Table, as found through the RSDT or XSDT.
+#[derive(Clone, Copy, Debug)]
+pub struct Srat<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Srat<'a> {
+    /// Wrap a table, or return `None` if it is not an SRAT or its length does not fit in
+    /// `bytes`.
+    pub fn new(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.get(..4)? != b"SRAT" {
+            return None;
+        }
+        let length = u32_at(bytes, 4)? as usize;
+        Some(Srat {
+            bytes: bytes.get(..length).filter(|b| b.len() >= HEADER)?,
+        })
+    }
+    /// Wrap the table at `addr`.
+    ///
+    /// # Safety
+    ///
+    /// `addr` must point to an ACPI table that stays valid and unchanged for `'a`.
+    pub unsafe fn from_ptr(addr: *const u8) -> Option<Self> {
+        let length = u32::from_le((addr.add(4) as *const u32).read_unaligned()) as usize;
+        Srat::new(core::slice::from_raw_parts(addr, length))
+    }
+    /// Iterate over the memory affinity structures, enabled or not, skipping the processor
+    /// and other affinity structures.
+    pub fn memory_affinity(&self) -> impl Iterator<Item = SratMemoryAffinity> + Clone + 'a {
+        let bytes = self.bytes;
+        let mut offset = HEADER;
+        core::iter::from_fn(move || loop {
+            let (kind, length) = (*bytes.get(offset)?, *bytes.get(offset + 1)? as usize);
+            let entry = bytes.get(offset..offset + length).filter(|_| length >= 2)?;
+            offset += length;
+            if kind != MEMORY_AFFINITY {
+                continue;
+            }
+            let u64_at =
+                |at| Some(u64::from(u32_at(entry, at)?) | u64::from(u32_at(entry, at + 4)?) << 32);
+            return Some(SratMemoryAffinity {
+                domain: u32_at(entry, 2)?,
+                base: u64_at(8)?,
+                length: u64_at(16)?,
+                flags: u32_at(entry, 28)?,
+            });
+        })
+    }
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Add the enabled memory affinity entries of proximity domain `domain`.
+    ///
+    /// `entries` come from [`Srat::memory_affinity`], or already parsed from elsewhere.
+    /// Enabled entries are added whether hot-pluggable or not, since the firmware only
+    /// enables the ranges that are present.
+    pub fn extend_srat_domain(
+        &mut self,
+        entries: impl IntoIterator<Item = SratMemoryAffinity>,
+        domain: u32,
+    ) -> Result<(), RegionError> {
+        let entries = entries.into_iter();
+        let entries = entries.filter(|e| e.is_enabled() && e.domain == domain);
+        self.extend_ranges(entries.map(|e| (e.base, e.length)))
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl RegionAllocator {
+    /// Build a region set for each proximity domain from the enabled memory affinity
+    /// entries, keyed by domain.
+    pub fn srat_domains(
+        entries: impl IntoIterator<Item = SratMemoryAffinity>,
+    ) -> Result<BTreeMap<u32, Self>, RegionError> {
+        let mut domains = BTreeMap::new();
+        for e in entries.into_iter().filter(|e| e.is_enabled()) {
+            let regions: &mut Self = domains.entry(e.domain).or_default();
+            regions.extend_ranges(Some((e.base, e.length)).into_iter())?;
+        }
+        Ok(domains)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::{Srat, SratMemoryAffinity as M};
+    use crate::RegionAllocator;
+    use alloc::vec::Vec;
+
+    fn memory(table: &mut Vec<u8>, domain: u32, base: u64, length: u64, flags: u32) {
+        table.extend_from_slice(&[1, 40]);
+        table.extend_from_slice(&domain.to_le_bytes());
+        table.extend_from_slice(&[0; 2]);
+        table.extend_from_slice(&base.to_le_bytes());
+        table.extend_from_slice(&length.to_le_bytes());
+        table.extend_from_slice(&[0; 4]);
+        table.extend_from_slice(&flags.to_le_bytes());
+        table.extend_from_slice(&[0; 8]);
+    }
+
+    #[test]
+    fn srat_test() {
+        let mut table = Vec::from(*b"SRAT");
+        table.resize(48, 0);
+        // A processor affinity structure to skip
+        table.extend_from_slice(&[0, 16]);
+        table.extend_from_slice(&[0; 14]);
+        memory(&mut table, 0, 0, 0xa0000, M::ENABLED);
+        memory(&mut table, 0, 0x100000, 0x7ff00000, M::ENABLED);
+        memory(&mut table, 1, 0x80000000, 0x40000000, M::ENABLED);
+        memory(
+            &mut table,
+            1,
+            0xc0000000,
+            0x10000000,
+            M::ENABLED | M::HOT_PLUGGABLE,
+        );
+        // An empty hot-plug slot
+        memory(&mut table, 2, 0xd0000000, 0x10000000, M::HOT_PLUGGABLE);
+        let length = table.len() as u32;
+        table[4..8].copy_from_slice(&length.to_le_bytes());
+
+        let srat = Srat::new(&table).unwrap();
+        assert_eq!(srat.memory_affinity().count(), 5);
+        let mut node0 = RegionAllocator::new();
+        node0.extend_srat_domain(srat.memory_affinity(), 0).unwrap();
+        assert_eq!(node0.len(), 2);
+        assert!(node0.check_region(0x100000, 0x7ff00000));
+        // Case 2: one set per domain, leaving the disabled slot out
+        let domains = RegionAllocator::srat_domains(srat.memory_affinity()).unwrap();
+        assert_eq!(domains.keys().copied().collect::<Vec<_>>(), [0, 1]);
+        assert!(domains[&0] == node0);
+        assert!(domains[&1].check_region(0x80000000, 0x50000000));
+        assert!(Srat::new(&table[..table.len() - 1]).is_none());
+        assert!(Srat::new(b"APIC").is_none());
+    }
+}
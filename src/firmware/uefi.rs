@@ -0,0 +1,155 @@
+//! The UEFI memory map returned by `GetMemoryMap`.
+
+use crate::{RegionAllocator, RegionError, RegionStorage};
+use core::mem::size_of;
+use core::ptr;
+
+/// Size of the pages counted by [`EfiMemoryDescriptor::number_of_pages`].
+const EFI_PAGE_SIZE: u64 = 4096;
+
+/// An `EFI_MEMORY_DESCRIPTOR`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EfiMemoryDescriptor {
+    pub kind: u32,
+    pub physical_start: u64,
+    pub virtual_start: u64,
+    pub number_of_pages: u64,
+    pub attribute: u64,
+}
+
+impl EfiMemoryDescriptor {
+    pub const RESERVED: u32 = 0;
+    pub const LOADER_CODE: u32 = 1;
+    pub const LOADER_DATA: u32 = 2;
+    pub const BOOT_SERVICES_CODE: u32 = 3;
+    pub const BOOT_SERVICES_DATA: u32 = 4;
+    pub const RUNTIME_SERVICES_CODE: u32 = 5;
+    pub const RUNTIME_SERVICES_DATA: u32 = 6;
+    pub const CONVENTIONAL: u32 = 7;
+    pub const UNUSABLE: u32 = 8;
+    pub const ACPI_RECLAIM: u32 = 9;
+    pub const ACPI_NVS: u32 = 10;
+    pub const MMIO: u32 = 11;
+    pub const MMIO_PORT_SPACE: u32 = 12;
+    pub const PAL_CODE: u32 = 13;
+    pub const PERSISTENT: u32 = 14;
+    pub const UNACCEPTED: u32 = 15;
+
+    /// Check whether memory of type `kind` is free RAM once boot services have exited.
+    ///
+    /// Loader code and data are not, as they hold the loaded kernel and what its
+    /// loader handed over.
+    pub fn is_usable(kind: u32) -> bool {
+        matches!(
+            kind,
+            Self::BOOT_SERVICES_CODE | Self::BOOT_SERVICES_DATA | Self::CONVENTIONAL
+        )
+    }
+    fn range(&self) -> (u64, u64) {
+        let length = self.number_of_pages.saturating_mul(EFI_PAGE_SIZE);
+        (self.physical_start, length)
+    }
+}
+
+/// A memory map buffer as filled by `GetMemoryMap`, whose descriptors are
+/// `descriptor_size` bytes apart rather than `size_of::<EfiMemoryDescriptor>()`.
+#[derive(Clone, Copy, Debug)]
+pub struct UefiMemoryMap<'a> {
+    buffer: &'a [u8],
+    descriptor_size: usize,
+}
+
+impl<'a> UefiMemoryMap<'a> {
+    /// Wrap the first `map_size` bytes of the buffer, or `None` if `descriptor_size` is
+    /// smaller than a descriptor or `map_size` larger than the buffer.
+    pub fn new(buffer: &'a [u8], map_size: usize, descriptor_size: usize) -> Option<Self> {
+        if descriptor_size < size_of::<EfiMemoryDescriptor>() {
+            return None;
+        }
+        Some(UefiMemoryMap {
+            buffer: buffer.get(..map_size)?,
+            descriptor_size,
+        })
+    }
+    /// Iterate over the descriptors.
+    pub fn iter(&self) -> impl Iterator<Item = EfiMemoryDescriptor> + Clone + 'a {
+        self.buffer
+            .chunks_exact(self.descriptor_size)
+            // SAFETY: each chunk holds at least one descriptor, which is plain data.
+            .map(|d| unsafe { ptr::read_unaligned(d.as_ptr() as *const EfiMemoryDescriptor) })
+    }
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Add the RAM a UEFI memory map describes as usable after `ExitBootServices`.
+    ///
+    /// Usable descriptors are added first and every other one is then subtracted, so where
+    /// descriptors overlap the reserved one wins. See [`EfiMemoryDescriptor::is_usable`].
+    pub fn extend_uefi(&mut self, map: &UefiMemoryMap) -> Result<(), RegionError> {
+        let entries = map.iter().map(|d| {
+            let (base, length) = d.range();
+            (base, length, EfiMemoryDescriptor::is_usable(d.kind))
+        });
+        self.extend_usable(entries)
+    }
+    /// Add the descriptors of a UEFI memory map whose type satisfies `f`, such as the
+    /// runtime services ones that must stay mapped.
+    pub fn extend_uefi_if(
+        &mut self,
+        map: &UefiMemoryMap,
+        mut f: impl FnMut(u32) -> bool,
+    ) -> Result<(), RegionError> {
+        let descriptors = map.iter().filter(|d| f(d.kind));
+        self.extend_ranges(descriptors.map(|d| d.range()))
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::{EfiMemoryDescriptor, UefiMemoryMap};
+    use crate::RegionAllocator;
+    use alloc::vec::Vec;
+    use core::mem::size_of;
+
+    #[test]
+    fn uefi_test() {
+        type D = EfiMemoryDescriptor;
+        let descriptors = [
+            (D::CONVENTIONAL, 0, 0xa0),
+            (D::RESERVED, 0xa0000, 0x60),
+            (D::LOADER_DATA, 0x100000, 0x100),
+            (D::BOOT_SERVICES_DATA, 0x200000, 0x100),
+            (D::CONVENTIONAL, 0x300000, 0x1000),
+            (D::RUNTIME_SERVICES_DATA, 0x1300000, 0x10),
+        ];
+        // Firmware may pad descriptors beyond the structure
+        let stride = size_of::<D>() + 8;
+        let mut buffer = Vec::new();
+        for &(kind, physical_start, number_of_pages) in &descriptors {
+            buffer.extend_from_slice(&kind.to_ne_bytes());
+            buffer.extend_from_slice(&[0; 4]);
+            buffer.extend_from_slice(&u64::to_ne_bytes(physical_start));
+            buffer.extend_from_slice(&[0; 8]);
+            buffer.extend_from_slice(&u64::to_ne_bytes(number_of_pages));
+            buffer.extend_from_slice(&[0; 8]);
+            buffer.extend_from_slice(&[0xff; 8]);
+        }
+        buffer.extend_from_slice(&[0; 64]);
+        let map = UefiMemoryMap::new(&buffer, descriptors.len() * stride, stride).unwrap();
+        assert_eq!(map.iter().count(), 6);
+        let mut ram = RegionAllocator::new();
+        ram.extend_uefi(&map).unwrap();
+        assert!(ram.check_region(0, 0xa0000));
+        assert!(ram.check_region(0x200000, 0x1100000));
+        assert_eq!(ram.len(), 2);
+        let mut runtime = RegionAllocator::new();
+        runtime
+            .extend_uefi_if(&map, |kind| kind == D::RUNTIME_SERVICES_DATA)
+            .unwrap();
+        assert!(runtime.check_region(0x1300000, 0x10000));
+        // Case 2: bad geometry
+        assert!(UefiMemoryMap::new(&buffer, buffer.len(), 16).is_none());
+        assert!(UefiMemoryMap::new(&buffer, buffer.len() + 1, stride).is_none());
+    }
+}
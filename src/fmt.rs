@@ -0,0 +1,123 @@
+//! Human-readable listings of a region set.
+
+use crate::{Region, RegionAllocator, RegionStorage};
+use core::fmt;
+
+/// Lists one region per line as `start-end : size`, like `/proc/iomem`, with inclusive
+/// ends in hexadecimal and sizes in bytes.
+///
+/// The width pads addresses with zeros to that many digits, 8 by default, and the
+/// alternate flag prints sizes in hexadecimal too: `{:16}` or `{:#}`.
+impl<S: RegionStorage> fmt::Display for RegionAllocator<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = f.width().unwrap_or(8);
+        for (i, r) in self.regions.range(..).enumerate() {
+            if i != 0 {
+                f.write_str("\n")?;
+            }
+            write!(f, "{:0w$x}-{:0w$x} : ", r.base, r.end() - 1, w = width)?;
+            match f.alternate() {
+                true => write!(f, "{:#x}", r.size)?,
+                false => write!(f, "{}", r.size)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lists the regions as `0xBASE..0xEND (size)`, with sizes in the largest binary unit
+/// dividing them; `{:#?}` puts each region on its own line.
+impl<S: RegionStorage> fmt::Debug for RegionAllocator<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("RegionAllocator ")?;
+        f.debug_list()
+            .entries(self.regions.range(..).map(Entry))
+            .finish()
+    }
+}
+
+/// Lists the regions as `0xBASE..0xEND`, leaving the formatting to the host.
+#[cfg(feature = "defmt")]
+impl<S: RegionStorage> defmt::Format for RegionAllocator<S> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "RegionAllocator [");
+        for (i, r) in self.regions.range(..).enumerate() {
+            if i != 0 {
+                defmt::write!(f, ", ");
+            }
+            defmt::write!(f, "{=usize:#x}..{=usize:#x}", r.base, r.end());
+        }
+        defmt::write!(f, "]");
+    }
+}
+
+struct Entry(Region);
+
+impl fmt::Debug for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Region { base, size } = self.0;
+        write!(f, "{:#x}..{:#x} ({})", base, base + size, Size(size))
+    }
+}
+
+/// Formats a byte count in the largest binary unit that divides it exactly.
+struct Size(usize);
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+        let shift = match self.0 {
+            0 => 0,
+            size => (size.trailing_zeros() / 10).min(UNITS.len() as u32 - 1),
+        };
+        write!(f, "{} {}", self.0 >> (shift * 10), UNITS[shift as usize])
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use crate::RegionAllocator;
+    use alloc::format;
+
+    #[test]
+    fn display_test() {
+        let mut alloc = RegionAllocator::new();
+        assert_eq!(format!("{}", alloc), "");
+        alloc.add(0, 0x9fc00);
+        alloc.add(0x10_0000, 0x7ff0_0000);
+        assert_eq!(
+            format!("{}", alloc),
+            "00000000-0009fbff : 654336\n00100000-7fffffff : 2146435072"
+        );
+        assert_eq!(
+            format!("{:12}", alloc),
+            "000000000000-00000009fbff : 654336\n000000100000-00007fffffff : 2146435072"
+        );
+        assert_eq!(
+            format!("{:#}", alloc),
+            "00000000-0009fbff : 0x9fc00\n00100000-7fffffff : 0x7ff00000"
+        );
+    }
+
+    #[test]
+    fn debug_test() {
+        let mut alloc = RegionAllocator::new();
+        assert_eq!(format!("{:?}", alloc), "RegionAllocator []");
+        alloc.add(0, 0x9fc00);
+        alloc.add(0x10_0000, 0x7ff0_0000);
+        alloc.add(0x9000_0000, 0x1001);
+        assert_eq!(
+            format!("{:?}", alloc),
+            "RegionAllocator [0x0..0x9fc00 (639 KiB), 0x100000..0x80000000 (2047 MiB), \
+             0x90000000..0x90001001 (4097 B)]"
+        );
+        assert_eq!(
+            format!("{:#?}", alloc),
+            "RegionAllocator [
+    0x0..0x9fc00 (639 KiB),
+    0x100000..0x80000000 (2047 MiB),
+    0x90000000..0x90001001 (4097 B),
+]"
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! Immutable copies of a region set, shared without copying again.
+
+use crate::{CapacityError, FrozenStorage, RegionAllocator, RegionStorage};
+use core::ops::Deref;
+
+/// An immutable copy of a region set, made by [`RegionAllocator::freeze`].
+///
+/// It dereferences to a [`RegionAllocator`] over a [`FrozenStorage`], so every query of
+/// the set works on it, with the settings of the set it was frozen from. The regions
+/// are copied once and then shared, so clones handed to other subsystems, or to a
+/// crash-dump writer, cost a reference count each and always agree.
+#[derive(Clone, Debug)]
+pub struct FrozenRegionSet {
+    set: RegionAllocator<FrozenStorage>,
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Return an immutable copy of the set, along with its settings and counters.
+    pub fn freeze(&self) -> FrozenRegionSet {
+        let set = RegionAllocator {
+            regions: FrozenStorage::new(self.regions.range(..)),
+            cursor: self.cursor,
+            endpoints: self.endpoints,
+            zero_size: self.zero_size,
+            granule: self.granule,
+            preserve: self.preserve,
+            fit_policy: self.fit_policy,
+            region_limit: self.region_limit,
+            counters: self.counters,
+            observer: None,
+        };
+        FrozenRegionSet { set }
+    }
+}
+
+impl FrozenRegionSet {
+    /// Copy the set into a new storage that can change again.
+    pub fn thaw<T: RegionStorage + Default>(&self) -> Result<RegionAllocator<T>, CapacityError> {
+        self.set.clone().into_storage(T::default())
+    }
+}
+
+impl Deref for FrozenRegionSet {
+    type Target = RegionAllocator<FrozenStorage>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ArrayStorage, Endpoints, RegionAllocator};
+
+    #[test]
+    fn freeze_test() {
+        let mut regions = RegionAllocator::new().with_endpoints(Endpoints::Closed);
+        regions.add(0x1000, 0x1000);
+        regions.add(0x4000, 0x2000);
+        let frozen = regions.freeze();
+        regions.subtract(0, 0x10000);
+        // Case 1: queries see the set as it was frozen, with its settings
+        assert_eq!(frozen.len(), 2);
+        assert!(frozen.check_region(0x4000, 0x2000));
+        assert!(frozen.check_point(0x2000));
+        assert_eq!(frozen.stats().free_bytes, 0x3000);
+        // Case 2: clones share the regions
+        let shared = frozen.clone();
+        assert_eq!(
+            shared.regions.regions().as_ptr(),
+            frozen.regions.regions().as_ptr()
+        );
+        // Case 3: thawing gives back a set that can change
+        let mut thawed = frozen.thaw::<ArrayStorage<4>>().unwrap();
+        assert!(thawed == *frozen);
+        thawed.subtract(0x4000, 0x1000);
+        assert_eq!(thawed.len(), 2);
+        assert_eq!(frozen.len(), 2);
+    }
+}
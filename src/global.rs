@@ -0,0 +1,208 @@
+//! A `#[global_allocator]` handing out heap memory from a region set.
+
+use crate::{HeapFreeStorage, LockedRegionAllocator, RegionAllocator};
+#[cfg(feature = "nightly")]
+use core::alloc::{AllocError, Allocator};
+use core::alloc::{GlobalAlloc, Layout};
+#[cfg(feature = "nightly")]
+use core::ptr::NonNull;
+use core::ptr::{self, null_mut};
+
+/// A [`GlobalAlloc`] serving allocations from the regions of a [`RegionAllocator`]
+/// behind a spinlock.
+///
+/// The storage must not allocate itself, hence the [`HeapFreeStorage`] bound. No size is
+/// kept next to allocations: `dealloc` and `realloc` are given the layout the memory was
+/// allocated with, whose size is exactly what goes back into the set. Memory freed while
+/// the storage is out of capacity is leaked rather than panicking inside the allocator.
+///
+/// With the `log` or `tracing` feature, events are emitted from inside `alloc` and
+/// `dealloc` with the lock held, as [`LockedRegionAllocator`] does. The logger or
+/// subscriber must then not use the global heap: it would spin on the lock, and even
+/// without the lock it would recurse into an allocation that is already failing.
+pub struct GlobalRegionAllocator<S> {
+    regions: LockedRegionAllocator<S>,
+}
+
+impl<S: HeapFreeStorage> GlobalRegionAllocator<S> {
+    /// Serve allocations from `regions`, which holds the heap memory.
+    ///
+    /// Pointers are rebuilt from the addresses in the set, so the heap memory must have
+    /// its provenance exposed, as casting a pointer to it with `as usize` does.
+    pub const fn new(regions: RegionAllocator<S>) -> Self {
+        GlobalRegionAllocator {
+            regions: LockedRegionAllocator::new(regions),
+        }
+    }
+    /// Return the locked set, such as to add heap memory once it is mapped.
+    pub fn regions(&self) -> &LockedRegionAllocator<S> {
+        &self.regions
+    }
+}
+
+unsafe impl<S: HeapFreeStorage + Send> GlobalAlloc for GlobalRegionAllocator<S> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match self.regions.allocate_by_size(layout.size(), layout.align()) {
+            Ok((base, _)) => base as *mut u8,
+            Err(_) => null_mut(),
+        }
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _ = self.regions.deallocate(ptr as usize, layout.size());
+    }
+    /// Shrink in place by giving back the tail, and grow in place if the bytes after the
+    /// allocation are free, moving it only when they are not.
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let (base, old) = (ptr as usize, layout.size());
+        let in_place = match new_size <= old {
+            true => self.regions.deallocate(base + new_size, old - new_size),
+            false => match base.checked_add(old) {
+                Some(end) => self.regions.allocate_by_addr(end, new_size - old),
+                None => return null_mut(),
+            },
+        };
+        if in_place.is_ok() {
+            return ptr;
+        }
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new = self.alloc(new_layout);
+        if !new.is_null() {
+            ptr::copy_nonoverlapping(ptr, new, old.min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new
+    }
+}
+
+/// Place collections in the regions with `Vec::new_in(&allocator)` and the like.
+///
+/// Zero-sized blocks are dangling pointers that never touch the set. Growing and shrinking
+/// happen in place when the block is aligned for the new layout and, to grow, the bytes
+/// after it are free; otherwise the block moves.
+#[cfg(feature = "nightly")]
+unsafe impl<S: HeapFreeStorage + Send> Allocator for GlobalRegionAllocator<S> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = match layout.size() {
+            0 => ptr::without_provenance_mut(layout.align()),
+            size => {
+                let regions = self.regions.allocate_by_size(size, layout.align());
+                // The heap memory was exposed by whoever added it to the set
+                ptr::with_exposed_provenance_mut(regions.map_err(|_| AllocError)?.0)
+            }
+        };
+        let ptr = NonNull::new(ptr).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            self.dealloc(ptr.as_ptr(), layout);
+        }
+    }
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old: Layout,
+        new: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let (base, end) = (ptr.as_ptr() as usize, ptr.as_ptr() as usize + old.size());
+        if old.size() != 0
+            && base.is_multiple_of(new.align())
+            && self
+                .regions
+                .allocate_by_addr(end, new.size() - old.size())
+                .is_ok()
+        {
+            return Ok(NonNull::slice_from_raw_parts(ptr, new.size()));
+        }
+        let moved = self.allocate(new)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(), moved.as_ptr() as *mut u8, old.size());
+        self.deallocate(ptr, old);
+        Ok(moved)
+    }
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old: Layout,
+        new: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let base = ptr.as_ptr() as usize;
+        if new.size() != 0 && base.is_multiple_of(new.align()) {
+            // Without room to free the tail, the block keeps it
+            let tail = self
+                .regions
+                .deallocate(base + new.size(), old.size() - new.size());
+            let size = if tail.is_ok() { new.size() } else { old.size() };
+            return Ok(NonNull::slice_from_raw_parts(ptr, size));
+        }
+        let moved = self.allocate(new)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(), moved.as_ptr() as *mut u8, new.size());
+        self.deallocate(ptr, old);
+        Ok(moved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GlobalRegionAllocator;
+    use crate::StaticRegionAllocator;
+    use core::alloc::{GlobalAlloc, Layout};
+
+    #[repr(align(64))]
+    struct Heap([u8; 0x400]);
+
+    #[test]
+    fn global_alloc_test() {
+        let mut heap = Heap([0; 0x400]);
+        let base = heap.0.as_mut_ptr() as usize;
+        let mut regions = StaticRegionAllocator::<4>::default();
+        regions.add(base, 0x400);
+        let global = GlobalRegionAllocator::new(regions);
+        let layout = Layout::from_size_align(0x100, 64).unwrap();
+        unsafe {
+            let a = global.alloc(layout);
+            let b = global.alloc(layout);
+            assert_eq!((a as usize, b as usize), (base, base + 0x100));
+            a.write_bytes(0xaa, 0x100);
+            // Case 1: shrinking and regrowing stay in place
+            assert_eq!(global.realloc(b, layout, 0x80), b);
+            let small = Layout::from_size_align(0x80, 64).unwrap();
+            assert_eq!(global.realloc(b, small, 0x180), b);
+            // Case 2: growing into used bytes moves the allocation
+            let moved = global.realloc(a, layout, 0x140);
+            assert_eq!(moved as usize, base + 0x280);
+            assert_eq!(*moved.add(0xff), 0xaa);
+            assert!(global.regions().check_region(base, 0x100));
+            let big = Layout::from_size_align(0x400, 64).unwrap();
+            assert!(global.alloc(big).is_null());
+            global.dealloc(moved, Layout::from_size_align(0x140, 64).unwrap());
+            global.dealloc(b, Layout::from_size_align(0x180, 64).unwrap());
+            assert!(global.regions().check_region(base, 0x400));
+        }
+    }
+
+    #[cfg(all(feature = "nightly", feature = "alloc"))]
+    #[test]
+    fn allocator_test() {
+        use alloc::vec::Vec;
+
+        let mut heap = Heap([0; 0x400]);
+        let base = heap.0.as_mut_ptr() as usize;
+        let mut regions = StaticRegionAllocator::<4>::default();
+        regions.add(base, 0x400);
+        let global = GlobalRegionAllocator::new(regions);
+        let mut v: Vec<u32, _> = Vec::with_capacity_in(16, &global);
+        v.extend(0..16);
+        assert_eq!(v.as_ptr() as usize, base);
+        // Growing stays in place while the bytes after are free
+        v.reserve_exact(48);
+        assert_eq!(v.as_ptr() as usize, base);
+        let w: Vec<u8, _> = Vec::with_capacity_in(8, &global);
+        assert_eq!(w.as_ptr() as usize, base + 0x100);
+        v.truncate(4);
+        v.shrink_to_fit();
+        assert!(global.regions().check_region(base + 0x10, 0xf0));
+        let empty: Vec<u64, _> = Vec::new_in(&global);
+        drop((v, w, empty));
+        assert!(global.regions().check_region(base, 0x400));
+    }
+}
@@ -0,0 +1,191 @@
+//! Memory hot-add and hot-remove, with the ranges still in use reported as blockers.
+
+use crate::{BTreeStorage, RegionAllocator, RegionError, RegionStorage};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+
+/// The reason a range could not be hot-removed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum HotRemoveError {
+    /// Parts of the range are allocated; these are they, in ascending order.
+    Busy(Vec<Range<usize>>),
+    /// Subtracting the range from the set failed.
+    Region(RegionError),
+}
+
+impl fmt::Display for HotRemoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HotRemoveError::Busy(blockers) => {
+                write!(f, "{} allocated ranges block the removal", blockers.len())
+            }
+            HotRemoveError::Region(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl core::error::Error for HotRemoveError {}
+
+impl From<RegionError> for HotRemoveError {
+    fn from(e: RegionError) -> Self {
+        HotRemoveError::Region(e)
+    }
+}
+
+/// A [`RegionAllocator`] whose memory can grow and shrink at run time, as DIMMs or
+/// virtio-mem blocks come and go.
+///
+/// Every hot-add opens a new epoch and tags the added range with it, so callers can tell
+/// memory that may go away again from memory present since boot, whose epoch is 0.
+#[derive(Clone, Debug)]
+pub struct HotplugRegionAllocator<S: RegionStorage = BTreeStorage> {
+    regions: RegionAllocator<S>,
+    /// Sizes and epochs of the hot-added ranges still present, by base.
+    sections: BTreeMap<usize, (usize, u64)>,
+    epoch: u64,
+}
+
+impl<S: RegionStorage> HotplugRegionAllocator<S> {
+    /// Wrap the set of memory present at boot.
+    pub fn new(regions: RegionAllocator<S>) -> Self {
+        HotplugRegionAllocator {
+            regions,
+            sections: BTreeMap::new(),
+            epoch: 0,
+        }
+    }
+    /// Return the set, to allocate from.
+    pub fn regions_mut(&mut self) -> &mut RegionAllocator<S> {
+        &mut self.regions
+    }
+    /// Return the set.
+    pub fn regions(&self) -> &RegionAllocator<S> {
+        &self.regions
+    }
+    /// Return the epoch of the latest hot-add.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+    /// Return the epoch `addr` was hot-added in, 0 if it was not.
+    pub fn epoch_of(&self, addr: usize) -> u64 {
+        match self.sections.range(..=addr).next_back() {
+            Some((&base, &(size, epoch))) if addr - base < size => epoch,
+            _ => 0,
+        }
+    }
+    /// Iterate over the hot-added ranges still present, with their epochs.
+    pub fn sections(&self) -> impl Iterator<Item = (Range<usize>, u64)> + '_ {
+        let sections = self.sections.iter();
+        sections.map(|(&base, &(size, epoch))| (base..base + size, epoch))
+    }
+    /// Add `size` bytes of new memory from `base`, returning the epoch it is tagged with.
+    ///
+    /// Fail as [`RegionAllocator::add_checked`] does if the range overlaps free memory.
+    pub fn hot_add(&mut self, base: usize, size: usize) -> Result<u64, RegionError> {
+        if size == 0 {
+            return Err(RegionError::EmptyRange);
+        }
+        self.regions.add_checked(base, size)?;
+        self.epoch += 1;
+        self.sections.insert(base, (size, self.epoch));
+        Ok(self.epoch)
+    }
+    /// Remove the `size` bytes from `base`, all of which must be free.
+    ///
+    /// Fail with [`HotRemoveError::Busy`] listing the allocated parts, which must be
+    /// migrated and freed before trying again. The set is left unchanged on failure.
+    pub fn hot_remove(&mut self, base: usize, size: usize) -> Result<(), HotRemoveError> {
+        let end = base.checked_add(size).ok_or(RegionError::Overflow)?;
+        let blockers = self.blockers(base, end);
+        if !blockers.is_empty() {
+            return Err(HotRemoveError::Busy(blockers));
+        }
+        self.regions.try_subtract(base, size)?;
+        self.untag(base, end);
+        Ok(())
+    }
+
+    /// Return the parts of `[base, end)` the set does not cover.
+    fn blockers(&self, base: usize, end: usize) -> Vec<Range<usize>> {
+        let mut blockers = Vec::new();
+        let mut addr = base;
+        while let Some(gap) = self.regions.first_gap(addr, end) {
+            blockers.push(gap.base..gap.end());
+            addr = gap.end();
+        }
+        blockers
+    }
+    /// Drop `[base, end)` from the hot-added ranges, trimming those it overlaps.
+    fn untag(&mut self, base: usize, end: usize) {
+        let first = match self.sections.range(..base).next_back() {
+            Some((&start, &(size, _))) if start + size > base => start,
+            _ => base,
+        };
+        let overlapping: Vec<_> = self
+            .sections
+            .range(first..end)
+            .map(|(&b, &s)| (b, s))
+            .collect();
+        for (start, (size, epoch)) in overlapping {
+            self.sections.remove(&start);
+            if start < base {
+                self.sections.insert(start, (base - start, epoch));
+            }
+            if start + size > end {
+                self.sections.insert(end, (start + size - end, epoch));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HotRemoveError, HotplugRegionAllocator};
+    use crate::{RegionAllocator, RegionError};
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn hotplug_test() {
+        let mut boot = RegionAllocator::new();
+        boot.add(0, 0x1000_0000);
+        let mut memory = HotplugRegionAllocator::new(boot);
+        // Case 1: hot-added ranges are tagged with a new epoch each
+        assert_eq!(memory.hot_add(0x1_0000_0000, 0x800_0000), Ok(1));
+        assert_eq!(memory.hot_add(0x2_0000_0000, 0x800_0000), Ok(2));
+        assert_eq!(
+            memory.hot_add(0x1_0000_0000, 0x1000),
+            Err(RegionError::Overlapping)
+        );
+        assert_eq!(
+            (memory.epoch_of(0x1000), memory.epoch_of(0x2_0000_1000)),
+            (0, 2)
+        );
+        // Case 2: allocated parts of a range block its removal
+        let regions = memory.regions_mut();
+        regions.allocate_by_addr(0x1_0000_1000, 0x1000).unwrap();
+        regions.allocate_by_addr(0x1_0400_0000, 0x2000).unwrap();
+        let blockers = vec![0x1_0000_1000..0x1_0000_2000, 0x1_0400_0000..0x1_0400_2000];
+        assert_eq!(
+            memory.hot_remove(0x1_0000_0000, 0x800_0000),
+            Err(HotRemoveError::Busy(blockers))
+        );
+        assert!(memory.regions().check_region(0x1_0000_0000, 0x1000));
+        // Case 3: once they are freed, the range goes away with its tag
+        memory.regions_mut().add(0x1_0000_1000, 0x1000);
+        memory.regions_mut().add(0x1_0400_0000, 0x2000);
+        assert_eq!(memory.hot_remove(0x1_0000_0000, 0x400_0000), Ok(()));
+        let sections: Vec<_> = memory.sections().collect();
+        assert_eq!(
+            sections,
+            [
+                (0x1_0400_0000..0x1_0800_0000, 1),
+                (0x2_0000_0000..0x2_0800_0000, 2)
+            ]
+        );
+        assert_eq!(memory.epoch_of(0x1_0000_0000), 0);
+        assert_eq!(memory.regions().len(), 3);
+    }
+}
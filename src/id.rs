@@ -0,0 +1,134 @@
+//! Integer IDs, such as ASIDs, IRQ numbers or descriptor slots, allocated from pools.
+
+#[cfg(feature = "alloc")]
+use crate::BTreeStorage;
+use crate::{RegionAllocator, RegionError, RegionStorage};
+use core::ops::Range;
+
+/// An allocator of integer IDs handing out the lowest free ID or run of IDs.
+///
+/// Free IDs are kept as ranges in a [`RegionAllocator`], so a pool of millions of IDs
+/// with few in use, or few free, takes a handful of regions.
+#[derive(Clone, Debug, Default)]
+pub struct IdAllocator<
+    #[cfg(feature = "alloc")] S: RegionStorage = BTreeStorage,
+    #[cfg(not(feature = "alloc"))] S: RegionStorage,
+> {
+    free: RegionAllocator<S>,
+}
+
+#[cfg(feature = "alloc")]
+impl IdAllocator {
+    /// Create an allocator with no pool, backed by a `BTreeStorage`.
+    pub const fn new() -> Self {
+        IdAllocator::with_storage(BTreeStorage::new())
+    }
+}
+
+impl<S: RegionStorage> IdAllocator<S> {
+    /// Create an allocator with no pool on top of a given storage, which is expected to
+    /// be empty.
+    pub const fn with_storage(storage: S) -> Self {
+        IdAllocator {
+            free: RegionAllocator::with_storage(storage),
+        }
+    }
+    /// Add the IDs in `ids` to the pools, failing with [`RegionError::Overlapping`] if
+    /// any of them is already free.
+    pub fn add_pool(&mut self, ids: Range<usize>) -> Result<(), RegionError> {
+        let count = ids
+            .end
+            .checked_sub(ids.start)
+            .ok_or(RegionError::Overflow)?;
+        self.free.add_checked(ids.start, count)
+    }
+    /// Return the free IDs as ranges.
+    pub fn free_ids(&self) -> &RegionAllocator<S> {
+        &self.free
+    }
+    /// Check whether `id` is in a pool and not allocated.
+    pub fn is_free(&self, id: usize) -> bool {
+        self.free.check_point(id)
+    }
+    /// Allocate the lowest free ID.
+    pub fn allocate(&mut self) -> Option<usize> {
+        self.allocate_in(0..usize::MAX)
+    }
+    /// Allocate the lowest free ID in `ids`.
+    pub fn allocate_in(&mut self, ids: Range<usize>) -> Option<usize> {
+        if ids.is_empty() {
+            return None;
+        }
+        let id = self
+            .free
+            .regions
+            .range(..ids.end)
+            .filter(|r| r.end() > ids.start)
+            .map(|r| r.base.max(ids.start))
+            .find(|&id| id < ids.end)?;
+        self.free.try_subtract(id, 1).ok()?;
+        Some(id)
+    }
+    /// Allocate the lowest run of `count` free IDs starting at a multiple of `alignment`,
+    /// returning its first ID.
+    pub fn allocate_range(&mut self, count: usize, alignment: usize) -> Result<usize, RegionError> {
+        if !alignment.is_power_of_two() {
+            return Err(RegionError::InvalidAlignment);
+        }
+        if count == 0 {
+            return Err(RegionError::EmptyRange);
+        }
+        let id = self
+            .free
+            .regions
+            .range(..)
+            .find_map(|r| r.fit(count, alignment - 1))
+            .ok_or(RegionError::NoFit)?;
+        self.free.try_subtract(id, count)?;
+        Ok(id)
+    }
+    /// Allocate a given ID, failing with [`RegionError::NotCovered`] if it is not free.
+    pub fn allocate_id(&mut self, id: usize) -> Result<(), RegionError> {
+        self.free.subtract_checked(id, 1)
+    }
+    /// Free an allocated ID, failing with [`RegionError::Overlapping`] if it is free.
+    pub fn free(&mut self, id: usize) -> Result<(), RegionError> {
+        self.free.add_checked(id, 1)
+    }
+    /// Free a run of `count` IDs from `id` returned by [`IdAllocator::allocate_range`].
+    pub fn free_range(&mut self, id: usize, count: usize) -> Result<(), RegionError> {
+        self.free.add_checked(id, count)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::IdAllocator;
+    use crate::RegionError;
+
+    #[test]
+    fn id_test() {
+        let mut ids = IdAllocator::new();
+        ids.add_pool(1..0x10000).unwrap();
+        assert_eq!(ids.add_pool(0x8000..0x20000), Err(RegionError::Overlapping));
+        // Case 1: the lowest free ID comes first, and freed IDs are reused
+        assert_eq!(ids.allocate(), Some(1));
+        assert_eq!(ids.allocate(), Some(2));
+        assert_eq!(ids.allocate(), Some(3));
+        assert_eq!(ids.free(2), Ok(()));
+        assert_eq!(ids.free(2), Err(RegionError::Overlapping));
+        assert_eq!(ids.allocate(), Some(2));
+        // Case 2: ranges, aligned runs and explicit IDs
+        assert_eq!(ids.allocate_in(0x100..0x200), Some(0x100));
+        assert_eq!(ids.allocate_range(4, 8), Ok(8));
+        assert_eq!(ids.allocate_id(0x101), Ok(()));
+        assert_eq!(ids.allocate_id(0x101), Err(RegionError::NotCovered));
+        assert_eq!(ids.allocate_in(0x100..0x102), None);
+        assert_eq!(ids.allocate_in(0x200..0x200), None);
+        assert!(ids.is_free(4) && !ids.is_free(8) && ids.is_free(12));
+        assert_eq!(ids.free_range(8, 4), Ok(()));
+        // Case 3: sparse usage stays compact
+        assert_eq!(ids.free_ids().len(), 2);
+        assert_eq!(ids.allocate_range(0x10000, 1), Err(RegionError::NoFit));
+    }
+}
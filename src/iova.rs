@@ -0,0 +1,158 @@
+//! An I/O virtual address allocator caching freed ranges by size class.
+
+use crate::{RegionAllocator, RegionError, RegionStorage};
+
+/// Number of size classes cached, from one page to `1 << (SIZE_CLASSES - 1)` pages.
+pub const SIZE_CLASSES: usize = 6;
+
+/// An allocator of I/O virtual addresses keeping up to `N` freed ranges of each size
+/// class in front of a [`RegionAllocator`].
+///
+/// Sizes are rounded up to a power of 2 pages and ranges are aligned to their size, so a
+/// freed range can serve any later request of its class without touching the set. Sizes
+/// above the largest class go straight to the set. When a class is full, it is flushed
+/// back to the set at once; when the set has no room, every class is flushed and the
+/// allocation is retried.
+pub struct IovaAllocator<S: RegionStorage, const N: usize> {
+    regions: RegionAllocator<S>,
+    page_shift: u32,
+    caches: [[usize; N]; SIZE_CLASSES],
+    lens: [usize; SIZE_CLASSES],
+}
+
+impl<S: RegionStorage, const N: usize> IovaAllocator<S, N> {
+    /// Allocate addresses from `regions` in pages of `page_size` bytes.
+    ///
+    /// Return `None` if `page_size` is not a power of 2.
+    pub fn new(regions: RegionAllocator<S>, page_size: usize) -> Option<Self> {
+        if !page_size.is_power_of_two() {
+            return None;
+        }
+        Some(IovaAllocator {
+            regions,
+            page_shift: page_size.trailing_zeros(),
+            caches: [[0; N]; SIZE_CLASSES],
+            lens: [0; SIZE_CLASSES],
+        })
+    }
+    /// Return the free ranges not held by the caches.
+    pub fn regions(&self) -> &RegionAllocator<S> {
+        &self.regions
+    }
+    /// Return number of ranges cached across all size classes.
+    pub fn cached(&self) -> usize {
+        self.lens.iter().sum()
+    }
+    /// Flush the caches and return the underlying set.
+    pub fn into_inner(mut self) -> RegionAllocator<S> {
+        self.flush();
+        self.regions
+    }
+    /// Round `size` up to a power of 2 pages, returning it with its size class.
+    fn round(&self, size: usize) -> Result<(usize, usize), RegionError> {
+        if size == 0 {
+            return Err(RegionError::EmptyRange);
+        }
+        let pages = (size - 1) >> self.page_shift;
+        let pages = (pages + 1)
+            .checked_next_power_of_two()
+            .ok_or(RegionError::Overflow)?;
+        let size = pages
+            .checked_shl(self.page_shift)
+            .filter(|&s| s != 0)
+            .ok_or(RegionError::Overflow)?;
+        Ok((size, pages.trailing_zeros() as usize))
+    }
+    /// Allocate a range of at least `size` bytes, aligned to its rounded size.
+    ///
+    /// Return its base; [`IovaAllocator::free`] takes the same `size` back.
+    pub fn allocate(&mut self, size: usize) -> Result<usize, RegionError> {
+        let (size, class) = self.round(size)?;
+        if class < SIZE_CLASSES && self.lens[class] > 0 {
+            self.lens[class] -= 1;
+            return Ok(self.caches[class][self.lens[class]]);
+        }
+        match self.regions.allocate_by_size(size, size) {
+            Err(RegionError::NoFit) if self.cached() > 0 => {
+                self.flush();
+                self.regions
+                    .allocate_by_size(size, size)
+                    .map(|(base, _)| base)
+            }
+            found => found.map(|(base, _)| base),
+        }
+    }
+    /// Free a range returned by [`IovaAllocator::allocate`] for the same `size`,
+    /// flushing its size class first if the class is full.
+    pub fn free(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        let (size, class) = self.round(size)?;
+        if class >= SIZE_CLASSES || N == 0 {
+            return self.regions.deallocate(base, size);
+        }
+        if self.lens[class] == N {
+            self.flush_class(class)?;
+        }
+        self.caches[class][self.lens[class]] = base;
+        self.lens[class] += 1;
+        Ok(())
+    }
+    /// Return every cached range to the set.
+    ///
+    /// A range the storage has no room for stays cached.
+    pub fn flush(&mut self) {
+        for class in 0..SIZE_CLASSES {
+            let _ = self.flush_class(class);
+        }
+    }
+
+    fn flush_class(&mut self, class: usize) -> Result<(), RegionError> {
+        let size = 1 << (class as u32 + self.page_shift);
+        while self.lens[class] > 0 {
+            let base = self.caches[class][self.lens[class] - 1];
+            self.regions.deallocate(base, size)?;
+            self.lens[class] -= 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::IovaAllocator;
+    use crate::{RegionAllocator, RegionError};
+
+    #[test]
+    fn iova_test() {
+        let mut regions = RegionAllocator::new();
+        regions.add(0x10_0000, 0x10000);
+        let mut iova = IovaAllocator::<_, 2>::new(regions, 0x1000).unwrap();
+        // Case 1: sizes round up to a power of 2 pages, aligned to it
+        assert_eq!(iova.allocate(0x1000), Ok(0x10_0000));
+        assert_eq!(iova.allocate(0x2001), Ok(0x10_4000));
+        assert_eq!(iova.allocate(0), Err(RegionError::EmptyRange));
+        // Case 2: freed ranges are cached by class and reused first
+        assert_eq!(iova.free(0x10_4000, 0x2001), Ok(()));
+        assert_eq!(iova.cached(), 1);
+        assert!(!iova.regions().check_point(0x10_4000));
+        assert_eq!(iova.allocate(0x3000), Ok(0x10_4000));
+        // Case 3: a full class is flushed at once
+        assert_eq!(iova.free(0x10_0000, 1), Ok(()));
+        let (a, b) = (iova.allocate(1).unwrap(), iova.allocate(1).unwrap());
+        assert_eq!((a, b), (0x10_0000, 0x10_8000));
+        let c = iova.allocate(1).unwrap();
+        assert_eq!(iova.free(a, 1).and(iova.free(b, 1)), Ok(()));
+        assert_eq!(iova.cached(), 2);
+        assert_eq!(iova.free(c, 1), Ok(()));
+        assert_eq!(iova.cached(), 1);
+        assert!(iova.regions().check_point(a) && iova.regions().check_point(b));
+        // Case 4: pressure on the set flushes every class
+        assert_eq!(iova.allocate(0x8000), Ok(0x10_8000));
+        assert_eq!(iova.cached(), 0);
+        assert_eq!(iova.allocate(0x4000), Ok(0x10_0000));
+        assert_eq!(iova.free(0x10_4000, 0x4000), Ok(()));
+        let regions = iova.into_inner();
+        assert!(regions.check_region(0x10_4000, 0x4000));
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions.stats().allocated_bytes, 0xc000);
+    }
+}
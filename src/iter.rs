@@ -0,0 +1,155 @@
+//! Iteration over a region set as address ranges.
+
+use crate::{RegionAllocator, RegionStorage};
+use core::iter::FusedIterator;
+use core::ops::Range;
+
+/// An iterator over the regions of a [`RegionAllocator`] as address ranges,
+/// created by [`RegionAllocator::iter`].
+pub struct Iter<'a, S: RegionStorage + 'a> {
+    inner: S::Iter<'a>,
+    len: usize,
+}
+
+impl<'a, S: RegionStorage> Iterator for Iter<'a, S> {
+    type Item = Range<usize>;
+
+    fn next(&mut self) -> Option<Range<usize>> {
+        let r = self.inner.next()?;
+        self.len -= 1;
+        Some(r.base..r.end())
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, S: RegionStorage> DoubleEndedIterator for Iter<'a, S> {
+    fn next_back(&mut self) -> Option<Range<usize>> {
+        let r = self.inner.next_back()?;
+        self.len -= 1;
+        Some(r.base..r.end())
+    }
+}
+
+impl<'a, S: RegionStorage> ExactSizeIterator for Iter<'a, S> {}
+
+impl<'a, S: RegionStorage> FusedIterator for Iter<'a, S> {}
+
+/// An iterator moving the regions out of a [`RegionAllocator`] as address ranges.
+///
+/// Each step looks the next region up by address, so the storage is kept as is
+/// instead of being drained.
+pub struct IntoIter<S> {
+    regions: S,
+    /// The lowest base not yet yielded from the front.
+    front: usize,
+    /// The highest base not yet yielded from the back.
+    back: usize,
+    len: usize,
+}
+
+impl<S: RegionStorage> Iterator for IntoIter<S> {
+    type Item = Range<usize>;
+
+    fn next(&mut self) -> Option<Range<usize>> {
+        if self.len == 0 {
+            return None;
+        }
+        let r = self.regions.range(self.front..).next()?;
+        // Regions are not empty, so no base is `usize::MAX`
+        self.front = r.base + 1;
+        self.len -= 1;
+        Some(r.base..r.end())
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<S: RegionStorage> DoubleEndedIterator for IntoIter<S> {
+    fn next_back(&mut self) -> Option<Range<usize>> {
+        if self.len == 0 {
+            return None;
+        }
+        let r = self.regions.range(..=self.back).next_back()?;
+        self.back = r.base.wrapping_sub(1);
+        self.len -= 1;
+        Some(r.base..r.end())
+    }
+}
+
+impl<S: RegionStorage> ExactSizeIterator for IntoIter<S> {}
+
+impl<S: RegionStorage> FusedIterator for IntoIter<S> {}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Iterate over the regions as address ranges, in ascending order.
+    pub fn iter(&self) -> Iter<'_, S> {
+        Iter {
+            inner: self.regions.range(..),
+            len: self.regions.len(),
+        }
+    }
+}
+
+impl<'a, S: RegionStorage> IntoIterator for &'a RegionAllocator<S> {
+    type Item = Range<usize>;
+    type IntoIter = Iter<'a, S>;
+
+    fn into_iter(self) -> Iter<'a, S> {
+        self.iter()
+    }
+}
+
+impl<S: RegionStorage> IntoIterator for RegionAllocator<S> {
+    type Item = Range<usize>;
+    type IntoIter = IntoIter<S>;
+
+    fn into_iter(self) -> IntoIter<S> {
+        IntoIter {
+            len: self.regions.len(),
+            regions: self.regions,
+            front: 0,
+            back: usize::MAX,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use crate::{ArrayStorage, RegionAllocator};
+    use alloc::vec::Vec;
+
+    #[test]
+    fn iter_test() {
+        let mut alloc = RegionAllocator::new();
+        alloc.add(0x3000, 0x1000);
+        alloc.add(0, 0x1000);
+        alloc.add(usize::MAX - 0x1000, 0x1000);
+        let ranges: Vec<_> = alloc.iter().collect();
+        assert_eq!(
+            ranges,
+            [0..0x1000, 0x3000..0x4000, usize::MAX - 0x1000..usize::MAX]
+        );
+        assert_eq!(alloc.iter().len(), 3);
+        assert_eq!(
+            alloc.iter().next_back(),
+            Some(usize::MAX - 0x1000..usize::MAX)
+        );
+        assert_eq!((&alloc).into_iter().map(|r| r.len()).sum::<usize>(), 0x3000);
+        // Case 2: owned iteration from both ends
+        let mut owned = alloc.into_iter();
+        assert_eq!(owned.next_back(), Some(usize::MAX - 0x1000..usize::MAX));
+        assert_eq!(owned.next(), Some(0..0x1000));
+        assert_eq!(owned.len(), 1);
+        assert_eq!(owned.next_back(), Some(0x3000..0x4000));
+        assert_eq!(owned.next(), None);
+        assert_eq!(owned.next_back(), None);
+        // Case 3: ranges go back in through FromIterator
+        let mut array = RegionAllocator::with_storage(ArrayStorage::<2>::new());
+        array.add(0x1000, 0x1000);
+        let copy: RegionAllocator = array.into_iter().collect();
+        assert!(copy.check_region(0x1000, 0x1000));
+    }
+}
@@ -0,0 +1,207 @@
+//! A log of every change to a region set, to rebuild it after a crash or a kexec.
+
+use crate::{BTreeStorage, CapacityError, Op, Region, RegionAllocator, RegionError, RegionStorage};
+use alloc::vec::Vec;
+
+/// An entry of a journal written by a [`JournaledRegionAllocator`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum JournalRecord {
+    /// The whole set follows, as this many [`Op::Add`] records; whatever came before can
+    /// be discarded.
+    Checkpoint(usize),
+    /// A change that was made. Allocations are recorded as the [`Op::AllocateAddr`] of
+    /// the region they took, so replaying them takes the same regions.
+    Op(Op),
+}
+
+/// Where a [`JournaledRegionAllocator`] writes its records, such as a persistent memory
+/// log or a buffer handed over to the next kernel.
+pub trait Journal {
+    /// Append a record, failing if there is no room for it.
+    fn append(&mut self, record: JournalRecord) -> Result<(), CapacityError>;
+}
+
+impl Journal for Vec<JournalRecord> {
+    fn append(&mut self, record: JournalRecord) -> Result<(), CapacityError> {
+        self.try_reserve(1).map_err(|_| CapacityError)?;
+        self.push(record);
+        Ok(())
+    }
+}
+
+/// A [`RegionAllocator`] that appends every change to a [`Journal`], from which
+/// [`RegionAllocator::replay`] rebuilds the set.
+///
+/// A change is only kept once its record is appended; if the journal is full the change
+/// is undone and fails with [`RegionError::Capacity`], so the journal never lags behind
+/// the set. [`JournaledRegionAllocator::checkpoint`] writes the whole set, bounding how
+/// much of the journal has to be replayed.
+#[derive(Clone, Debug)]
+pub struct JournaledRegionAllocator<J: Journal, S: RegionStorage = BTreeStorage> {
+    regions: RegionAllocator<S>,
+    journal: J,
+}
+
+impl<J: Journal, S: RegionStorage> JournaledRegionAllocator<J, S> {
+    /// Wrap a [`RegionAllocator`], writing a checkpoint of its set first.
+    pub fn new(regions: RegionAllocator<S>, journal: J) -> Result<Self, CapacityError> {
+        let mut journaled = JournaledRegionAllocator { regions, journal };
+        journaled.checkpoint()?;
+        Ok(journaled)
+    }
+    /// Return the set.
+    pub fn regions(&self) -> &RegionAllocator<S> {
+        &self.regions
+    }
+    /// Return the journal.
+    pub fn journal(&self) -> &J {
+        &self.journal
+    }
+    /// Return the journal, such as to discard the records before the latest checkpoint.
+    pub fn journal_mut(&mut self) -> &mut J {
+        &mut self.journal
+    }
+    /// Unwrap the set and the journal.
+    pub fn into_inner(self) -> (RegionAllocator<S>, J) {
+        (self.regions, self.journal)
+    }
+    /// Write the whole set to the journal.
+    ///
+    /// If the journal fills up partway through, it ends with an incomplete checkpoint,
+    /// which has fewer records after it than it announces and should be discarded.
+    pub fn checkpoint(&mut self) -> Result<(), CapacityError> {
+        let journal = &mut self.journal;
+        journal.append(JournalRecord::Checkpoint(self.regions.len()))?;
+        for r in self.regions.regions.range(..) {
+            journal.append(JournalRecord::Op(Op::Add(r)))?;
+        }
+        Ok(())
+    }
+    /// See [`RegionAllocator::try_add`].
+    pub fn add(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        self.apply(Op::Add(Region { base, size })).map(drop)
+    }
+    /// See [`RegionAllocator::try_subtract`].
+    pub fn subtract(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        self.apply(Op::Subtract(Region { base, size })).map(drop)
+    }
+    /// See [`RegionAllocator::allocate_by_addr`].
+    pub fn allocate_by_addr(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        self.apply(Op::AllocateAddr(Region { base, size }))
+            .map(drop)
+    }
+    /// See [`RegionAllocator::allocate_by_size`].
+    pub fn allocate_by_size(
+        &mut self,
+        size: usize,
+        alignment: usize,
+    ) -> Result<(usize, usize), RegionError> {
+        let taken = self.apply(Op::AllocateSize { size, alignment })?;
+        let r = taken.expect("allocations return the region taken");
+        Ok((r.base, r.size))
+    }
+
+    fn apply(&mut self, op: Op) -> Result<Option<Region>, RegionError> {
+        let (cursor, counters) = (self.regions.cursor, self.regions.counters);
+        let mut undo = Vec::new();
+        let taken = self.regions.apply_undoable(op, &mut undo)?;
+        let record = match taken {
+            Some(r) => Op::AllocateAddr(r),
+            None => op,
+        };
+        if let Err(e) = self.journal.append(JournalRecord::Op(record)) {
+            self.regions.undo(undo.into_iter());
+            self.regions.cursor = cursor;
+            self.regions.counters = counters;
+            return Err(e.into());
+        }
+        Ok(taken)
+    }
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Rebuild a set from the records of a [`Journal`], such as those from its latest
+    /// checkpoint on.
+    ///
+    /// Each checkpoint empties the set before the regions after it are added. Fails with
+    /// the error of the first change that cannot be made again, which means the records
+    /// do not come from one journal, or start after a change they depend on.
+    pub fn replay(
+        &mut self,
+        records: impl IntoIterator<Item = JournalRecord>,
+    ) -> Result<(), RegionError> {
+        records.into_iter().try_for_each(|record| match record {
+            JournalRecord::Checkpoint(_) => {
+                self.retain(|_, _| false);
+                Ok(())
+            }
+            JournalRecord::Op(op) => self.apply(Some(op)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Journal, JournalRecord, JournaledRegionAllocator};
+    use crate::{ArrayStorage, CapacityError, Op, Region, RegionAllocator, RegionError};
+    use alloc::vec::Vec;
+
+    /// A journal with room for a fixed number of records.
+    struct Bounded(Vec<JournalRecord>, usize);
+
+    impl Journal for Bounded {
+        fn append(&mut self, record: JournalRecord) -> Result<(), CapacityError> {
+            if self.0.len() == self.1 {
+                return Err(CapacityError);
+            }
+            self.0.push(record);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn journal_test() {
+        let mut boot = RegionAllocator::new();
+        boot.add(0, 0x4000);
+        boot.add(0x8000, 0x4000);
+        let mut regions = JournaledRegionAllocator::new(boot, Vec::new()).unwrap();
+        assert_eq!(regions.allocate_by_size(0x1000, 0x1000), Ok((0, 0x1000)));
+        assert_eq!(regions.subtract(0x9000, 0x1000), Ok(()));
+        assert_eq!(regions.add(0x4000, 0x1000), Ok(()));
+        assert_eq!(
+            regions.allocate_by_addr(0x1_0000, 1),
+            Err(RegionError::NotCovered)
+        );
+        let r = |base, size| Region { base, size };
+        // Case 1: changes follow the checkpoint, allocations with the region taken
+        assert_eq!(
+            regions.journal()[3..],
+            [
+                JournalRecord::Op(Op::AllocateAddr(r(0, 0x1000))),
+                JournalRecord::Op(Op::Subtract(r(0x9000, 0x1000))),
+                JournalRecord::Op(Op::Add(r(0x4000, 0x1000))),
+            ]
+        );
+        let mut rebuilt = RegionAllocator::with_storage(ArrayStorage::<4>::new());
+        rebuilt.add(0x2_0000, 0x1000);
+        assert_eq!(rebuilt.replay(regions.journal().iter().copied()), Ok(()));
+        assert!(rebuilt == *regions.regions());
+        // Case 2: replaying from a later checkpoint gives the same set
+        regions.checkpoint().unwrap();
+        let (regions, journal) = regions.into_inner();
+        let mut rebuilt = RegionAllocator::new();
+        assert_eq!(rebuilt.replay(journal[6..].iter().copied()), Ok(()));
+        assert!(rebuilt == regions);
+        // Case 3: changes the journal has no room for are undone
+        let mut regions = JournaledRegionAllocator::new(regions, Bounded(Vec::new(), 5)).unwrap();
+        assert_eq!(regions.allocate_by_size(0x1000, 1), Ok((0x1000, 0x1000)));
+        let before = regions.regions().clone();
+        assert_eq!(regions.add(0x10_0000, 0x1000), Err(RegionError::Capacity));
+        assert_eq!(
+            regions.allocate_by_size(0x1000, 1),
+            Err(RegionError::Capacity)
+        );
+        assert!(regions.regions() == &before);
+        assert_eq!(regions.regions().stats(), before.stats());
+    }
+}
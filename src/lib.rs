@@ -1,61 +1,587 @@
 #![no_std]
-#![feature(btree_drain_filter)]
+#![cfg_attr(feature = "nightly", feature(allocator_api))]
 
+#[cfg(feature = "alloc")]
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
-use alloc::collections::BTreeSet;
-use alloc::vec::Vec;
+pub mod arch;
+pub mod atomic;
+pub mod audit;
+pub mod bitmap;
+#[cfg(feature = "alloc")]
+pub mod bounce;
+#[cfg(feature = "alloc")]
+pub mod buddy;
+mod builder;
+pub mod bump;
+mod color;
+mod compact;
+#[cfg(feature = "critical-section")]
+pub mod critical;
+mod error;
+#[cfg(any(
+    feature = "bootloader_api",
+    feature = "e820",
+    feature = "fdt",
+    feature = "limine",
+    feature = "multiboot2",
+    feature = "srat",
+    feature = "uefi"
+))]
+pub mod firmware;
+mod fmt;
+#[cfg(feature = "alloc")]
+mod frozen;
+pub mod global;
+#[cfg(feature = "alloc")]
+pub mod hotplug;
+pub mod id;
+pub mod iova;
+mod iter;
+#[cfg(feature = "alloc")]
+pub mod journal;
+pub mod locked;
+pub mod magazine;
+#[cfg(feature = "alloc")]
+pub mod memslot;
+mod observe;
+mod ops;
+#[cfg(feature = "rayon")]
+mod par;
+mod parse;
+pub mod pci;
+mod percpu;
+pub mod port;
+mod raw;
+mod render;
+#[cfg(feature = "alloc")]
+pub mod resource;
+pub mod ring;
+#[cfg(feature = "serde")]
+mod serialize;
+mod set;
+pub mod sharded;
+#[cfg(feature = "alloc")]
+pub mod slab;
+#[cfg(feature = "alloc")]
+pub mod snapshot;
+pub mod stack;
+mod stats;
+pub mod storage;
+#[cfg(feature = "alloc")]
+mod transaction;
+pub mod typed;
+#[cfg(feature = "alloc")]
+pub mod undo;
+#[cfg(feature = "alloc")]
+pub mod vector;
+#[cfg(feature = "alloc")]
+pub mod vma;
+
+pub use atomic::AtomicFrameAllocator;
+pub use audit::{AuditEntry, AuditedRegionAllocator, Clock};
+pub use bitmap::BitmapAllocator;
+#[cfg(feature = "alloc")]
+pub use bounce::{BouncePool, BounceStats};
+#[cfg(feature = "alloc")]
+pub use buddy::BuddyAllocator;
+pub use builder::RegionAllocatorBuilder;
+pub use bump::BumpRegion;
 use core::cmp::{max, min};
+use core::convert::TryFrom;
+use core::hash::{Hash, Hasher};
+use core::iter::FromIterator;
+use core::ops::Range;
+#[cfg(feature = "critical-section")]
+pub use critical::CriticalSectionRegionAllocator;
+pub use error::{RegionError, Violation};
+#[cfg(feature = "alloc")]
+pub use frozen::FrozenRegionSet;
+pub use global::GlobalRegionAllocator;
+#[cfg(feature = "alloc")]
+pub use hotplug::{HotRemoveError, HotplugRegionAllocator};
+pub use id::IdAllocator;
+pub use iova::IovaAllocator;
+pub use iter::{IntoIter, Iter};
+#[cfg(feature = "alloc")]
+pub use journal::{Journal, JournalRecord, JournaledRegionAllocator};
+pub use locked::{Interrupts, LockedRegionAllocator};
+pub use magazine::Magazine;
+#[cfg(feature = "alloc")]
+pub use memslot::{MemorySlots, SlotOffset};
+pub use observe::Observer;
+pub use ops::Op;
+pub use parse::{ParseError, ParseErrorKind};
+pub use pci::{PciAllocator, PciResource};
+pub use percpu::{PerCpuAreas, PerCpuLayout};
+pub use port::PortAllocator;
+pub use raw::RawError;
+pub use render::AddressMap;
+#[cfg(feature = "alloc")]
+pub use resource::{ResourceId, ResourceTree};
+pub use ring::RingRegion;
+pub use sharded::ShardedRegionAllocator;
+#[cfg(feature = "alloc")]
+pub use slab::SlabCache;
+#[cfg(feature = "alloc")]
+pub use snapshot::{Snapshot, SnapshotRegionAllocator};
+pub use stack::{StackAllocation, StackRegion};
+use stats::Counters;
+pub use stats::{Fragmentation, RegionStats, Watermarks};
+#[cfg(feature = "soa")]
+pub use storage::SoaStorage;
+#[cfg(feature = "alloc")]
+pub use storage::{
+    ArenaStorage, BTreeStorage, CowStorage, FrozenStorage, SizeClassStorage, VecStorage,
+};
+pub use storage::{
+    ArrayStorage, CapacityError, HeapFreeStorage, IntrusiveStorage, RegionStorage, SliceStorage,
+};
+#[cfg(feature = "alloc")]
+pub use transaction::Transaction;
+pub use typed::{Address, TypedRegionAllocator};
+#[cfg(feature = "alloc")]
+pub use undo::UndoRegionAllocator;
+#[cfg(feature = "alloc")]
+pub use vector::VectorAllocator;
+#[cfg(feature = "alloc")]
+pub use vma::{AddressSpace, Area, Backing};
 
-#[derive(Eq, Copy, Clone, Debug, Ord, PartialEq, PartialOrd)]
-struct Region {
+/// A region `[base, base + size)` stored in a [`RegionAllocator`].
+///
+/// The geometry helpers treat regions as half-open, whatever the [`Endpoints`] of a set;
+/// they expect `base + size` not to overflow, which holds for every region of a set.
+#[derive(Eq, Copy, Clone, Debug, Hash, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Region {
     pub base: usize,
     pub size: usize,
 }
 
+impl Region {
+    /// Create the region `[base, base + size)`.
+    pub const fn new(base: usize, size: usize) -> Self {
+        Region { base, size }
+    }
+    /// Return the exclusive end.
+    pub const fn end(&self) -> usize {
+        self.base + self.size
+    }
+    /// Check whether the region holds no bytes.
+    pub const fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+    /// Check whether `addr` lies in the region.
+    pub const fn contains(&self, addr: usize) -> bool {
+        self.base <= addr && addr < self.end()
+    }
+    /// Check whether every byte of `other` lies in the region.
+    pub const fn covers(&self, other: &Region) -> bool {
+        self.base <= other.base && other.end() <= self.end()
+    }
+    /// Check whether the two regions share at least one byte.
+    pub fn overlaps(&self, other: &Region) -> bool {
+        self.intersect(other).is_some()
+    }
+    /// Return the bytes the two regions share, if any.
+    pub fn intersect(&self, other: &Region) -> Option<Region> {
+        let (base, end) = (max(self.base, other.base), min(self.end(), other.end()));
+        (base < end).then(|| Region {
+            base,
+            size: end - base,
+        })
+    }
+    /// Check whether one region ends where the other starts, so that adding both
+    /// to a set merges them without any overlap.
+    pub const fn is_adjacent(&self, other: &Region) -> bool {
+        self.end() == other.base || other.end() == self.base
+    }
+    /// Return the largest region within this one whose base and end are multiples of
+    /// `alignment`, or `None` if there is none or `alignment` is not a power of 2.
+    pub fn align_inward(&self, alignment: usize) -> Option<Region> {
+        if !alignment.is_power_of_two() {
+            return None;
+        }
+        let base = self.base.checked_add(alignment - 1)? & !(alignment - 1);
+        let end = self.end() & !(alignment - 1);
+        (base < end).then(|| Region {
+            base,
+            size: end - base,
+        })
+    }
+    /// Return the smallest region containing this one whose base and end are multiples
+    /// of `alignment`, or `None` if it does not fit in the address space or `alignment`
+    /// is not a power of 2.
+    pub fn align_outward(&self, alignment: usize) -> Option<Region> {
+        if !alignment.is_power_of_two() {
+            return None;
+        }
+        let base = self.base & !(alignment - 1);
+        let end = self.end().checked_add(alignment - 1)? & !(alignment - 1);
+        Some(Region {
+            base,
+            size: end - base,
+        })
+    }
+    /// Return the lowest base within the region for `size` bytes aligned to `align + 1`.
+    pub(crate) fn fit(&self, size: usize, align: usize) -> Option<usize> {
+        if size > self.size {
+            return None;
+        }
+        let base = self.base.checked_add(align)? & !align;
+        Some(base).filter(|&b| b.checked_add(size).is_some_and(|e| e <= self.end()))
+    }
+}
+
+/// Whether the end address `base + size` of a region counts as part of it in queries.
+///
+/// This affects [`RegionAllocator::check_point`] and the overlap tests of the checked
+/// operations; sizes always count the addresses from `base` up to, not including, the end.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Endpoints {
+    /// `[base, base + size)`: regions that only touch do not overlap.
+    #[default]
+    HalfOpen,
+    /// `[base, base + size]`: regions that touch overlap at their shared endpoint.
+    Closed,
+}
+
+/// What operations do with empty ranges, which never appear in the set.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ZeroSize {
+    /// Adding, subtracting or allocating 0 bytes succeeds without changing the set, and
+    /// allocations of 0 bytes are not counted in the [`RegionStats`].
+    #[default]
+    Ignore,
+    /// Adding, subtracting or allocating 0 bytes fails with [`RegionError::EmptyRange`].
+    Reject,
+}
+
+/// Where [`RegionAllocator::allocate_by_size`] places an allocation among the regions
+/// it fits in.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum FitPolicy {
+    /// Try the region following the previous allocation first, then the lowest fit, so
+    /// a run of equal-size allocations does not rescan the set from its lowest region.
+    #[default]
+    Next,
+    /// Take the fit chosen by [`RegionStorage::find_fit`], the lowest one unless the
+    /// storage says otherwise.
+    First,
+    /// Take the smallest region that fits, the lowest of those of equal size, keeping
+    /// large regions whole at the cost of a scan of the set.
+    Best,
+}
+
 /// An endpoint-based region allocator.
-#[derive(Default)]
-pub struct RegionAllocator {
-    regions: BTreeSet<Region>,
+///
+/// The region set is kept in a [`RegionStorage`], a `BTreeStorage` by default.
+///
+/// Every mutation works out the new regions before touching the set and applies them with
+/// one atomic [`RegionStorage::splice`], so a failure, or a panic unwinding through it,
+/// leaves the set exactly as it was.
+#[derive(Clone, Default)]
+pub struct RegionAllocator<
+    #[cfg(feature = "alloc")] S = BTreeStorage,
+    #[cfg(not(feature = "alloc"))] S,
+> {
+    regions: S,
+    /// End of the last region handed out by [`RegionAllocator::allocate_by_size`].
+    cursor: Option<usize>,
+    endpoints: Endpoints,
+    zero_size: ZeroSize,
+    /// One less than the granule every range must be aligned to, 0 if byte-granular.
+    granule: usize,
+    /// The sizes of the aligned spans allocations by size avoid breaking, ORed together.
+    preserve: usize,
+    fit_policy: FitPolicy,
+    /// Most regions a change may grow the set to, unlimited if `None`.
+    region_limit: Option<usize>,
+    counters: Counters,
+    observer: Option<&'static dyn Observer>,
 }
 
+/// A [`RegionAllocator`] holding up to `N` regions inline, usable before any heap exists.
+///
+/// Use [`RegionAllocator::try_add`] and [`RegionAllocator::try_subtract`] to detect
+/// when the capacity is exceeded.
+pub type StaticRegionAllocator<const N: usize> = RegionAllocator<ArrayStorage<N>>;
+
+#[cfg(feature = "alloc")]
 impl RegionAllocator {
     /// Create an empty [`RegionAllocator`].
-    pub fn new() -> Self {
-        RegionAllocator::default()
+    pub const fn new() -> Self {
+        RegionAllocator::with_storage(BTreeStorage::new())
+    }
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Create a [`RegionAllocator`] on top of a given storage.
+    ///
+    /// The storage is expected to be empty, or to hold disjoint and non-adjacent regions.
+    pub const fn with_storage(storage: S) -> Self {
+        RegionAllocator {
+            regions: storage,
+            cursor: None,
+            endpoints: Endpoints::HalfOpen,
+            zero_size: ZeroSize::Ignore,
+            granule: 0,
+            preserve: 0,
+            fit_policy: FitPolicy::Next,
+            region_limit: None,
+            counters: Counters::new(),
+            observer: None,
+        }
+    }
+    /// Use the given endpoint semantics for queries, half-open by default.
+    pub const fn with_endpoints(mut self, endpoints: Endpoints) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+    /// Return the endpoint semantics used for queries.
+    pub fn endpoints(&self) -> Endpoints {
+        self.endpoints
+    }
+    /// Use the given policy for empty ranges, ignoring them by default.
+    pub const fn with_zero_size(mut self, zero_size: ZeroSize) -> Self {
+        self.zero_size = zero_size;
+        self
+    }
+    /// Return the policy for empty ranges.
+    pub fn zero_size(&self) -> ZeroSize {
+        self.zero_size
+    }
+    /// Require every range added, subtracted or allocated to start and end on a multiple
+    /// of `granule` bytes, such as the page size, instead of accepting any byte range.
+    ///
+    /// Operations given a range off the granule fail with [`RegionError::Unaligned`], and
+    /// allocations by size are aligned to at least the granule. A granule of 1, the
+    /// default, accepts every range. The regions already in the set are not checked.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `granule` is not a power of 2.
+    pub const fn with_granule(mut self, granule: usize) -> Self {
+        assert!(granule.is_power_of_two(), "granule is not a power of 2");
+        self.granule = granule - 1;
+        self
+    }
+    /// Return the granule ranges must be aligned to, 1 if byte-granular.
+    pub fn granule(&self) -> usize {
+        self.granule + 1
+    }
+    /// Make [`RegionAllocator::allocate_by_size`] keep free naturally aligned spans of the
+    /// given sizes whole where it can, such as `0x20_0000 | 0x4000_0000` for the 2 MiB and
+    /// 1 GiB spans large pages map. `spans` is the sizes, each a power of 2, ORed together;
+    /// 0, the default, turns the preference off.
+    ///
+    /// An allocation smaller than a span is placed where it does not cut into any aligned
+    /// span of that size lying wholly in the set, trying the smallest size first, and only
+    /// where no such placement exists is it placed as usual. This costs a scan of the set
+    /// on every allocation.
+    pub const fn with_preserved_spans(mut self, spans: usize) -> Self {
+        self.preserve = spans;
+        self
+    }
+    /// Return the sizes of the spans allocations keep whole, ORed together.
+    pub fn preserved_spans(&self) -> usize {
+        self.preserve
+    }
+    /// Use the given policy to place allocations by size, [`FitPolicy::Next`] by default.
+    ///
+    /// Spans kept whole with [`RegionAllocator::with_preserved_spans`] take precedence
+    /// over any policy.
+    pub const fn with_fit_policy(mut self, policy: FitPolicy) -> Self {
+        self.fit_policy = policy;
+        self
+    }
+    /// Return the policy placing allocations by size.
+    pub fn fit_policy(&self) -> FitPolicy {
+        self.fit_policy
+    }
+    /// Fail with [`RegionError::Capacity`] any change that would grow the set past
+    /// `limit` regions, such as to bound the heap a `BTreeStorage` takes, or to keep the
+    /// set small enough to move into an [`ArrayStorage`] later. `None`, the default,
+    /// sets no limit.
+    ///
+    /// Changes that do not add regions always succeed, so a set already above the limit
+    /// can still shrink.
+    pub const fn with_region_limit(mut self, limit: Option<usize>) -> Self {
+        self.region_limit = limit;
+        self
+    }
+    /// Return the most regions a change may grow the set to, if limited.
+    pub fn region_limit(&self) -> Option<usize> {
+        self.region_limit
+    }
+    /// Move all regions into another storage, which is expected to be empty.
+    ///
+    /// This is how an allocator bootstrapped on a [`SliceStorage`] or an [`ArrayStorage`]
+    /// is upgraded to a heap-backed storage once the heap is online.
+    pub fn into_storage<T: RegionStorage>(
+        self,
+        mut storage: T,
+    ) -> Result<RegionAllocator<T>, CapacityError> {
+        for r in self.regions.range(..) {
+            storage.splice(r.base..=r.base, &[r])?;
+        }
+        Ok(RegionAllocator {
+            regions: storage,
+            cursor: self.cursor,
+            endpoints: self.endpoints,
+            zero_size: self.zero_size,
+            granule: self.granule,
+            preserve: self.preserve,
+            fit_policy: self.fit_policy,
+            region_limit: self.region_limit,
+            counters: self.counters,
+            observer: self.observer,
+        })
     }
     /// Add a region `[base, base + size)` to the set.
-    /// The left endpoint is inclusive, and the right endpoint is exclusive.
+    /// The left endpoint is inclusive, and the right endpoint is exclusive,
+    /// so the highest region can end at `usize::MAX` but not beyond.
     ///
     /// Any two overlapped or adjacent regions will be merged.
     /// In the final region set, no regions are intersected.
     /// For example if both `[0, 10)` and `[10, 20)` are added sequentially,
     /// only `[0, 20)` will be in the final region set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `base + size` overflows or the storage runs out of capacity,
+    /// see [`RegionAllocator::try_add`].
     pub fn add(&mut self, base: usize, size: usize) {
+        if let Err(e) = self.try_add(base, size) {
+            panic!("cannot add region: {:?}", e);
+        }
+    }
+    /// Add a region like [`RegionAllocator::add`], failing with [`RegionError::Overflow`]
+    /// if `base + size` overflows and with [`RegionError::Capacity`] if the storage is full.
+    /// The set is left unchanged on failure.
+    pub fn try_add(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        let end = base.checked_add(size).ok_or(RegionError::Overflow)?;
+        self.check_granule(base, size)?;
+        if size == 0 {
+            return self.empty_range();
+        }
         let mut new_region = Region { base, size };
-        let overlaps = self.intersection_all(&new_region);
-        for b in overlaps {
-            if let Some(b) = Self::merge_internal(&mut new_region, b) {
-                self.insert_internal(b);
+        let start = match self.find_internal(base) {
+            Some(r) if r.end() >= base => r.base,
+            _ => base,
+        };
+        let (mut merged, mut count) = (0, 0);
+        for b in self.regions.range(start..=end) {
+            #[cfg(feature = "log")]
+            if b.base < end && base < b.end() {
+                log::warn!(
+                    "adding {:#x}..{:#x} overlaps {:#x}..{:#x}",
+                    base,
+                    end,
+                    b.base,
+                    b.end()
+                );
             }
+            merged += b.size;
+            count += 1;
+            Self::merge_internal(&mut new_region, b);
         }
-        self.insert_internal(new_region);
+        self.check_limit(count, 1)?;
+        self.regions.splice(start..=end, &[new_region])?;
+        self.spliced(merged, new_region.size);
+        self.notify(|o| o.added(Region { base, size }));
+        #[cfg(feature = "tracing")]
+        tracing::trace!(base, size, "add");
+        Ok(())
+    }
+    /// Add a region like [`RegionAllocator::try_add`], but fail with
+    /// [`RegionError::Overlapping`] if it overlaps any region in the set.
+    ///
+    /// Adjacent regions are still merged. This detects broken memory maps
+    /// instead of silently merging their overlapping entries.
+    pub fn add_checked(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        let end = base.checked_add(size).ok_or(RegionError::Overflow)?;
+        self.check_granule(base, size)?;
+        if size == 0 {
+            return self.empty_range();
+        }
+        if self.overlaps(base, end) {
+            return Err(RegionError::Overlapping);
+        }
+        self.try_add(base, size)
     }
     /// Subtract the whole region set with a given region.
     /// After this operation, all regions in the set have no intersection with the given one.
     /// Regions completely contained by the given region will be removed.
     /// Regions wholly containing the given region will be splitted into two parts
+    ///
+    /// # Panics
+    ///
+    /// Panics if `base + size` overflows or the storage runs out of capacity,
+    /// see [`RegionAllocator::try_subtract`].
     pub fn subtract(&mut self, base: usize, size: usize) {
-        let mut new_region = Region { base, size };
-        let overlaps = self.intersection_all(&new_region);
-        for b in overlaps {
-            let res = Self::subtract_internal(b, &mut new_region);
-            if let Some(b) = res.0 {
-                self.insert_internal(b);
-            }
-            if let Some(b) = res.1 {
-                self.insert_internal(b);
-            }
+        if let Err(e) = self.try_subtract(base, size) {
+            panic!("cannot subtract region: {:?}", e);
+        }
+    }
+    /// Subtract a region like [`RegionAllocator::subtract`], failing with
+    /// [`RegionError::Overflow`] if `base + size` overflows and with [`RegionError::Capacity`]
+    /// if splitting a region needs more room than the storage has.
+    /// The set is left unchanged on failure.
+    pub fn try_subtract(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        base.checked_add(size).ok_or(RegionError::Overflow)?;
+        self.check_granule(base, size)?;
+        if size == 0 {
+            return self.empty_range();
+        }
+        let src = Region { base, size };
+        let first = self.find_internal(base).filter(|r| r.end() > base);
+        let start = first.map_or(base, |r| r.base);
+        let last = match self.regions.range(start..src.end()).next_back() {
+            Some(r) => r,
+            None => return Ok(()),
+        };
+        let left = first.and_then(|r| Self::subtract_internal(r, &src).0);
+        let right = Self::subtract_internal(last, &src).1;
+        let mut pieces = [src; 2];
+        let mut n = 0;
+        for piece in left.into_iter().chain(right) {
+            pieces[n] = piece;
+            n += 1;
+        }
+        let (mut removed, mut count) = (0, 0);
+        for r in self.regions.range(start..src.end()) {
+            removed += r.size;
+            count += 1;
+        }
+        self.check_limit(count, n)?;
+        self.regions.splice(start..=src.end() - 1, &pieces[..n])?;
+        self.spliced(removed, pieces[..n].iter().map(|r| r.size).sum());
+        self.notify(|o| o.subtracted(src));
+        #[cfg(feature = "tracing")]
+        tracing::trace!(base, size, "subtract");
+        Ok(())
+    }
+
+    /// Subtract a region like [`RegionAllocator::try_subtract`], but fail with
+    /// [`RegionError::NotCovered`] or [`RegionError::PartiallyCovered`] unless it lies
+    /// wholly within one region of the set. The latter carries the lowest sub-range of the
+    /// request that is missing from the set. The set is left unchanged on failure.
+    pub fn subtract_checked(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        let end = base.checked_add(size).ok_or(RegionError::Overflow)?;
+        self.check_granule(base, size)?;
+        if size == 0 {
+            return self.empty_range();
+        }
+        match self.find_internal(base) {
+            Some(r) if end <= r.end() => self.try_subtract(base, size),
+            _ if self.overlaps(base, end) => match self.first_gap(base, end) {
+                Some(gap) => Err(RegionError::PartiallyCovered(gap)),
+                None => Err(RegionError::NotCovered),
+            },
+            _ => Err(RegionError::NotCovered),
         }
     }
 
@@ -67,36 +593,161 @@ impl RegionAllocator {
         }
     }
 
-    pub fn allocate_by_addr(&mut self, base: usize, size: usize) -> bool {
-        for r in &self.regions {
-            if r.base <= base && base + size <= r.base + r.size {
-                self.subtract(base, size);
-                return true;
-            }
+    /// Allocate the region `[base, base + size)`, which must be wholly in the set.
+    ///
+    /// This is [`RegionAllocator::subtract_checked`] under the name used by allocators;
+    /// on [`RegionError::PartiallyCovered`] the missing sub-range tells which bytes are
+    /// held elsewhere.
+    pub fn allocate_by_addr(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        let taken = self.subtract_checked(base, size);
+        if size != 0 || taken.is_err() {
+            self.record(size, taken.map(|()| Region { base, size }));
+        }
+        #[cfg(feature = "log")]
+        if let Err(e) = &taken {
+            log::warn!("cannot allocate {:#x} bytes at {:#x}: {}", size, base, e);
         }
-        false
+        #[cfg(feature = "tracing")]
+        match &taken {
+            Ok(()) => tracing::debug!(base, size, "allocated"),
+            Err(e) => tracing::debug!(base, size, error = %e, "allocation failed"),
+        }
+        taken
     }
     /// Allocate a region at an arbitrary position aligned to a given power of 2.
-    pub fn allocate_by_size(&mut self, size: usize, alignment: usize) -> Option<(usize, usize)> {
+    ///
+    /// The position is chosen by the [`FitPolicy`]; by default the region that follows
+    /// the previous successful allocation is tried first, so a run of equal-size
+    /// allocations does not rescan the set from its lowest region. Otherwise the position
+    /// is chosen by [`RegionStorage::find_fit`], which is the lowest fitting one unless
+    /// the storage says otherwise.
+    pub fn allocate_by_size(
+        &mut self,
+        size: usize,
+        alignment: usize,
+    ) -> Result<(usize, usize), RegionError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("allocate_by_size", size, alignment).entered();
+        if size == 0 && alignment.is_power_of_two() && self.zero_size == ZeroSize::Ignore {
+            // Nothing is taken, so any aligned base will do; the one at the cursor is
+            // where the next allocation would go
+            let align = (alignment - 1) | self.granule;
+            let base = self.cursor.and_then(|c| c.checked_add(align));
+            return Ok((base.map_or(0, |b| b & !align), 0));
+        }
+        let found = self.take_fit(size, alignment);
+        self.record(size, found.map(|(base, size)| Region { base, size }));
+        #[cfg(feature = "log")]
+        if let Err(e) = &found {
+            log::warn!(
+                "cannot allocate {:#x} bytes aligned to {:#x}: {}",
+                size,
+                alignment,
+                e
+            );
+        }
+        #[cfg(feature = "tracing")]
+        match &found {
+            Ok((base, size)) => tracing::debug!(base, size, "allocated"),
+            Err(e) => tracing::debug!(size, alignment, error = %e, "allocation failed"),
+        }
+        found
+    }
+    fn take_fit(&mut self, size: usize, alignment: usize) -> Result<(usize, usize), RegionError> {
         if !alignment.is_power_of_two() {
-            return None;
+            return Err(RegionError::InvalidAlignment);
         }
-        let align = alignment - 1;
-        for r in &self.regions {
-            if size > r.size {
-                continue;
-            }
-            let base = (r.base + align) & !align;
-            if r.base <= base && base + size <= r.base + r.size {
-                self.subtract(base, size);
-                return Some((base, size));
+        if size == 0 {
+            self.empty_range()?;
+        }
+        self.check_granule(0, size)?;
+        let align = (alignment - 1) | self.granule;
+        let base = self
+            .preserving_fit(size, align)
+            .or_else(|| self.policy_fit(size, align))
+            .ok_or(RegionError::NoFit)?;
+        self.try_subtract(base, size)?;
+        self.cursor = Some(base + size);
+        Ok((base, size))
+    }
+    /// Allocate a region like [`RegionAllocator::allocate_by_size`], then pass it to `f`.
+    /// If `f` fails, the region is given back and the set is left as before.
+    ///
+    /// This lets an allocation depend on a second, fallible one, such as a physical
+    /// range backing a virtual one, without leaking the first when the second fails.
+    pub fn allocate_with<R>(
+        &mut self,
+        size: usize,
+        alignment: usize,
+        f: impl FnOnce(usize, usize) -> Result<R, RegionError>,
+    ) -> Result<((usize, usize), R), RegionError> {
+        let cursor = self.cursor;
+        let (base, size) = self.allocate_by_size(size, alignment)?;
+        match f(base, size) {
+            Ok(r) => Ok(((base, size), r)),
+            Err(e) => {
+                // Putting the region back restores the earlier set, which fitted in the storage.
+                let _ = self.try_add(base, size);
+                self.cursor = cursor;
+                // Empty allocations are not counted
+                if size != 0 {
+                    self.counters.revert(size);
+                }
+                Err(e)
             }
         }
-        None
+    }
+    /// Allocate `size` bytes from both `self` and `other`, with their own alignments,
+    /// or from neither. Return the bases in `self` and in `other`.
+    pub fn allocate_paired<T: RegionStorage>(
+        &mut self,
+        other: &mut RegionAllocator<T>,
+        size: usize,
+        alignment: usize,
+        other_alignment: usize,
+    ) -> Result<(usize, usize), RegionError> {
+        let found = self.allocate_with(size, alignment, |_, size| {
+            other.allocate_by_size(size, other_alignment)
+        });
+        found.map(|((base, _), (other_base, _))| (base, other_base))
     }
     /// Find if any region perfectly match a given range.
     pub fn check_region(&self, base: usize, size: usize) -> bool {
-        self.regions.contains(&Region { base, size })
+        self.regions.range(base..=base).any(|r| r.size == size)
+    }
+    /// Check that the stored regions are sorted, non-empty, non-overlapping, non-adjacent
+    /// and end within the address space, returning the first violation otherwise.
+    ///
+    /// The allocator maintains these itself; this is for storages handed over from
+    /// elsewhere, such as a previous boot stage, and for debug checks.
+    pub fn validate(&self) -> Result<(), Violation> {
+        self.violations().next().map_or(Ok(()), Err)
+    }
+    /// Iterate over every broken invariant, see [`RegionAllocator::validate`].
+    pub fn violations(&self) -> impl Iterator<Item = Violation> + '_ {
+        let mut prev: Option<Region> = None;
+        self.regions.range(..).flat_map(move |r| {
+            let own = if r.size == 0 {
+                Some(Violation::Empty(r))
+            } else if r.base.checked_add(r.size).is_none() {
+                Some(Violation::Overflow(r))
+            } else {
+                None
+            };
+            let pair = prev.replace(r).and_then(|p| {
+                let p_end = p.base.saturating_add(p.size);
+                if r.base < p.base {
+                    Some(Violation::Unsorted(p, r))
+                } else if r.base < p_end {
+                    Some(Violation::Overlapping(p, r))
+                } else if r.base == p_end {
+                    Some(Violation::Adjacent(p, r))
+                } else {
+                    None
+                }
+            });
+            own.into_iter().chain(pair)
+        })
     }
     /// Return number of regions in the set.
     pub fn len(&self) -> usize {
@@ -105,40 +756,133 @@ impl RegionAllocator {
     pub fn is_empty(&self) -> bool {
         self.regions.is_empty()
     }
-    /// Check whether the point is covered.
+    /// Check whether the point is covered, which for the end address of a region
+    /// depends on [`RegionAllocator::endpoints`].
     pub fn check_point(&self, addr: usize) -> bool {
-        for r in &self.regions {
-            if r.base <= addr && addr <= r.base + r.size {
-                return true;
-            }
+        match (self.find_internal(addr), self.endpoints) {
+            (Some(r), Endpoints::HalfOpen) => addr < r.end(),
+            (Some(r), Endpoints::Closed) => addr <= r.end(),
+            (None, _) => false,
         }
-        false
     }
 
-    fn intersection_all(&mut self, region: &Region) -> Vec<Region> {
-        self.regions
-            .drain_filter(|r| {
-                !(r.base > region.base + region.size || r.base + r.size < region.base)
-            })
-            .collect()
+    /// Find a fit for `size` bytes aligned to `align + 1` under the [`FitPolicy`].
+    fn policy_fit(&self, size: usize, align: usize) -> Option<usize> {
+        match self.fit_policy {
+            FitPolicy::Next => self
+                .cursor
+                .and_then(|cursor| self.regions.range(cursor..).next())
+                .and_then(|r| r.fit(size, align))
+                .or_else(|| self.regions.find_fit(size, align)),
+            FitPolicy::First => self.regions.find_fit(size, align),
+            FitPolicy::Best => self
+                .regions
+                .range(..)
+                .filter_map(|r| Some((r.size, r.fit(size, align)?)))
+                .min_by_key(|&(size, _)| size)
+                .map(|(_, base)| base),
+        }
+    }
+    /// Find the lowest fit for `size` bytes aligned to `align + 1` that cuts into no wholly
+    /// free aligned span of the smallest preserved size it can avoid.
+    fn preserving_fit(&self, size: usize, align: usize) -> Option<usize> {
+        let mut spans = self.preserve;
+        while spans != 0 {
+            let span = 1 << spans.trailing_zeros();
+            spans &= spans - 1;
+            if size >= span {
+                continue;
+            }
+            for r in self.regions.range(..) {
+                // Whole spans lie in `[up, down)`, leaving slivers at both ends
+                let up = r
+                    .base
+                    .checked_add(span - 1)
+                    .map_or(r.end(), |b| b & !(span - 1));
+                let down = r.end() & !(span - 1);
+                let slivers = match up < down {
+                    true => [
+                        Region::new(r.base, up - r.base),
+                        Region::new(down, r.end() - down),
+                    ],
+                    false => [r, Region::new(r.end(), 0)],
+                };
+                if let Some(base) = slivers.iter().find_map(|s| s.fit(size, align)) {
+                    return Some(base);
+                }
+            }
+        }
+        None
+    }
+    /// Check that replacing `removed` regions with `added` ones keeps the set within its
+    /// region limit, or does not grow it.
+    fn check_limit(&self, removed: usize, added: usize) -> Result<(), CapacityError> {
+        match self.region_limit {
+            Some(limit) if added > removed && self.regions.len() - removed + added > limit => {
+                Err(CapacityError)
+            }
+            _ => Ok(()),
+        }
+    }
+    /// Check that a range starts and ends on the granule.
+    fn check_granule(&self, base: usize, size: usize) -> Result<(), RegionError> {
+        match (base | size) & self.granule {
+            0 => Ok(()),
+            _ => Err(RegionError::Unaligned),
+        }
+    }
+    /// Apply the [`ZeroSize`] policy to an empty range.
+    fn empty_range(&self) -> Result<(), RegionError> {
+        match self.zero_size {
+            ZeroSize::Ignore => Ok(()),
+            ZeroSize::Reject => Err(RegionError::EmptyRange),
+        }
+    }
+    /// Check whether any region intersects the range from `base` to `end` under
+    /// the endpoint semantics in use.
+    fn overlaps(&self, base: usize, end: usize) -> bool {
+        match self.endpoints {
+            Endpoints::HalfOpen => {
+                base < end
+                    && (self.find_internal(base).is_some_and(|r| r.end() > base)
+                        || self.regions.range(base..end).next().is_some())
+            }
+            Endpoints::Closed => {
+                self.find_internal(base).is_some_and(|r| r.end() >= base)
+                    || self.regions.range(base..=end).next().is_some()
+            }
+        }
+    }
+    /// Find the lowest sub-range of `[base, end)` that no region covers.
+    pub(crate) fn first_gap(&self, base: usize, end: usize) -> Option<Region> {
+        let start = match self.find_internal(base) {
+            Some(r) if r.end() > base => r.end().min(end),
+            _ => base,
+        };
+        let stop = match self.regions.range(start..end).next() {
+            Some(r) => r.base,
+            None => end,
+        };
+        (start < end).then(|| Region {
+            base: start,
+            size: stop - start,
+        })
     }
-    fn insert_internal(&mut self, a: Region) {
-        self.regions.insert(a);
+    /// Find the region with the greatest base not above `addr`.
+    fn find_internal(&self, addr: usize) -> Option<Region> {
+        self.regions.range(..=addr).next_back()
     }
-    fn merge_internal(a: &mut Region, b: Region) -> Option<Region> {
+    /// Extend `a` to cover `b`, which is expected to touch it.
+    fn merge_internal(a: &mut Region, b: Region) {
         let a_end = a.base + a.size;
         let b_end = b.base + b.size;
-        if a_end < b.base || b_end < a.base {
-            return Some(b);
-        }
         let new_base = min(a.base, b.base);
         let new_end = max(a_end, b_end);
         let new_size = new_end - new_base;
         a.base = new_base;
         a.size = new_size;
-        None
     }
-    fn subtract_internal(target: Region, src: &mut Region) -> (Option<Region>, Option<Region>) {
+    fn subtract_internal(target: Region, src: &Region) -> (Option<Region>, Option<Region>) {
         let t_end = target.base + target.size;
         let s_end = src.base + src.size;
         let left = if src.base > target.base {
@@ -162,10 +906,402 @@ impl RegionAllocator {
     }
 }
 
+/// Sets hash by their regions, consistently with equality, so sets covering the same
+/// bytes hash alike whatever their storages and settings.
+impl<S: RegionStorage> Hash for RegionAllocator<S> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.regions.len());
+        self.regions.range(..).for_each(|r| r.hash(state));
+    }
+}
+
+/// Collect `(base, size)` pairs in any order, merging them as [`RegionAllocator::add`] does.
+impl<S: RegionStorage + Default> FromIterator<(usize, usize)> for RegionAllocator<S> {
+    fn from_iter<I: IntoIterator<Item = (usize, usize)>>(iter: I) -> Self {
+        let mut regions = RegionAllocator::default();
+        regions.extend(iter);
+        regions
+    }
+}
+
+/// Collect ranges in any order, merging them as [`RegionAllocator::add`] does.
+impl<S: RegionStorage + Default> FromIterator<Range<usize>> for RegionAllocator<S> {
+    fn from_iter<I: IntoIterator<Item = Range<usize>>>(iter: I) -> Self {
+        let mut regions = RegionAllocator::default();
+        regions.extend(iter);
+        regions
+    }
+}
+
+/// Build a set from `(base, size)` entries in any order with [`RegionAllocator::add_checked`],
+/// for tables where ranges are not supposed to overlap. Fails with
+/// [`RegionError::Overlapping`] on the first entry overlapping an earlier one and with
+/// [`RegionError::Overflow`] on one that does not fit in the address space; touching
+/// entries are merged.
+impl<S: RegionStorage + Default> TryFrom<&[(usize, usize)]> for RegionAllocator<S> {
+    type Error = RegionError;
+
+    fn try_from(entries: &[(usize, usize)]) -> Result<Self, RegionError> {
+        let mut regions = RegionAllocator::default();
+        for &(base, size) in entries {
+            regions.add_checked(base, size)?;
+        }
+        Ok(regions)
+    }
+}
+
+/// Add every `(base, size)` pair with [`RegionAllocator::add`], panicking as it does.
+impl<S: RegionStorage> Extend<(usize, usize)> for RegionAllocator<S> {
+    fn extend<I: IntoIterator<Item = (usize, usize)>>(&mut self, iter: I) {
+        iter.into_iter()
+            .for_each(|(base, size)| self.add(base, size));
+    }
+}
+
+/// Add every range with [`RegionAllocator::add`], panicking as it does.
+impl<S: RegionStorage> Extend<Range<usize>> for RegionAllocator<S> {
+    fn extend<I: IntoIterator<Item = Range<usize>>>(&mut self, iter: I) {
+        iter.into_iter()
+            .for_each(|range| self.add(range.start, range.len()));
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    #[cfg(feature = "alloc")]
     use super::RegionAllocator;
+    use super::{Region, RegionError, StaticRegionAllocator};
 
+    #[test]
+    fn static_test() {
+        let mut alloc = StaticRegionAllocator::<2>::default();
+        assert_eq!(alloc.try_add(0, 100), Ok(()));
+        assert_eq!(alloc.try_add(200, 100), Ok(()));
+        // Case 1: merging needs no extra room
+        assert_eq!(alloc.try_add(100, 20), Ok(()));
+        assert!(alloc.check_region(0, 120));
+        // Case 2: a third region does not fit
+        assert_eq!(alloc.try_add(400, 100), Err(RegionError::Capacity));
+        assert_eq!(alloc.try_subtract(10, 10), Err(RegionError::Capacity));
+        assert_eq!(alloc.allocate_by_addr(250, 10), Err(RegionError::Capacity));
+        assert!(alloc.check_region(0, 120));
+        assert!(alloc.check_region(200, 100));
+        // Case 3: trimming and removing still work when full
+        assert_eq!(alloc.allocate_by_size(100, 1), Ok((0, 100)));
+        assert_eq!(alloc.allocate_by_addr(200, 100), Ok(()));
+        assert_eq!(alloc.try_subtract(105, 5), Ok(()));
+        assert!(alloc.check_region(100, 5));
+        assert!(alloc.check_region(110, 10));
+    }
+    #[test]
+    fn region_test() {
+        let r = Region::new(0x1800, 0x2000);
+        assert!(r.contains(0x1800) && r.contains(0x37ff) && !r.contains(0x3800));
+        assert!(r.covers(&Region::new(0x2000, 0x1000)) && !r.covers(&Region::new(0x3000, 0x1000)));
+        // Case 1: intersections
+        let touching = Region::new(0x3800, 0x100);
+        assert!(r.is_adjacent(&touching) && touching.is_adjacent(&r));
+        assert!(!r.overlaps(&touching));
+        assert_eq!(r.intersect(&touching), None);
+        assert_eq!(
+            r.intersect(&Region::new(0x3000, 0x1000)),
+            Some(Region::new(0x3000, 0x800))
+        );
+        assert!(!r.is_adjacent(&Region::new(0x3000, 0x1000)));
+        // Case 2: alignment
+        assert_eq!(r.align_inward(0x1000), Some(Region::new(0x2000, 0x1000)));
+        assert_eq!(r.align_outward(0x1000), Some(Region::new(0x1000, 0x3000)));
+        assert_eq!(r.align_inward(0x2000), None);
+        assert_eq!(r.align_inward(3), None);
+        assert_eq!(Region::new(usize::MAX - 1, 1).align_outward(4), None);
+        assert_eq!(
+            Region::new(usize::MAX - 0x2800, 0x2800).align_inward(0x1000),
+            Some(Region::new(usize::MAX - 0x1fff, 0x1000))
+        );
+    }
+    #[test]
+    fn try_from_test() {
+        use core::convert::TryFrom;
+
+        let table = [(0x3000, 0x1000), (0, 0x1000), (0x1000, 0x800)];
+        let regions = StaticRegionAllocator::<4>::try_from(&table[..]).unwrap();
+        assert!(regions.check_region(0, 0x1800));
+        assert!(regions.check_region(0x3000, 0x1000));
+        // Case 2: overlapping, overflowing and excess entries
+        let overlapping = [(0, 0x1000), (0x2000, 0x1000), (0xfff, 0x10)];
+        let result = StaticRegionAllocator::<4>::try_from(&overlapping[..]);
+        assert_eq!(result.map(|r| r.len()), Err(RegionError::Overlapping));
+        let overflowing = [(usize::MAX, 2)];
+        let result = StaticRegionAllocator::<4>::try_from(&overflowing[..]);
+        assert_eq!(result.map(|r| r.len()), Err(RegionError::Overflow));
+        let many = [(0, 1), (2, 1), (4, 1)];
+        let result = StaticRegionAllocator::<2>::try_from(&many[..]);
+        assert_eq!(result.map(|r| r.len()), Err(RegionError::Capacity));
+    }
+    #[test]
+    fn from_iter_test() {
+        let map = [(0x3000, 0x1000), (0, 0x1000), (0x800, 0x1000), (0x5000, 0)];
+        let mut alloc: StaticRegionAllocator<4> = map.iter().copied().collect();
+        assert!(alloc.check_region(0, 0x1800));
+        assert!(alloc.check_region(0x3000, 0x1000));
+        assert_eq!(alloc.len(), 2);
+        // Ranges merge the same way
+        alloc.extend([0x1800..0x2000, 0x4000..0x5000, 0x6000..0x6000]);
+        assert!(alloc.check_region(0, 0x2000));
+        assert!(alloc.check_region(0x3000, 0x2000));
+        let ranges: StaticRegionAllocator<4> =
+            [0x3000..0x5000, 0..0x2000].iter().cloned().collect();
+        assert!(ranges == alloc);
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn add_checked_test() {
+        let mut alloc = RegionAllocator::new();
+        alloc.add(100, 100);
+        // Case 1: overlaps at either end or inside are rejected
+        assert_eq!(alloc.add_checked(50, 51), Err(RegionError::Overlapping));
+        assert_eq!(alloc.add_checked(199, 10), Err(RegionError::Overlapping));
+        assert_eq!(alloc.add_checked(120, 10), Err(RegionError::Overlapping));
+        assert_eq!(alloc.add_checked(0, 1000), Err(RegionError::Overlapping));
+        assert!(alloc.check_region(100, 100));
+        // Case 2: adjacent regions still merge
+        assert_eq!(alloc.add_checked(50, 50), Ok(()));
+        assert_eq!(alloc.add_checked(200, 50), Ok(()));
+        assert!(alloc.check_region(50, 200));
+        assert_eq!(alloc.len(), 1);
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn subtract_checked_test() {
+        let mut alloc = RegionAllocator::new();
+        alloc.add(100, 100);
+        alloc.add(300, 100);
+        // Case 1: ranges not wholly free are refused and nothing changes
+        assert_eq!(
+            alloc.subtract_checked(150, 100),
+            Err(RegionError::PartiallyCovered(Region {
+                base: 200,
+                size: 50
+            }))
+        );
+        assert_eq!(
+            alloc.subtract_checked(150, 200),
+            Err(RegionError::PartiallyCovered(Region {
+                base: 200,
+                size: 100
+            }))
+        );
+        assert_eq!(
+            alloc.subtract_checked(200, 100),
+            Err(RegionError::NotCovered)
+        );
+        assert!(alloc.check_region(100, 100));
+        assert!(alloc.check_region(300, 100));
+        // Case 2: covered ranges are subtracted
+        assert_eq!(alloc.subtract_checked(120, 30), Ok(()));
+        assert!(alloc.check_region(100, 20));
+        assert!(alloc.check_region(150, 50));
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn endpoints_test() {
+        use super::Endpoints;
+
+        let mut alloc = RegionAllocator::new();
+        alloc.add(100, 100);
+        // Case 1: half-open by default
+        assert!(alloc.check_point(100));
+        assert!(alloc.check_point(199));
+        assert!(!alloc.check_point(200));
+        assert_eq!(alloc.add_checked(200, 10), Ok(()));
+        assert_eq!(
+            alloc.subtract_checked(210, 10),
+            Err(RegionError::NotCovered)
+        );
+        // Case 2: closed regions include their end and overlap when touching
+        let mut alloc = alloc.with_endpoints(Endpoints::Closed);
+        assert!(alloc.check_point(210));
+        assert!(!alloc.check_point(211));
+        assert_eq!(alloc.add_checked(210, 10), Err(RegionError::Overlapping));
+        assert_eq!(alloc.add_checked(90, 10), Err(RegionError::Overlapping));
+        assert_eq!(
+            alloc.subtract_checked(210, 10),
+            Err(RegionError::PartiallyCovered(Region {
+                base: 210,
+                size: 10
+            }))
+        );
+        assert_eq!(alloc.add_checked(50, 10), Ok(()));
+        assert_eq!(alloc.len(), 2);
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn zero_size_test() {
+        use super::ZeroSize;
+
+        let mut alloc = RegionAllocator::new();
+        alloc.add(100, 100);
+        alloc.add(300, 100);
+        // Case 1: empty ranges are ignored by default
+        alloc.add(200, 0);
+        alloc.add(250, 0);
+        alloc.subtract(150, 0);
+        assert_eq!(alloc.len(), 2);
+        assert!(!alloc.check_region(250, 0));
+        assert!(alloc.check_region(100, 100));
+        assert_eq!(RegionAllocator::new().allocate_by_size(0, 1), Ok((0, 0)));
+        assert_eq!(alloc.allocate_by_size(0x10, 1), Ok((100, 0x10)));
+        assert_eq!(alloc.allocate_by_size(0, 1), Ok((0x74, 0)));
+        assert_eq!(alloc.allocate_by_size(0, 0x40), Ok((0x80, 0)));
+        assert_eq!(alloc.allocate_by_addr(0x90, 0), Ok(()));
+        assert_eq!(alloc.allocate_by_size(0x10, 1), Ok((0x74, 0x10)));
+        assert_eq!(alloc.stats().allocations, 2);
+        let failed = alloc.allocate_with(0, 1, |_, _| Err::<(), _>(RegionError::NoFit));
+        assert_eq!(failed, Err(RegionError::NoFit));
+        assert_eq!(alloc.stats().allocations, 2);
+        // Case 2: or rejected
+        let mut alloc = alloc.with_zero_size(ZeroSize::Reject);
+        assert_eq!(alloc.try_add(200, 0), Err(RegionError::EmptyRange));
+        assert_eq!(alloc.try_subtract(150, 0), Err(RegionError::EmptyRange));
+        assert_eq!(alloc.allocate_by_addr(150, 0), Err(RegionError::EmptyRange));
+        assert_eq!(alloc.allocate_by_size(0, 1), Err(RegionError::EmptyRange));
+        assert_eq!(alloc.len(), 2);
+    }
+    #[test]
+    fn preserved_spans_test() {
+        let mut alloc = StaticRegionAllocator::<4>::default();
+        alloc.add(0x200000, 0x200000);
+        alloc.add(0x500000, 0x2000);
+        let mut preserving = alloc.clone().with_preserved_spans(0x200000);
+        assert_eq!(
+            alloc.allocate_by_size(0x1000, 0x1000),
+            Ok((0x200000, 0x1000))
+        );
+        // Case 1: the sliver is used up before the whole span is cut into
+        assert_eq!(
+            preserving.allocate_by_size(0x1000, 0x1000),
+            Ok((0x500000, 0x1000))
+        );
+        assert_eq!(
+            preserving.allocate_by_size(0x1000, 0x1000),
+            Ok((0x501000, 0x1000))
+        );
+        assert_eq!(
+            preserving.allocate_by_size(0x1000, 0x1000),
+            Ok((0x200000, 0x1000))
+        );
+        // Case 2: then the head of the broken span is a sliver too
+        assert_eq!(
+            preserving.allocate_by_size(0x1000, 0x1000),
+            Ok((0x201000, 0x1000))
+        );
+        // Case 3: allocations as large as the span are placed as usual
+        let mut preserving = alloc.with_preserved_spans(0x200000 | 0x40000000);
+        preserving.add(0x200000, 0x1000);
+        assert_eq!(
+            preserving.allocate_by_size(0x200000, 1),
+            Ok((0x200000, 0x200000))
+        );
+    }
+    #[test]
+    fn granule_test() {
+        let mut alloc = StaticRegionAllocator::<4>::default().with_granule(0x1000);
+        assert_eq!(alloc.granule(), 0x1000);
+        alloc.add(0x1000, 0x4000);
+        // Case 1: ranges off the granule are rejected, leaving the set as is
+        assert_eq!(alloc.try_add(0x8000, 0x800), Err(RegionError::Unaligned));
+        assert_eq!(
+            alloc.try_subtract(0x1800, 0x1000),
+            Err(RegionError::Unaligned)
+        );
+        assert_eq!(alloc.add_checked(0x10, 0x1000), Err(RegionError::Unaligned));
+        assert_eq!(
+            alloc.allocate_by_addr(0x2000, 0x10),
+            Err(RegionError::Unaligned)
+        );
+        assert_eq!(alloc.allocate_by_size(0x10, 1), Err(RegionError::Unaligned));
+        assert!(alloc.check_region(0x1000, 0x4000) && alloc.len() == 1);
+        // Case 2: allocations by size are aligned to the granule
+        assert_eq!(alloc.allocate_by_addr(0x1000, 0x1000), Ok(()));
+        assert_eq!(alloc.allocate_by_size(0x1000, 1), Ok((0x2000, 0x1000)));
+        assert_eq!(alloc.clone().with_granule(1).try_add(0x8000, 0x800), Ok(()));
+    }
+    #[test]
+    fn validate_test() {
+        use super::{ArrayStorage, Region, RegionAllocator, RegionStorage, Violation};
+
+        let mut alloc = StaticRegionAllocator::<8>::default();
+        alloc.add(0, 10);
+        alloc.add(20, 10);
+        assert_eq!(alloc.validate(), Ok(()));
+        // A storage filled behind the allocator's back
+        let r = |base, size| Region { base, size };
+        let bad = [r(0, 10), r(10, 5), r(12, 0), r(5, 1), r(usize::MAX, 2)];
+        let mut storage = ArrayStorage::<8>::new();
+        storage.splice(0..=0, &bad).unwrap();
+        let alloc = RegionAllocator::with_storage(storage);
+        let mut violations = alloc.violations();
+        assert_eq!(violations.next(), Some(Violation::Adjacent(bad[0], bad[1])));
+        assert_eq!(violations.next(), Some(Violation::Empty(bad[2])));
+        assert_eq!(
+            violations.next(),
+            Some(Violation::Overlapping(bad[1], bad[2]))
+        );
+        assert_eq!(violations.next(), Some(Violation::Unsorted(bad[2], bad[3])));
+        assert_eq!(violations.next(), Some(Violation::Overflow(bad[4])));
+        assert_eq!(violations.next(), None);
+        assert_eq!(alloc.validate(), Err(Violation::Adjacent(bad[0], bad[1])));
+    }
+    #[test]
+    fn overflow_test() {
+        let mut alloc = StaticRegionAllocator::<4>::default();
+        let top = usize::MAX - 0x1000;
+        alloc.add(top, 0x1000);
+        assert!(alloc.check_region(top, 0x1000));
+        assert!(alloc.check_point(usize::MAX - 1));
+        // Case 1: ranges past the end of the address space are rejected
+        assert_eq!(
+            alloc.try_add(usize::MAX - 10, 11),
+            Err(RegionError::Overflow)
+        );
+        assert_eq!(
+            alloc.try_subtract(top, usize::MAX),
+            Err(RegionError::Overflow)
+        );
+        assert!(alloc.check_region(top, 0x1000));
+        // Case 2: aligning near the top does not wrap around
+        assert_eq!(
+            alloc.allocate_by_size(0x800, 0x1000),
+            Ok((usize::MAX - 0xfff, 0x800))
+        );
+        assert_eq!(
+            alloc.allocate_by_size(0x1000, 0x1000),
+            Err(RegionError::NoFit)
+        );
+        assert_eq!(alloc.allocate_by_addr(usize::MAX - 1, 1), Ok(()));
+        assert!(alloc.check_region(usize::MAX - 0x7ff, 0x7fe));
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn scratch_test() {
+        use super::{Region, SliceStorage};
+        use core::mem::MaybeUninit;
+
+        let mut scratch = [MaybeUninit::<Region>::uninit(); 4];
+        let mut early = RegionAllocator::with_storage(SliceStorage::new(&mut scratch));
+        early.add(0, 100);
+        early.add(200, 100);
+        early.subtract(50, 10);
+        assert_eq!(early.try_add(400, 10), Ok(()));
+        assert_eq!(early.try_add(500, 10), Err(RegionError::Capacity));
+        let mut alloc = early.into_storage(super::BTreeStorage::new()).unwrap();
+        alloc.add(500, 10);
+        assert_eq!(alloc.len(), 5);
+        assert!(alloc.check_region(0, 50));
+        assert!(alloc.check_region(60, 40));
+        assert!(alloc.check_region(500, 10));
+    }
+    #[cfg(feature = "alloc")]
     #[test]
     fn add_test_2() {
         let mut alloc = RegionAllocator::new();
@@ -176,6 +1312,7 @@ mod tests {
         alloc.add(500, 100);
         assert!(alloc.check_region(0, 700));
     }
+    #[cfg(feature = "alloc")]
     #[test]
     fn add_test() {
         let mut alloc = RegionAllocator::new();
@@ -207,6 +1344,7 @@ mod tests {
         alloc.add(500, 100);
         assert!(alloc.check_region(0, 700));
     }
+    #[cfg(feature = "alloc")]
     #[test]
     fn sub_test() {
         let mut alloc = RegionAllocator::new();
@@ -238,6 +1376,7 @@ mod tests {
         assert!(alloc.check_region(400, 100));
         assert_eq!(alloc.len(), 3);
     }
+    #[cfg(feature = "alloc")]
     #[test]
     fn alloc_test() {
         let mut alloc = RegionAllocator::new();
@@ -245,17 +1384,79 @@ mod tests {
         alloc.add(200, 300);
         alloc.add(600, 200);
         // Case 1: successful alloc
-        assert_eq!(alloc.allocate_by_addr(10, 10), true);
-        assert_eq!(alloc.allocate_by_size(12, 1 << 3), Some((24, 12)));
+        assert_eq!(alloc.allocate_by_addr(10, 10), Ok(()));
+        assert_eq!(alloc.allocate_by_size(12, 1 << 3), Ok((24, 12)));
         // Case 2: invalid args
-        assert_eq!(alloc.allocate_by_size(1, 9), None);
+        assert_eq!(
+            alloc.allocate_by_size(1, 9),
+            Err(RegionError::InvalidAlignment)
+        );
+        assert_eq!(
+            alloc.allocate_by_addr(usize::MAX, 2),
+            Err(RegionError::Overflow)
+        );
         // Case 3: unsuccessful alloc
-        assert_eq!(alloc.allocate_by_addr(0, 20), false);
-        assert_eq!(alloc.allocate_by_addr(30, 20), false);
-        assert_eq!(alloc.allocate_by_size(400, 1), None);
-        assert_eq!(alloc.allocate_by_size(300, 1 << 5), None);
+        assert_eq!(
+            alloc.allocate_by_addr(0, 20),
+            Err(RegionError::PartiallyCovered(Region { base: 10, size: 10 }))
+        );
+        assert_eq!(
+            alloc.allocate_by_addr(30, 20),
+            Err(RegionError::PartiallyCovered(Region { base: 30, size: 6 }))
+        );
+        assert_eq!(
+            alloc.allocate_by_addr(100, 50),
+            Err(RegionError::NotCovered)
+        );
+        assert_eq!(alloc.allocate_by_size(400, 1), Err(RegionError::NoFit));
+        assert_eq!(alloc.allocate_by_size(300, 1 << 5), Err(RegionError::NoFit));
         // Change regions and alloc again
         alloc.add(500, 100);
-        assert_eq!(alloc.allocate_by_size(400, 1 << 6), Some((256, 400)));
+        assert_eq!(alloc.allocate_by_size(400, 1 << 6), Ok((256, 400)));
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn alloc_cursor_test() {
+        let mut alloc = RegionAllocator::new();
+        alloc.add(0, 1000);
+        assert_eq!(alloc.allocate_by_size(100, 1), Ok((0, 100)));
+        assert_eq!(alloc.allocate_by_size(100, 1), Ok((100, 100)));
+        // A freed hole below the cursor is not preferred
+        alloc.add(0, 100);
+        assert_eq!(alloc.allocate_by_size(100, 1), Ok((200, 100)));
+        // Fall back to the lowest fit once the cached region is exhausted
+        assert_eq!(alloc.allocate_by_size(700, 1), Ok((300, 700)));
+        assert_eq!(alloc.allocate_by_size(100, 1), Ok((0, 100)));
+        assert!(alloc.is_empty());
+        // First fit takes the freed hole below the cursor
+        let mut alloc = RegionAllocator::new().with_fit_policy(super::FitPolicy::First);
+        alloc.add(0, 1000);
+        assert_eq!(alloc.allocate_by_size(100, 1), Ok((0, 100)));
+        alloc.add(0, 100);
+        assert_eq!(alloc.allocate_by_size(100, 1), Ok((0, 100)));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn alloc_paired_test() {
+        let mut virt = RegionAllocator::new();
+        let mut phys = RegionAllocator::new();
+        virt.add(0x10000, 0x10000);
+        phys.add(0x1000, 0x3000);
+        assert_eq!(
+            virt.allocate_paired(&mut phys, 0x2000, 0x8000, 0x1000),
+            Ok((0x10000, 0x1000))
+        );
+        // A failing second allocation gives the first one back
+        assert_eq!(
+            virt.allocate_paired(&mut phys, 0x2000, 0x8000, 0x1000),
+            Err(RegionError::NoFit)
+        );
+        assert!(virt.check_region(0x12000, 0xe000));
+        assert!(phys.check_region(0x3000, 0x1000));
+        assert_eq!(
+            virt.allocate_paired(&mut phys, 0x1000, 0x8000, 0x1000),
+            Ok((0x18000, 0x3000))
+        );
     }
 }
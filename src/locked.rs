@@ -0,0 +1,334 @@
+//! A spinlock-protected [`RegionAllocator`] for sharing between cores.
+
+#[cfg(feature = "alloc")]
+use crate::BTreeStorage;
+use crate::{
+    HeapFreeStorage, RegionAllocator, RegionError, RegionStats, RegionStorage, Watermarks,
+};
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A [`RegionAllocator`] behind a spinlock, usable through a shared reference.
+///
+/// The lock does not poison: a panic while it is held simply releases it on unwind,
+/// so the allocator stays usable from panic and crash paths.
+///
+/// With the `log` or `tracing` feature, the events of an operation are emitted while the
+/// lock is held, so a logger or subscriber must not allocate from, or otherwise lock, the
+/// same allocator, or it spins forever.
+pub struct LockedRegionAllocator<
+    #[cfg(feature = "alloc")] S = BTreeStorage,
+    #[cfg(not(feature = "alloc"))] S,
+> {
+    locked: AtomicBool,
+    inner: UnsafeCell<RegionAllocator<S>>,
+}
+
+// SAFETY: access to `inner` is serialized by `locked`.
+unsafe impl<S: Send> Sync for LockedRegionAllocator<S> {}
+
+/// Hooks masking interrupts on the current CPU, supplied by the platform.
+///
+/// See [`LockedRegionAllocator::with_irqs_disabled`].
+pub trait Interrupts {
+    /// Whatever is needed to restore the previous interrupt state, such as saved flags.
+    type State;
+
+    /// Disable interrupts and return the state before.
+    fn disable() -> Self::State;
+    /// Restore a state returned by [`Interrupts::disable`].
+    fn restore(state: Self::State);
+}
+
+/// Restores the interrupt state on drop, including on unwind.
+struct IrqGuard<I: Interrupts>(Option<I::State>);
+
+impl<I: Interrupts> Drop for IrqGuard<I> {
+    fn drop(&mut self) {
+        if let Some(state) = self.0.take() {
+            I::restore(state);
+        }
+    }
+}
+
+/// Exclusive access to the [`RegionAllocator`] inside a [`LockedRegionAllocator`].
+pub struct LockGuard<'a, S> {
+    lock: &'a LockedRegionAllocator<S>,
+}
+
+impl<S> Deref for LockGuard<'_, S> {
+    type Target = RegionAllocator<S>;
+
+    fn deref(&self) -> &RegionAllocator<S> {
+        // SAFETY: the guard holds the lock.
+        unsafe { &*self.lock.inner.get() }
+    }
+}
+
+impl<S> DerefMut for LockGuard<'_, S> {
+    fn deref_mut(&mut self) -> &mut RegionAllocator<S> {
+        // SAFETY: the guard holds the lock.
+        unsafe { &mut *self.lock.inner.get() }
+    }
+}
+
+impl<S> Drop for LockGuard<'_, S> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+impl<S> LockedRegionAllocator<S> {
+    /// Wrap a [`RegionAllocator`].
+    pub const fn new(inner: RegionAllocator<S>) -> Self {
+        LockedRegionAllocator {
+            locked: AtomicBool::new(false),
+            inner: UnsafeCell::new(inner),
+        }
+    }
+    /// Unwrap the [`RegionAllocator`].
+    pub fn into_inner(self) -> RegionAllocator<S> {
+        self.inner.into_inner()
+    }
+    /// Spin until the lock is acquired.
+    pub fn lock(&self) -> LockGuard<'_, S> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            while self.locked.load(Ordering::Relaxed) {
+                spin_loop();
+            }
+        }
+    }
+    /// Run `f` with interrupts disabled through `I` and the lock held.
+    ///
+    /// An interrupt handler on this CPU can then never spin on the lock held by the code
+    /// it interrupted. The lock is released before interrupts are restored.
+    pub fn with_irqs_disabled<I: Interrupts, R>(
+        &self,
+        f: impl FnOnce(&mut RegionAllocator<S>) -> R,
+    ) -> R {
+        let _irqs = IrqGuard::<I>(Some(I::disable()));
+        let mut guard = self.lock();
+        let r = f(&mut guard);
+        drop(guard);
+        r
+    }
+    /// Acquire the lock if it is free, without spinning.
+    pub fn try_lock(&self) -> Option<LockGuard<'_, S>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| LockGuard { lock: self })
+    }
+}
+
+impl<S: RegionStorage> LockedRegionAllocator<S> {
+    /// See [`RegionAllocator::add`].
+    pub fn add(&self, base: usize, size: usize) {
+        self.lock().add(base, size)
+    }
+    /// See [`RegionAllocator::try_add`].
+    pub fn try_add(&self, base: usize, size: usize) -> Result<(), RegionError> {
+        self.lock().try_add(base, size)
+    }
+    /// See [`RegionAllocator::subtract`].
+    pub fn subtract(&self, base: usize, size: usize) {
+        self.lock().subtract(base, size)
+    }
+    /// See [`RegionAllocator::try_subtract`].
+    pub fn try_subtract(&self, base: usize, size: usize) -> Result<(), RegionError> {
+        self.lock().try_subtract(base, size)
+    }
+    /// See [`RegionAllocator::add_or_subtract`].
+    pub fn add_or_subtract(&self, base: usize, size: usize, is_add: bool) {
+        self.lock().add_or_subtract(base, size, is_add)
+    }
+    /// See [`RegionAllocator::allocate_by_addr`].
+    pub fn allocate_by_addr(&self, base: usize, size: usize) -> Result<(), RegionError> {
+        self.lock().allocate_by_addr(base, size)
+    }
+    /// See [`RegionAllocator::allocate_by_size`].
+    pub fn allocate_by_size(
+        &self,
+        size: usize,
+        alignment: usize,
+    ) -> Result<(usize, usize), RegionError> {
+        self.lock().allocate_by_size(size, alignment)
+    }
+    /// See [`RegionAllocator::deallocate`].
+    pub fn deallocate(&self, base: usize, size: usize) -> Result<(), RegionError> {
+        self.lock().deallocate(base, size)
+    }
+    /// See [`RegionAllocator::stats`].
+    pub fn stats(&self) -> RegionStats {
+        self.lock().stats()
+    }
+    /// See [`RegionAllocator::watermarks`].
+    pub fn watermarks(&self) -> Watermarks {
+        self.lock().watermarks()
+    }
+    /// See [`RegionAllocator::check_region`].
+    pub fn check_region(&self, base: usize, size: usize) -> bool {
+        self.lock().check_region(base, size)
+    }
+    /// See [`RegionAllocator::check_point`].
+    pub fn check_point(&self, addr: usize) -> bool {
+        self.lock().check_point(addr)
+    }
+    /// See [`RegionAllocator::len`].
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+    /// See [`RegionAllocator::is_empty`].
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+}
+
+impl<S: HeapFreeStorage> LockedRegionAllocator<S> {
+    /// Like [`RegionAllocator::allocate_by_size`], but fail at once with
+    /// [`RegionError::WouldBlock`] if the lock is held elsewhere.
+    ///
+    /// Neither spinning nor heap allocation happens, so this is safe to call from an
+    /// interrupt handler that may have preempted the lock holder.
+    pub fn try_allocate_by_size(
+        &self,
+        size: usize,
+        alignment: usize,
+    ) -> Result<(usize, usize), RegionError> {
+        let mut regions = self.try_lock().ok_or(RegionError::WouldBlock)?;
+        regions.allocate_by_size(size, alignment)
+    }
+    /// Like [`RegionAllocator::allocate_by_addr`], but fail at once with
+    /// [`RegionError::WouldBlock`] if the lock is held elsewhere.
+    pub fn try_allocate_by_addr(&self, base: usize, size: usize) -> Result<(), RegionError> {
+        let mut regions = self.try_lock().ok_or(RegionError::WouldBlock)?;
+        regions.allocate_by_addr(base, size)
+    }
+}
+
+impl<S: Default> Default for LockedRegionAllocator<S> {
+    fn default() -> Self {
+        LockedRegionAllocator::new(RegionAllocator::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Interrupts, LockedRegionAllocator};
+    use crate::{ArrayStorage, RegionAllocator, RegionError};
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    static ALLOC: LockedRegionAllocator<ArrayStorage<8>> =
+        LockedRegionAllocator::new(RegionAllocator::with_storage(ArrayStorage::new()));
+
+    static ENABLED: AtomicBool = AtomicBool::new(true);
+
+    struct FakeIrqs;
+
+    impl Interrupts for FakeIrqs {
+        type State = bool;
+
+        fn disable() -> bool {
+            ENABLED.swap(false, Ordering::SeqCst)
+        }
+        fn restore(state: bool) {
+            assert!(ALLOC.try_lock().is_some(), "restored with the lock held");
+            ENABLED.store(state, Ordering::SeqCst);
+        }
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn log_test() {
+        extern crate std;
+
+        static REGIONS: LockedRegionAllocator<ArrayStorage<2>> =
+            LockedRegionAllocator::new(RegionAllocator::with_storage(ArrayStorage::new()));
+        static HELD: AtomicBool = AtomicBool::new(false);
+
+        /// Checks that the lock is held while the failure below is logged.
+        struct Logger;
+
+        impl log::Log for Logger {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+            fn log(&self, record: &log::Record) {
+                if std::format!("{}", record.args()).contains("0x7770000") {
+                    HELD.store(REGIONS.try_lock().is_none(), Ordering::SeqCst);
+                }
+            }
+            fn flush(&self) {}
+        }
+
+        log::set_logger(&Logger).unwrap();
+        log::set_max_level(log::LevelFilter::Warn);
+        REGIONS.add(0, 0x1000);
+        assert_eq!(
+            REGIONS.allocate_by_size(0x777_0000, 1),
+            Err(RegionError::NoFit)
+        );
+        assert!(HELD.load(Ordering::SeqCst));
+        assert_eq!(REGIONS.allocate_by_size(0x1000, 1), Ok((0, 0x1000)));
+    }
+
+    #[test]
+    fn locked_test() {
+        extern crate std;
+
+        ALLOC.add(0, 0x10000);
+        // Case 1: concurrent allocations are disjoint
+        let threads: std::vec::Vec<_> = (0..4)
+            .map(|_| {
+                std::thread::spawn(|| {
+                    (0..16)
+                        .map(|_| ALLOC.allocate_by_size(0x100, 0x100).unwrap().0)
+                        .collect::<std::vec::Vec<_>>()
+                })
+            })
+            .collect();
+        let mut bases: std::vec::Vec<_> = threads
+            .into_iter()
+            .flat_map(|t| t.join().unwrap())
+            .collect();
+        bases.sort_unstable();
+        bases.dedup();
+        assert_eq!(bases.len(), 64);
+        assert!(ALLOC.check_region(0x4000, 0xc000));
+        // Case 2: contended try-variants fail without spinning
+        let guard = ALLOC.lock();
+        assert!(ALLOC.try_lock().is_none());
+        assert_eq!(
+            ALLOC.try_allocate_by_size(0x100, 0x100),
+            Err(RegionError::WouldBlock)
+        );
+        assert_eq!(
+            ALLOC.try_allocate_by_addr(0x4000, 0x100),
+            Err(RegionError::WouldBlock)
+        );
+        drop(guard);
+        assert_eq!(
+            ALLOC.try_allocate_by_size(0x100, 0x100),
+            Ok((0x4000, 0x100))
+        );
+        assert_eq!(ALLOC.try_allocate_by_addr(0x4100, 0x100), Ok(()));
+        // Case 3: the lock is released on unwind
+        let _ = std::panic::catch_unwind(|| {
+            let _guard = ALLOC.lock();
+            panic!();
+        });
+        assert!(ALLOC.try_lock().is_some());
+        // Case 4: interrupts are masked around the locked access
+        let taken = ALLOC.with_irqs_disabled::<FakeIrqs, _>(|r| {
+            assert!(!ENABLED.load(Ordering::SeqCst));
+            r.allocate_by_size(0x100, 0x100)
+        });
+        assert_eq!(taken, Ok((0x4200, 0x100)));
+        assert!(ENABLED.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,156 @@
+//! A per-CPU cache of fixed-size blocks in front of a shared [`LockedRegionAllocator`].
+
+use crate::{LockedRegionAllocator, RegionStorage};
+
+/// A per-CPU stack of up to `N` cached blocks of one size taken from a
+/// [`LockedRegionAllocator`].
+///
+/// Each CPU owns its own [`Magazine`], so allocating and freeing only take the central
+/// lock when the magazine runs empty or full, and then move half a magazine at once.
+/// Dropping a [`Magazine`] flushes the blocks it still caches.
+pub struct Magazine<'a, S: RegionStorage, const N: usize> {
+    central: &'a LockedRegionAllocator<S>,
+    size: usize,
+    alignment: usize,
+    blocks: [usize; N],
+    len: usize,
+}
+
+impl<'a, S: RegionStorage, const N: usize> Magazine<'a, S, N> {
+    /// Create an empty [`Magazine`] of blocks of `size` bytes aligned to `alignment`.
+    ///
+    /// Return `None` if `size` is 0, `alignment` is not a power of 2, or `N` is 0.
+    pub fn new(
+        central: &'a LockedRegionAllocator<S>,
+        size: usize,
+        alignment: usize,
+    ) -> Option<Self> {
+        if size == 0 || !alignment.is_power_of_two() || N == 0 {
+            return None;
+        }
+        Some(Magazine {
+            central,
+            size,
+            alignment,
+            blocks: [0; N],
+            len: 0,
+        })
+    }
+    /// Return number of blocks cached.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// Check whether no block is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Allocate a block, refilling from the central allocator if the magazine is empty.
+    pub fn allocate(&mut self) -> Option<usize> {
+        if self.len == 0 {
+            self.refill(N.div_ceil(2));
+        }
+        self.len = self.len.checked_sub(1)?;
+        Some(self.blocks[self.len])
+    }
+    /// Free a block returned by [`Magazine::allocate`], possibly from another [`Magazine`]
+    /// of the same size, flushing half the magazine first if it is full.
+    pub fn deallocate(&mut self, base: usize) {
+        if self.len == N {
+            self.flush_to(N / 2);
+        }
+        self.blocks[self.len] = base;
+        self.len += 1;
+    }
+    /// Return every cached block to the central allocator.
+    pub fn flush(&mut self) {
+        self.flush_to(0);
+    }
+
+    fn refill(&mut self, count: usize) {
+        let mut central = self.central.lock();
+        while self.len < count {
+            match central.allocate_by_size(self.size, self.alignment) {
+                Ok((base, _)) => self.blocks[self.len] = base,
+                Err(_) => break,
+            }
+            self.len += 1;
+        }
+    }
+    fn flush_to(&mut self, len: usize) {
+        let mut central = self.central.lock();
+        while self.len > len {
+            if central
+                .deallocate(self.blocks[self.len - 1], self.size)
+                .is_err()
+            {
+                break;
+            }
+            self.len -= 1;
+        }
+    }
+}
+
+impl<S: RegionStorage, const N: usize> Drop for Magazine<'_, S, N> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    extern crate std;
+
+    use super::Magazine;
+    use crate::LockedRegionAllocator;
+    use std::sync::Barrier;
+    use std::vec::Vec;
+
+    static CENTRAL: LockedRegionAllocator =
+        LockedRegionAllocator::new(crate::RegionAllocator::new());
+
+    #[test]
+    fn magazine_test() {
+        CENTRAL.add(0, 0x10000);
+        {
+            let mut magazine = Magazine::<_, 8>::new(&CENTRAL, 0x100, 0x100).unwrap();
+            // Case 1: the first allocation takes half a magazine
+            assert_eq!(magazine.allocate(), Some(0x300));
+            assert_eq!(magazine.len(), 3);
+            assert!(CENTRAL.check_region(0x400, 0xfc00));
+            // Case 2: a full magazine flushes half of it
+            let blocks: Vec<_> = (0..7).map(|_| magazine.allocate().unwrap()).collect();
+            magazine.deallocate(0x300);
+            blocks.iter().for_each(|&b| magazine.deallocate(b));
+            assert_eq!(magazine.len(), 8);
+            let (extra, _) = CENTRAL.allocate_by_size(0x100, 0x100).unwrap();
+            magazine.deallocate(extra);
+            assert_eq!(magazine.len(), 5);
+        }
+        assert!(CENTRAL.check_region(0, 0x10000));
+        // Case 3: one magazine per thread
+        let barrier = Barrier::new(4);
+        let mut blocks: Vec<_> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut magazine = Magazine::<_, 4>::new(&CENTRAL, 0x100, 0x100).unwrap();
+                        let blocks: Vec<_> =
+                            (0..16).map(|_| magazine.allocate().unwrap()).collect();
+                        barrier.wait();
+                        blocks.iter().for_each(|&b| magazine.deallocate(b));
+                        blocks
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .flat_map(|t| t.join().unwrap())
+                .collect()
+        });
+        blocks.sort_unstable();
+        blocks.dedup();
+        assert_eq!(blocks.len(), 64);
+        assert!(CENTRAL.check_region(0, 0x10000));
+        assert_eq!(CENTRAL.stats().allocated_bytes, 0);
+    }
+}
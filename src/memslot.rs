@@ -0,0 +1,136 @@
+//! Guest physical memory slots, as a hypervisor registers them for a VM.
+
+use crate::{RegionAllocator, RegionError};
+use alloc::collections::BTreeMap;
+use core::ops::Range;
+
+/// A slot found by [`MemorySlots::translate`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SlotOffset<'a, T> {
+    /// The guest physical address the slot starts at.
+    pub base: usize,
+    /// The offset of the translated address in the slot.
+    pub offset: usize,
+    pub data: &'a T,
+}
+
+/// The guest physical memory slots of a VM, each carrying its own metadata, such as the
+/// host address backing it or its dirty-logging state.
+///
+/// Unlike regions in a [`RegionAllocator`], slots never merge: touching slots stay apart,
+/// and a slot overlapping any other, even by a byte, is refused.
+#[derive(Clone, Debug)]
+pub struct MemorySlots<T> {
+    slots: BTreeMap<usize, (usize, T)>,
+    /// The addresses any slot covers.
+    occupied: RegionAllocator,
+}
+
+impl<T> Default for MemorySlots<T> {
+    fn default() -> Self {
+        MemorySlots::new()
+    }
+}
+
+impl<T> MemorySlots<T> {
+    /// Create an empty set of slots.
+    pub const fn new() -> Self {
+        MemorySlots {
+            slots: BTreeMap::new(),
+            occupied: RegionAllocator::new(),
+        }
+    }
+    /// Return number of slots.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+    /// Register a slot of `size` bytes from guest physical address `base`.
+    ///
+    /// Fail with [`RegionError::Overlapping`] if it overlaps a registered slot.
+    pub fn register(&mut self, base: usize, size: usize, data: T) -> Result<(), RegionError> {
+        if size == 0 {
+            return Err(RegionError::EmptyRange);
+        }
+        self.occupied.add_checked(base, size)?;
+        self.slots.insert(base, (size, data));
+        Ok(())
+    }
+    /// Unregister the slot starting at `base`, returning its size and data.
+    pub fn unregister(&mut self, base: usize) -> Option<(usize, T)> {
+        let (size, data) = self.slots.remove(&base)?;
+        self.occupied.subtract(base, size);
+        Some((size, data))
+    }
+    /// Translate a guest physical address to the slot covering it and the offset in it.
+    pub fn translate(&self, gpa: usize) -> Option<SlotOffset<'_, T>> {
+        let (&base, (size, data)) = self.slots.range(..=gpa).next_back()?;
+        (gpa - base < *size).then_some(SlotOffset {
+            base,
+            offset: gpa - base,
+            data,
+        })
+    }
+    /// Iterate over the slots overlapping `size` bytes from `base`, in ascending order.
+    pub fn intersecting(
+        &self,
+        base: usize,
+        size: usize,
+    ) -> impl Iterator<Item = (Range<usize>, &T)> + '_ {
+        let end = base.saturating_add(size);
+        let first = match self.translate(base) {
+            Some(slot) => slot.base,
+            None => base,
+        };
+        let slots = self.slots.range(first..end).filter(move |_| size > 0);
+        slots.map(|(&base, (size, data))| (base..base + size, data))
+    }
+    /// Iterate over every slot in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (Range<usize>, &T)> + '_ {
+        let slots = self.slots.iter();
+        slots.map(|(&base, (size, data))| (base..base + size, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemorySlots, SlotOffset};
+    use crate::RegionError;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn memslot_test() {
+        let mut slots = MemorySlots::new();
+        slots.register(0, 0xa0000, "low").unwrap();
+        slots.register(0xa0000, 0x20000, "vga").unwrap();
+        slots.register(0x100000, 0x100000, "high").unwrap();
+        // Case 1: touching slots stay apart, overlapping ones are refused
+        assert_eq!(slots.len(), 3);
+        assert_eq!(
+            slots.register(0xbf000, 0x2000, "bad"),
+            Err(RegionError::Overlapping)
+        );
+        // Case 2: translation finds the slot and the offset
+        let slot = SlotOffset {
+            base: 0xa0000,
+            offset: 0x8000,
+            data: &"vga",
+        };
+        assert_eq!(slots.translate(0xa8000), Some(slot));
+        assert_eq!(slots.translate(0xc0000), None);
+        // Case 3: slots intersecting a range
+        let found: Vec<_> = slots
+            .intersecting(0x9f000, 0x62000)
+            .map(|(_, d)| *d)
+            .collect();
+        assert_eq!(found, ["low", "vga", "high"]);
+        assert_eq!(slots.intersecting(0xc0000, 0x40000).count(), 0);
+        assert_eq!(slots.intersecting(0x1000, 0).count(), 0);
+        // Case 4: unregistered ranges can be registered again
+        assert_eq!(slots.unregister(0xa0000), Some((0x20000, "vga")));
+        assert_eq!(slots.register(0xbf000, 0x2000, "rom"), Ok(()));
+        assert_eq!(slots.iter().count(), 3);
+    }
+}
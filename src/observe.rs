@@ -0,0 +1,110 @@
+//! Hooks told about every change to a region set.
+
+use crate::{Region, RegionAllocator, RegionError, RegionStorage};
+
+/// A receiver of the changes made to a [`RegionAllocator`], set with
+/// [`RegionAllocator::with_observer`], such as to mirror the set into IOMMU page
+/// tables.
+///
+/// Each method is called after the change is made, and only if it succeeds. An
+/// allocation is reported both as the subtraction that takes the region and as the
+/// allocation itself, and a deallocation as the addition that gives it back. The
+/// methods take `&self` as the set may be shared between cores; observers that keep
+/// state synchronize it themselves.
+pub trait Observer: Sync {
+    /// `region` was added to the set.
+    fn added(&self, _region: Region) {}
+    /// `region` was subtracted from the set, whether or not all of it was in the set.
+    fn subtracted(&self, _region: Region) {}
+    /// `region` was allocated.
+    fn allocated(&self, _region: Region) {}
+    /// An allocation of `size` bytes failed with `error`.
+    fn allocation_failed(&self, _size: usize, _error: RegionError) {}
+    /// The set was rebuilt in one go by a set operation, such as
+    /// [`RegionAllocator::clamp`], and should be read again.
+    fn rebuilt(&self) {}
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Tell `observer` about every change made to the set from now on.
+    pub const fn with_observer(mut self, observer: &'static dyn Observer) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    pub(crate) fn notify(&self, f: impl FnOnce(&dyn Observer)) {
+        if let Some(observer) = self.observer {
+            f(observer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Observer;
+    use crate::{Region, RegionError, StaticRegionAllocator};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts the bytes of each kind of change.
+    struct Tally([AtomicUsize; 5]);
+
+    impl Observer for Tally {
+        fn added(&self, region: Region) {
+            self.0[0].fetch_add(region.size, Ordering::Relaxed);
+        }
+        fn subtracted(&self, region: Region) {
+            self.0[1].fetch_add(region.size, Ordering::Relaxed);
+        }
+        fn allocated(&self, region: Region) {
+            self.0[2].fetch_add(region.size, Ordering::Relaxed);
+        }
+        fn allocation_failed(&self, size: usize, _: RegionError) {
+            self.0[3].fetch_add(size, Ordering::Relaxed);
+        }
+        fn rebuilt(&self) {
+            self.0[4].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    impl Tally {
+        fn get(&self) -> [usize; 5] {
+            let mut tally = [0; 5];
+            for (t, n) in tally.iter_mut().zip(&self.0) {
+                *t = n.load(Ordering::Relaxed);
+            }
+            tally
+        }
+    }
+
+    #[test]
+    fn observer_test() {
+        static TALLY: Tally = Tally([
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+        ]);
+        let mut regions = StaticRegionAllocator::<4>::default().with_observer(&TALLY);
+        // Case 1: additions and subtractions with the ranges given
+        regions.add(0, 0x4000);
+        regions.subtract(0x3000, 0x2000);
+        assert_eq!(TALLY.get(), [0x4000, 0x2000, 0, 0, 0]);
+        // Case 2: allocations also report the subtraction taking them
+        assert_eq!(regions.allocate_by_size(0x1000, 0x1000), Ok((0, 0x1000)));
+        assert_eq!(
+            regions.allocate_by_addr(0x8000, 0x800),
+            Err(RegionError::NotCovered)
+        );
+        assert_eq!(regions.deallocate(0, 0x1000), Ok(()));
+        assert_eq!(TALLY.get(), [0x5000, 0x3000, 0x1000, 0x800, 0]);
+        // Case 3: failed changes are not reported, rebuilt sets are
+        assert!(regions.try_add(usize::MAX, 2).is_err());
+        assert_eq!(regions.clamp(0, 0x2000), Ok(()));
+        assert_eq!(TALLY.get(), [0x5000, 0x3000, 0x1000, 0x800, 1]);
+        // Case 4: regions dropped by retain are reported as subtracted
+        regions.add(0x3000, 0x1000);
+        regions.retain(|base, _| base != 0);
+        assert_eq!(TALLY.get(), [0x6000, 0x5000, 0x1000, 0x800, 1]);
+    }
+}
@@ -0,0 +1,293 @@
+//! Region set changes as values, to compute and replay deltas between sets.
+
+use crate::{Region, RegionAllocator, RegionError, RegionStorage};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::iter;
+
+/// A change to a region set.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Op {
+    /// Add the region, as [`RegionAllocator::add`] does.
+    Add(Region),
+    /// Subtract the region, as [`RegionAllocator::subtract`] does.
+    Subtract(Region),
+    /// Allocate the region, as [`RegionAllocator::allocate_by_addr`] does.
+    AllocateAddr(Region),
+    /// Allocate a region of `size` bytes, as [`RegionAllocator::allocate_by_size`] does.
+    ///
+    /// Replayed on the same set, this allocates the same region again.
+    AllocateSize { size: usize, alignment: usize },
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Apply `ops` in order, stopping at the first one that fails.
+    ///
+    /// Additions and subtractions go through [`RegionAllocator::try_add`] and
+    /// [`RegionAllocator::try_subtract`], so nothing panics; the operations before the
+    /// failing one stay applied, and the failing one leaves the set unchanged.
+    pub fn apply(&mut self, ops: impl IntoIterator<Item = Op>) -> Result<(), RegionError> {
+        ops.into_iter().try_for_each(|op| match op {
+            Op::Add(r) => self.try_add(r.base, r.size),
+            Op::Subtract(r) => self.try_subtract(r.base, r.size),
+            Op::AllocateAddr(r) => self.allocate_by_addr(r.base, r.size),
+            Op::AllocateSize { size, alignment } => {
+                self.allocate_by_size(size, alignment).map(drop)
+            }
+        })
+    }
+    /// Apply `op` like [`RegionAllocator::apply`], appending the operations undoing it to
+    /// `undo` and returning the region it allocated, if any.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn apply_undoable(
+        &mut self,
+        op: Op,
+        undo: &mut Vec<Op>,
+    ) -> Result<Option<Region>, RegionError> {
+        let end = |r: Region| r.base.checked_add(r.size).ok_or(RegionError::Overflow);
+        let taken = match op {
+            Op::Add(r) => {
+                let undone: Vec<_> = self.undo_add(r.base, end(r)?).collect();
+                self.try_add(r.base, r.size)?;
+                undo.extend(undone);
+                return Ok(None);
+            }
+            Op::Subtract(r) => {
+                let undone: Vec<_> = self.undo_subtract(r.base, end(r)?).collect();
+                self.try_subtract(r.base, r.size)?;
+                undo.extend(undone);
+                return Ok(None);
+            }
+            Op::AllocateAddr(r) => self.allocate_by_addr(r.base, r.size).map(|()| r)?,
+            Op::AllocateSize { size, alignment } => {
+                let (base, size) = self.allocate_by_size(size, alignment)?;
+                Region { base, size }
+            }
+        };
+        undo.push(Op::Add(taken));
+        Ok(Some(taken))
+    }
+    /// Replay operations logged by [`RegionAllocator::apply_undoable`] backwards.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn undo(&mut self, undo: impl DoubleEndedIterator<Item = Op>) {
+        // Every step back restores a set the storage held before
+        for op in undo.rev() {
+            let undone = match op {
+                Op::Add(r) => self.try_add(r.base, r.size),
+                Op::Subtract(r) => self.try_subtract(r.base, r.size),
+                _ => unreachable!("only additions and subtractions are logged"),
+            };
+            undone.expect("restoring an earlier set needs no more room than it had");
+        }
+    }
+    /// Return the operations undoing an addition of `[base, end)`: the subtraction of
+    /// each gap of the set it would fill.
+    #[cfg(feature = "alloc")]
+    fn undo_add(&self, base: usize, end: usize) -> impl Iterator<Item = Op> + '_ {
+        let mut addr = base;
+        iter::from_fn(move || {
+            let gap = self.first_gap(addr, end)?;
+            addr = gap.end();
+            Some(Op::Subtract(gap))
+        })
+    }
+    /// Return the operations undoing a subtraction of `[base, end)`: the addition of
+    /// each part of the set it would remove.
+    #[cfg(feature = "alloc")]
+    fn undo_subtract(&self, base: usize, end: usize) -> impl Iterator<Item = Op> + '_ {
+        let window = Region {
+            base,
+            size: end - base,
+        };
+        let first = self.find_internal(base).map_or(base, |r| r.base);
+        let inside = self.regions.range(first..end);
+        inside
+            .filter_map(move |r| r.intersect(&window))
+            .map(Op::Add)
+    }
+    /// Return the fewest operations turning the set into `new`, in ascending address
+    /// order.
+    ///
+    /// Subtractions cover only bytes missing from `new` and additions only bytes in it, so
+    /// no two operations overlap and they may be applied in any order. Within that, each
+    /// one spans as much as it can: two regions that disappear with nothing between them
+    /// take a single subtraction, and an addition runs over bytes the set already has.
+    pub fn diff<'a, T: RegionStorage>(
+        &'a self,
+        new: &'a RegionAllocator<T>,
+    ) -> impl Iterator<Item = Op> + 'a {
+        let mut changes = self.changes(new).peekable();
+        iter::from_fn(move || {
+            let mut op = changes.next()?;
+            while let Some(joined) = changes.peek().and_then(|&next| self.join(op, next)) {
+                op = joined;
+                changes.next();
+            }
+            Some(op)
+        })
+    }
+    /// Join two operations of [`RegionAllocator::diff`] into one spanning the range
+    /// between them, if that range is the same in both sets and the joined operation
+    /// leaves it so.
+    fn join(&self, op: Op, next: Op) -> Option<Op> {
+        let span = |a: Region, b: Region| Region {
+            base: a.base,
+            size: b.end() - a.base,
+        };
+        match (op, next) {
+            (Op::Subtract(a), Op::Subtract(b)) => {
+                let between = Region {
+                    base: a.end(),
+                    size: b.base - a.end(),
+                };
+                let empty = self.first_gap(between.base, b.base) == Some(between);
+                empty.then(|| Op::Subtract(span(a, b)))
+            }
+            (Op::Add(a), Op::Add(b)) => {
+                let full = self.first_gap(a.end(), b.base).is_none();
+                full.then(|| Op::Add(span(a, b)))
+            }
+            _ => None,
+        }
+    }
+    /// Return the operations of [`RegionAllocator::diff`] before they are joined, each
+    /// covering a maximal range where the two sets differ.
+    fn changes<'a, T: RegionStorage>(
+        &'a self,
+        new: &'a RegionAllocator<T>,
+    ) -> impl Iterator<Item = Op> + 'a {
+        let mut old = self.regions.range(..).peekable();
+        let mut new = new.regions.range(..).peekable();
+        let mut pos = 0;
+        iter::from_fn(move || loop {
+            while old.next_if(|r| r.end() <= pos).is_some() {}
+            while new.next_if(|r| r.end() <= pos).is_some() {}
+            let start = pos;
+            let (op, stop): (fn(Region) -> Op, _) = match (old.peek(), new.peek()) {
+                (None, None) => return None,
+                (Some(o), Some(n)) if o.base <= pos && n.base <= pos => {
+                    pos = min(o.end(), n.end());
+                    continue;
+                }
+                (Some(o), n) if o.base <= pos => {
+                    (Op::Subtract, n.map_or(o.end(), |n| min(o.end(), n.base)))
+                }
+                (o, Some(n)) if n.base <= pos => {
+                    (Op::Add, o.map_or(n.end(), |o| min(n.end(), o.base)))
+                }
+                (o, n) => {
+                    pos = min(
+                        o.map_or(usize::MAX, |o| o.base),
+                        n.map_or(usize::MAX, |n| n.base),
+                    );
+                    continue;
+                }
+            };
+            pos = stop;
+            return Some(op(Region {
+                base: start,
+                size: stop - start,
+            }));
+        })
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::Op;
+    use crate::{ArrayStorage, Region, RegionAllocator, RegionError};
+    use alloc::vec::Vec;
+
+    #[test]
+    fn diff_test() {
+        let mut old = RegionAllocator::new();
+        old.add(0, 0x1000);
+        old.add(0x2000, 0x2000);
+        old.add(0x6000, 0x1000);
+        old.add(usize::MAX - 0x100, 0x100);
+        let mut new = RegionAllocator::with_storage(ArrayStorage::<4>::new());
+        new.add(0, 0x1000);
+        new.add(0x3000, 0x2000);
+        new.add(0x6800, 0x1000);
+        new.add(usize::MAX - 0x80, 0x80);
+        let ops: Vec<_> = old.diff(&new).collect();
+        let r = |base, size| Region { base, size };
+        assert_eq!(
+            ops,
+            [
+                Op::Subtract(r(0x2000, 0x1000)),
+                Op::Add(r(0x4000, 0x1000)),
+                Op::Subtract(r(0x6000, 0x800)),
+                Op::Add(r(0x7000, 0x800)),
+                Op::Subtract(r(usize::MAX - 0x100, 0x80)),
+            ]
+        );
+        // Replaying the delta reproduces the new set
+        assert_eq!(old.apply(ops), Ok(()));
+        assert!(old == new);
+        assert_eq!(old.diff(&new).next(), None);
+        // Operations of the same kind are joined across ranges alike in both sets
+        let mut old = RegionAllocator::new();
+        old.add(0x1000, 0x1000);
+        old.add(0x3000, 0x1000);
+        old.add(0x6000, 0x1000);
+        let mut new = RegionAllocator::new();
+        new.add(0x5000, 0x3000);
+        let ops: Vec<_> = old.diff(&new).collect();
+        assert_eq!(
+            ops,
+            [Op::Subtract(r(0x1000, 0x3000)), Op::Add(r(0x5000, 0x3000))]
+        );
+        assert_eq!(old.apply(ops), Ok(()));
+        assert!(old == new);
+    }
+
+    #[test]
+    fn apply_test() {
+        let ops = [
+            Op::Add(Region {
+                base: 0,
+                size: 0x4000,
+            }),
+            Op::AllocateAddr(Region {
+                base: 0x1000,
+                size: 0x1000,
+            }),
+            Op::AllocateSize {
+                size: 0x800,
+                alignment: 0x800,
+            },
+            Op::Subtract(Region {
+                base: 0x3000,
+                size: 0x800,
+            }),
+        ];
+        let mut a = RegionAllocator::new();
+        let mut b = RegionAllocator::with_storage(ArrayStorage::<4>::new());
+        assert_eq!(a.apply(ops), Ok(()));
+        assert_eq!(b.apply(ops), Ok(()));
+        assert!(a == b);
+        assert!(a.check_region(0x800, 0x800) && a.check_region(0x2000, 0x1000));
+        // Replay stops at the first failure
+        let more = [
+            Op::Add(Region {
+                base: 0x5000,
+                size: 0x1000,
+            }),
+            Op::AllocateAddr(Region {
+                base: 0,
+                size: 0x1000,
+            }),
+            Op::Add(Region {
+                base: 0x7000,
+                size: 0x1000,
+            }),
+        ];
+        assert!(matches!(
+            a.apply(more),
+            Err(RegionError::PartiallyCovered(_))
+        ));
+        assert!(a.check_region(0x5000, 0x1000) && !a.check_point(0x7000));
+    }
+}
@@ -0,0 +1,71 @@
+//! Parallel bulk construction of a [`RegionAllocator`].
+
+use crate::{CapacityError, Region, RegionAllocator, RegionStorage};
+use core::cmp::max;
+use rayon::prelude::*;
+use std::vec::Vec;
+
+/// Ranges merged by one task before the per-task results are stitched together.
+const CHUNK: usize = 1 << 16;
+
+impl<S: RegionStorage + Default> RegionAllocator<S> {
+    /// Build a [`RegionAllocator`] holding the union of `ranges`, given as `(base, size)`
+    /// pairs in any order, sorting and merging them on the rayon thread pool.
+    ///
+    /// The result is the same as adding every range in turn, empty ones being ignored,
+    /// but millions of ranges take a fraction of the time.
+    pub fn par_from_ranges(mut ranges: Vec<(usize, usize)>) -> Result<Self, CapacityError> {
+        ranges.retain(|&(_, size)| size != 0);
+        ranges.par_sort_unstable_by_key(|&(base, _)| base);
+        let chunks: Vec<Vec<Region>> = ranges
+            .par_chunks(CHUNK)
+            .map(|chunk| merge(chunk.iter().map(|&(base, size)| Region { base, size })))
+            .collect();
+        let regions = merge(chunks.into_iter().flatten());
+        let mut storage = S::default();
+        for r in regions {
+            storage.splice(r.base..=r.base, &[r])?;
+        }
+        Ok(RegionAllocator::with_storage(storage))
+    }
+}
+
+/// Merge regions sorted by base into disjoint, non-adjacent ones.
+fn merge(sorted: impl Iterator<Item = Region>) -> Vec<Region> {
+    let mut merged: Vec<Region> = Vec::new();
+    for r in sorted {
+        match merged.last_mut() {
+            Some(last) if r.base <= last.end() => last.size = max(last.end(), r.end()) - last.base,
+            _ => merged.push(r),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{RegionAllocator, RegionStorage, VecStorage};
+    use std::vec::Vec;
+
+    #[test]
+    fn par_from_ranges_test() {
+        let mut x = 0x9e3779b97f4a7c15u64;
+        let mut next = move || {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x
+        };
+        // More ranges than one chunk, overlapping within and across chunks
+        let ranges: Vec<_> = (0..200_000)
+            .map(|_| ((next() % 0x1000_0000) as usize, (next() % 0x1000) as usize))
+            .collect();
+        let mut expected = RegionAllocator::with_storage(VecStorage::new());
+        ranges
+            .iter()
+            .for_each(|&(base, size)| expected.add(base, size));
+        let built = RegionAllocator::<VecStorage>::par_from_ranges(ranges).unwrap();
+        assert_eq!(built.len(), expected.len());
+        assert!(built.regions.range(..).eq(expected.regions.range(..)));
+    }
+}
@@ -0,0 +1,216 @@
+//! Region sets written as text, such as reserved ranges in configuration files.
+
+use crate::{RegionAllocator, RegionError, RegionStorage};
+use core::fmt;
+use core::str::FromStr;
+
+/// The reason a region list could not be parsed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ParseErrorKind {
+    /// An entry is neither `start..end`, `start..=end` nor `base+size`.
+    InvalidEntry,
+    /// A number has no digits, a digit its base does not have, or an unknown suffix.
+    InvalidNumber,
+    /// A number or range does not fit in the address space.
+    Overflow,
+    /// A range ends below its start.
+    Reversed,
+    /// Adding the range to the set failed.
+    Region(RegionError),
+}
+
+/// An error parsing a region list, pointing at the offending entry or number.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ParseError {
+    /// Byte offset of the entry or number in the input.
+    pub position: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ParseErrorKind::InvalidEntry => f.write_str("expected start..end or base+size")?,
+            ParseErrorKind::InvalidNumber => f.write_str("invalid number")?,
+            ParseErrorKind::Overflow => f.write_str("range overflows the address space")?,
+            ParseErrorKind::Reversed => f.write_str("range ends below its start")?,
+            ParseErrorKind::Region(e) => write!(f, "{}", e)?,
+        }
+        write!(f, " at byte {}", self.position)
+    }
+}
+
+impl core::error::Error for ParseError {}
+
+/// Parse a comma-separated list of ranges, each written as `start..end`, `start..=end` or
+/// `base+size`, such as `"0x1000..0x9f000, 0x100000+0x7ee0000"`.
+///
+/// Numbers are decimal or, with a `0x`, `0o` or `0b` prefix, hexadecimal, octal or binary.
+/// They may contain `_` separators and end in `K`, `M`, `G` or `T` for binary multiples.
+/// Ranges may overlap or touch and are merged as [`RegionAllocator::add`] merges them.
+impl<S: RegionStorage + Default> FromStr for RegionAllocator<S> {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        let mut regions = RegionAllocator::default();
+        if s.trim().is_empty() {
+            return Ok(regions);
+        }
+        for entry in s.split(',') {
+            let (base, size) = parse_entry(s, entry)?;
+            regions.try_add(base, size).map_err(|e| ParseError {
+                position: offset(s, entry.trim_start()),
+                kind: ParseErrorKind::Region(e),
+            })?;
+        }
+        Ok(regions)
+    }
+}
+
+/// Return the byte offset of `part` in `s`, which it is a slice of.
+fn offset(s: &str, part: &str) -> usize {
+    part.as_ptr() as usize - s.as_ptr() as usize
+}
+
+fn parse_entry(s: &str, entry: &str) -> Result<(usize, usize), ParseError> {
+    let entry = entry.trim();
+    let error = |part: &str, kind| ParseError {
+        position: offset(s, part),
+        kind,
+    };
+    let (start, rest, inclusive) = if let Some((start, end)) = entry.split_once("..=") {
+        (start, end, true)
+    } else if let Some((start, end)) = entry.split_once("..") {
+        (start, end, false)
+    } else if let Some((base, size)) = entry.split_once('+') {
+        let base_value = parse_number(s, base)?;
+        let size = parse_number(s, size)?;
+        base_value
+            .checked_add(size)
+            .ok_or(error(entry, ParseErrorKind::Overflow))?;
+        return Ok((base_value, size));
+    } else {
+        return Err(error(entry, ParseErrorKind::InvalidEntry));
+    };
+    let (start_value, end) = (parse_number(s, start)?, parse_number(s, rest)?);
+    let end = match inclusive {
+        true => end
+            .checked_add(1)
+            .ok_or(error(entry, ParseErrorKind::Overflow))?,
+        false => end,
+    };
+    match end.checked_sub(start_value) {
+        Some(size) => Ok((start_value, size)),
+        None => Err(error(entry, ParseErrorKind::Reversed)),
+    }
+}
+
+fn parse_number(s: &str, number: &str) -> Result<usize, ParseError> {
+    let number = number.trim();
+    let error = |kind| ParseError {
+        position: offset(s, number),
+        kind,
+    };
+    let (radix, digits) = match number.get(..2) {
+        Some("0x" | "0X") => (16, &number[2..]),
+        Some("0o" | "0O") => (8, &number[2..]),
+        Some("0b" | "0B") => (2, &number[2..]),
+        _ => (10, number),
+    };
+    let (digits, shift) = match digits.as_bytes().last() {
+        Some(b'K') => (&digits[..digits.len() - 1], 10),
+        Some(b'M') => (&digits[..digits.len() - 1], 20),
+        Some(b'G') => (&digits[..digits.len() - 1], 30),
+        Some(b'T') => (&digits[..digits.len() - 1], 40),
+        _ => (digits, 0),
+    };
+    let mut value = 0usize;
+    let mut any = false;
+    for c in digits.chars().filter(|&c| c != '_') {
+        let digit = c
+            .to_digit(radix)
+            .ok_or(error(ParseErrorKind::InvalidNumber))?;
+        value = value
+            .checked_mul(radix as usize)
+            .and_then(|v| v.checked_add(digit as usize))
+            .ok_or(error(ParseErrorKind::Overflow))?;
+        any = true;
+    }
+    if !any {
+        return Err(error(ParseErrorKind::InvalidNumber));
+    }
+    1usize
+        .checked_shl(shift)
+        .and_then(|unit| value.checked_mul(unit))
+        .ok_or(error(ParseErrorKind::Overflow))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParseError, ParseErrorKind};
+    use crate::{RegionError, StaticRegionAllocator};
+
+    type Regions = StaticRegionAllocator<4>;
+
+    fn error(position: usize, kind: ParseErrorKind) -> Result<Regions, ParseError> {
+        Err(ParseError { position, kind })
+    }
+
+    #[test]
+    fn parse_test() {
+        let regions: Regions = "0x1000..0x9f000, 0x100000+0x7ee0000".parse().unwrap();
+        assert!(regions.check_region(0x1000, 0x9e000));
+        assert!(regions.check_region(0x100000, 0x7ee0000));
+        // Case 1: inclusive ends, suffixes, separators, other bases, and merging
+        let regions: Regions = " 4K..=0x1fff,8K+4K ,0b1_0000_0000_0000_0000..1M, 0o40000000+ 1G"
+            .parse()
+            .unwrap();
+        assert!(regions.check_region(0x1000, 0x2000));
+        assert!(regions.check_region(0x10000, 0xf0000));
+        assert!(regions.check_region(0x80_0000, 0x4000_0000));
+        assert_eq!(regions.len(), 3);
+        assert!("".parse::<Regions>().is_ok_and(|r| r.is_empty()));
+        // Case 2: errors point at the offending entry or number
+        let parse = |s: &str| s.parse::<Regions>();
+        assert_eq!(
+            parse("0x1000..0x2000, 0x3000"),
+            error(16, ParseErrorKind::InvalidEntry)
+        );
+        assert_eq!(
+            parse("0x1000..0x2000,"),
+            error(15, ParseErrorKind::InvalidEntry)
+        );
+        assert_eq!(
+            parse("0x1000..0xg000"),
+            error(8, ParseErrorKind::InvalidNumber)
+        );
+        assert_eq!(parse("0x..1"), error(0, ParseErrorKind::InvalidNumber));
+        assert_eq!(
+            parse("1..2, 20X+1"),
+            error(6, ParseErrorKind::InvalidNumber)
+        );
+        assert_eq!(parse("0x2000..0x1000"), error(0, ParseErrorKind::Reversed));
+        assert_eq!(
+            parse("1+0xffff_ffff_ffff_ffff_ff"),
+            error(2, ParseErrorKind::Overflow)
+        );
+        assert_eq!(parse(" 2..=0T"), error(1, ParseErrorKind::Reversed));
+        assert_eq!(
+            parse("0..1, 2..3, 4..5, 6..7, 8..9"),
+            error(24, ParseErrorKind::Region(RegionError::Capacity))
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn parse_error_display_test() {
+        use alloc::string::ToString;
+
+        let e = "0x1000..0x2000, 0x3000".parse::<Regions>().unwrap_err();
+        assert_eq!(e.to_string(), "expected start..end or base+size at byte 16");
+        let e = "0..1, 2..3, 4..5, 6..7, 8..9"
+            .parse::<Regions>()
+            .unwrap_err();
+        assert_eq!(e.to_string(), "storage is out of capacity at byte 24");
+    }
+}
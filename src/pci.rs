@@ -0,0 +1,210 @@
+//! PCI resource assignment: BARs and bridge windows from I/O and memory pools.
+
+#[cfg(feature = "alloc")]
+use crate::BTreeStorage;
+use crate::{RegionAllocator, RegionError, RegionStorage};
+
+/// The granule of I/O windows behind a PCI-to-PCI bridge.
+pub const IO_WINDOW_GRANULE: usize = 0x1000;
+/// The granule of memory windows, prefetchable or not, behind a PCI-to-PCI bridge.
+pub const MEMORY_WINDOW_GRANULE: usize = 0x10_0000;
+
+/// The address space a BAR or a bridge window decodes.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PciResource {
+    Io,
+    Memory,
+    /// Prefetchable memory, which may also be placed in non-prefetchable memory.
+    Prefetchable,
+}
+
+impl PciResource {
+    /// Return the smallest BAR of this kind.
+    const fn min_bar(self) -> usize {
+        match self {
+            PciResource::Io => 4,
+            PciResource::Memory | PciResource::Prefetchable => 16,
+        }
+    }
+    /// Return the granule of bridge windows of this kind.
+    pub const fn window_granule(self) -> usize {
+        match self {
+            PciResource::Io => IO_WINDOW_GRANULE,
+            PciResource::Memory | PciResource::Prefetchable => MEMORY_WINDOW_GRANULE,
+        }
+    }
+}
+
+/// The I/O, memory and prefetchable memory pools of a host bridge or a bridge window.
+///
+/// BARs are sized to a power of 2 and aligned to their size; bridge windows are rounded
+/// to their granule. A prefetchable request falls back on the memory pool once the
+/// prefetchable pool has no room, never the other way around.
+#[derive(Clone, Debug, Default)]
+pub struct PciAllocator<
+    #[cfg(feature = "alloc")] S: RegionStorage = BTreeStorage,
+    #[cfg(not(feature = "alloc"))] S: RegionStorage,
+> {
+    io: RegionAllocator<S>,
+    memory: RegionAllocator<S>,
+    prefetchable: RegionAllocator<S>,
+}
+
+impl<S: RegionStorage> PciAllocator<S> {
+    /// Assign resources from the given free I/O, memory and prefetchable memory.
+    pub fn new(
+        io: RegionAllocator<S>,
+        memory: RegionAllocator<S>,
+        prefetchable: RegionAllocator<S>,
+    ) -> Self {
+        PciAllocator {
+            io,
+            memory,
+            prefetchable,
+        }
+    }
+    /// Return the pool of `kind`.
+    pub fn pool(&self, kind: PciResource) -> &RegionAllocator<S> {
+        match kind {
+            PciResource::Io => &self.io,
+            PciResource::Memory => &self.memory,
+            PciResource::Prefetchable => &self.prefetchable,
+        }
+    }
+    /// Return the pool of `kind`, to add or reserve ranges.
+    pub fn pool_mut(&mut self, kind: PciResource) -> &mut RegionAllocator<S> {
+        match kind {
+            PciResource::Io => &mut self.io,
+            PciResource::Memory => &mut self.memory,
+            PciResource::Prefetchable => &mut self.prefetchable,
+        }
+    }
+    /// Allocate `size` bytes aligned to `alignment` from the pool of `kind`, falling back
+    /// on the memory pool for prefetchable requests.
+    fn allocate(
+        &mut self,
+        kind: PciResource,
+        size: usize,
+        alignment: usize,
+    ) -> Result<(usize, PciResource), RegionError> {
+        match self.pool_mut(kind).allocate_by_size(size, alignment) {
+            Ok((base, _)) => Ok((base, kind)),
+            Err(RegionError::NoFit) if kind == PciResource::Prefetchable => {
+                let (base, _) = self.memory.allocate_by_size(size, alignment)?;
+                Ok((base, PciResource::Memory))
+            }
+            Err(e) => Err(e),
+        }
+    }
+    /// Assign a BAR of `size` bytes, rounded up to a power of 2 and to the smallest BAR
+    /// of its kind, at an address aligned to that size.
+    ///
+    /// Return the base, the rounded size, and the pool it came from.
+    pub fn allocate_bar(
+        &mut self,
+        kind: PciResource,
+        size: usize,
+    ) -> Result<(usize, usize, PciResource), RegionError> {
+        let size = size
+            .max(kind.min_bar())
+            .checked_next_power_of_two()
+            .ok_or(RegionError::Overflow)?;
+        let (base, pool) = self.allocate(kind, size, size)?;
+        Ok((base, size, pool))
+    }
+    /// Assign a bridge window of `size` bytes, rounded up to the window granule, aligned
+    /// to `alignment` or the granule, whichever is larger.
+    ///
+    /// `alignment` is typically that of the largest BAR behind the bridge. Return the
+    /// base, the rounded size, and the pool it came from.
+    pub fn allocate_window(
+        &mut self,
+        kind: PciResource,
+        size: usize,
+        alignment: usize,
+    ) -> Result<(usize, usize, PciResource), RegionError> {
+        if !alignment.is_power_of_two() {
+            return Err(RegionError::InvalidAlignment);
+        }
+        let granule = kind.window_granule();
+        let size = size
+            .max(1)
+            .checked_add(granule - 1)
+            .ok_or(RegionError::Overflow)?
+            & !(granule - 1);
+        let (base, pool) = self.allocate(kind, size, alignment.max(granule))?;
+        Ok((base, size, pool))
+    }
+    /// Give back a BAR or a window to the pool it came from.
+    pub fn release(
+        &mut self,
+        pool: PciResource,
+        base: usize,
+        size: usize,
+    ) -> Result<(), RegionError> {
+        self.pool_mut(pool).add_checked(base, size)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::{PciAllocator, PciResource};
+    use crate::{RegionAllocator, RegionError};
+
+    fn pool(base: usize, size: usize) -> RegionAllocator {
+        let mut pool = RegionAllocator::new();
+        pool.add(base, size);
+        pool
+    }
+
+    #[test]
+    fn pci_test() {
+        let mut pci = PciAllocator::new(
+            pool(0x1000, 0xf000),
+            pool(0xc000_0000, 0x1000_0000),
+            pool(0xe000_0000, 0x20_0000),
+        );
+        // Case 1: BARs are naturally aligned powers of 2
+        assert_eq!(
+            pci.allocate_bar(PciResource::Memory, 0x100),
+            Ok((0xc000_0000, 0x100, PciResource::Memory))
+        );
+        assert_eq!(
+            pci.allocate_bar(PciResource::Memory, 0x3000),
+            Ok((0xc000_4000, 0x4000, PciResource::Memory))
+        );
+        assert_eq!(
+            pci.allocate_bar(PciResource::Io, 1),
+            Ok((0x1000, 4, PciResource::Io))
+        );
+        // Case 2: windows are rounded to their granule
+        assert_eq!(
+            pci.allocate_window(PciResource::Io, 0x10, 4),
+            Ok((0x2000, 0x1000, PciResource::Io))
+        );
+        assert_eq!(
+            pci.allocate_window(PciResource::Memory, 0x1234, 0x20_0000),
+            Ok((0xc020_0000, 0x10_0000, PciResource::Memory))
+        );
+        // Case 3: prefetchable requests fall back on memory, not the reverse
+        assert_eq!(
+            pci.allocate_bar(PciResource::Prefetchable, 0x20_0000),
+            Ok((0xe000_0000, 0x20_0000, PciResource::Prefetchable))
+        );
+        assert_eq!(
+            pci.allocate_bar(PciResource::Prefetchable, 0x10_0000),
+            Ok((0xc030_0000, 0x10_0000, PciResource::Memory))
+        );
+        assert_eq!(
+            pci.release(PciResource::Prefetchable, 0xe000_0000, 0x20_0000),
+            Ok(())
+        );
+        assert_eq!(
+            pci.allocate_bar(PciResource::Memory, 0x1000_0000),
+            Err(RegionError::NoFit)
+        );
+        assert!(pci
+            .pool(PciResource::Prefetchable)
+            .check_region(0xe000_0000, 0x20_0000));
+    }
+}
@@ -0,0 +1,150 @@
+//! Per-CPU areas carved from a region set.
+
+use crate::{RegionAllocator, RegionError, RegionStorage};
+
+/// How per-CPU areas are laid out in the block carved for them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PerCpuLayout {
+    /// Areas follow each other, each rounded up to the alignment.
+    Contiguous,
+    /// Areas start `stride` bytes apart, which must be a multiple of the alignment no
+    /// smaller than an area. The gaps between them stay in the set.
+    Strided(usize),
+}
+
+/// The bases of the per-CPU areas returned by [`RegionAllocator::allocate_per_cpu`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PerCpuAreas {
+    base: usize,
+    stride: usize,
+    size: usize,
+    cpus: usize,
+}
+
+impl PerCpuAreas {
+    /// Return the base of the area of `cpu`, or `None` if there are not that many CPUs.
+    pub fn base_of(&self, cpu: usize) -> Option<usize> {
+        (cpu < self.cpus).then(|| self.base + self.stride * cpu)
+    }
+    /// Return the distance between the bases of neighbouring areas.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+    /// Return the size of each area.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+    /// Return the number of areas.
+    pub fn cpus(&self) -> usize {
+        self.cpus
+    }
+    /// Iterate over the bases of the areas, in CPU order.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = usize> + '_ {
+        (0..self.cpus).map(move |cpu| self.base + self.stride * cpu)
+    }
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Allocate an area of `size` bytes aligned to `alignment` for each of `cpus` CPUs,
+    /// laid out as `layout` says within one block.
+    ///
+    /// With [`PerCpuLayout::Strided`], a stride that is not a multiple of `alignment` or
+    /// is smaller than `size` fails with [`RegionError::InvalidAlignment`]. The set is left
+    /// unchanged on failure.
+    pub fn allocate_per_cpu(
+        &mut self,
+        size: usize,
+        alignment: usize,
+        cpus: usize,
+        layout: PerCpuLayout,
+    ) -> Result<PerCpuAreas, RegionError> {
+        if !alignment.is_power_of_two() {
+            return Err(RegionError::InvalidAlignment);
+        }
+        let stride = match layout {
+            PerCpuLayout::Contiguous => {
+                size.checked_add(alignment - 1)
+                    .ok_or(RegionError::Overflow)?
+                    & !(alignment - 1)
+            }
+            PerCpuLayout::Strided(stride) if stride.is_multiple_of(alignment) && stride >= size => {
+                stride
+            }
+            PerCpuLayout::Strided(_) => return Err(RegionError::InvalidAlignment),
+        };
+        // The last area needs no padding after it
+        let span = match cpus {
+            0 => 0,
+            n => stride
+                .checked_mul(n - 1)
+                .and_then(|s| s.checked_add(size))
+                .ok_or(RegionError::Overflow)?,
+        };
+        let cursor = self.cursor;
+        let (base, _) = self.allocate_by_size(span, alignment)?;
+        let areas = PerCpuAreas {
+            base,
+            stride,
+            size,
+            cpus,
+        };
+        if stride > size {
+            for (i, area) in areas.iter().enumerate().take(cpus.saturating_sub(1)) {
+                if let Err(e) = self.deallocate(area + size, stride - size) {
+                    // Putting the block back restores the earlier set, which fitted in the storage.
+                    let _ = self.try_add(base, span);
+                    self.cursor = cursor;
+                    self.counters.revert(span - i * (stride - size));
+                    return Err(e);
+                }
+            }
+        }
+        Ok(areas)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PerCpuLayout;
+    use crate::{RegionError, StaticRegionAllocator};
+
+    #[test]
+    fn per_cpu_test() {
+        let mut alloc = StaticRegionAllocator::<8>::default();
+        alloc.add(0x1000, 0x10000);
+        let areas = alloc
+            .allocate_per_cpu(0x300, 0x100, 4, PerCpuLayout::Contiguous)
+            .unwrap();
+        assert_eq!(areas.stride(), 0x300);
+        assert_eq!(areas.base_of(3), Some(0x1900));
+        assert_eq!(areas.base_of(4), None);
+        assert!(alloc.check_region(0x1c00, 0xf400));
+        // Case 1: strided areas leave their gaps in the set
+        let areas = alloc
+            .allocate_per_cpu(0x800, 0x1000, 3, PerCpuLayout::Strided(0x2000))
+            .unwrap();
+        let mut bases = areas.iter();
+        assert_eq!(
+            (bases.next(), bases.next(), bases.len()),
+            (Some(0x2000), Some(0x4000), 1)
+        );
+        assert!(alloc.check_region(0x2800, 0x1800) && alloc.check_region(0x4800, 0x1800));
+        assert!(!alloc.check_point(0x6000) && alloc.check_point(0x6800));
+        // Case 2: bad strides, and a gap the storage has no room for
+        assert_eq!(
+            alloc.allocate_per_cpu(0x800, 0x1000, 2, PerCpuLayout::Strided(0x1800)),
+            Err(RegionError::InvalidAlignment)
+        );
+        assert_eq!(
+            alloc.allocate_per_cpu(0x2000, 0x1000, 2, PerCpuLayout::Strided(0x1000)),
+            Err(RegionError::InvalidAlignment)
+        );
+        let mut full = StaticRegionAllocator::<1>::default();
+        full.add(0, 0x10000);
+        assert_eq!(
+            full.allocate_per_cpu(0x800, 0x1000, 4, PerCpuLayout::Strided(0x1000)),
+            Err(RegionError::Capacity)
+        );
+        assert!(full.check_region(0, 0x10000));
+    }
+}
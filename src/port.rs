@@ -0,0 +1,152 @@
+//! 16-bit port numbers with an ephemeral range and delayed reuse.
+
+use crate::{IdAllocator, RegionError, RegionStorage};
+use core::ops::RangeInclusive;
+
+/// An allocator of port numbers from 1 to 65535.
+///
+/// Ephemeral ports are searched from a random start in the ephemeral range, so
+/// successive connections do not get predictable ports. Freed ports wait `reuse_delay`
+/// ticks, in whatever unit the caller counts time, before they can be handed out again;
+/// up to `N` ports wait at once, and the oldest is released early when more are freed.
+pub struct PortAllocator<S: RegionStorage, const N: usize> {
+    free: IdAllocator<S>,
+    ephemeral: RangeInclusive<u16>,
+    reuse_delay: u64,
+    state: u64,
+    delayed: [(u16, u64); N],
+    head: usize,
+    len: usize,
+}
+
+impl<S: RegionStorage, const N: usize> PortAllocator<S, N> {
+    /// Create an allocator with every port free, on top of a given empty storage.
+    ///
+    /// `seed` drives the choice of ephemeral ports and should differ between boots.
+    pub fn new(
+        storage: S,
+        ephemeral: RangeInclusive<u16>,
+        reuse_delay: u64,
+        seed: u64,
+    ) -> Result<Self, RegionError> {
+        let mut free = IdAllocator::with_storage(storage);
+        free.add_pool(1..0x10000)?;
+        Ok(PortAllocator {
+            free,
+            ephemeral,
+            reuse_delay,
+            state: seed | 1,
+            delayed: [(0, 0); N],
+            head: 0,
+            len: 0,
+        })
+    }
+    /// Check whether `port` can be allocated at tick `now`.
+    pub fn is_free(&mut self, port: u16, now: u64) -> bool {
+        self.release(now);
+        self.free.is_free(port.into())
+    }
+    /// Return number of freed ports still waiting out the reuse delay.
+    pub fn delayed(&self) -> usize {
+        self.len
+    }
+    /// Allocate a given port at tick `now`, failing with [`RegionError::NotCovered`] if
+    /// it is in use or waiting out the reuse delay.
+    pub fn allocate(&mut self, port: u16, now: u64) -> Result<(), RegionError> {
+        self.release(now);
+        self.free.allocate_id(port.into())
+    }
+    /// Allocate a free ephemeral port at tick `now`, searching upwards from a random
+    /// start and wrapping around the ephemeral range.
+    pub fn allocate_ephemeral(&mut self, now: u64) -> Option<u16> {
+        self.release(now);
+        let (low, high) = (
+            *self.ephemeral.start() as usize,
+            *self.ephemeral.end() as usize,
+        );
+        if low > high {
+            return None;
+        }
+        let start = low + (self.next_random() % (high - low + 1) as u64) as usize;
+        let port = self
+            .free
+            .allocate_in(start..high + 1)
+            .or_else(|| self.free.allocate_in(low..start))?;
+        Some(port as u16)
+    }
+    /// Free an allocated port at tick `now`, to be reused `reuse_delay` ticks later.
+    ///
+    /// Fail with [`RegionError::Overlapping`] if the port is free or already waiting.
+    pub fn free(&mut self, port: u16, now: u64) -> Result<(), RegionError> {
+        let waiting = (0..self.len).any(|i| self.delayed[(self.head + i) % N].0 == port);
+        if port == 0 || waiting || self.free.is_free(port.into()) {
+            return Err(RegionError::Overlapping);
+        }
+        if N == 0 || self.reuse_delay == 0 {
+            return self.free.free(port.into());
+        }
+        if self.len == N {
+            self.pop()?;
+        }
+        self.delayed[(self.head + self.len) % N] = (port, now);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Release the waiting ports whose delay has passed at tick `now`.
+    fn release(&mut self, now: u64) {
+        while self.len > 0 {
+            let (_, freed) = self.delayed[self.head];
+            if now.wrapping_sub(freed) < self.reuse_delay || self.pop().is_err() {
+                break;
+            }
+        }
+    }
+    fn pop(&mut self) -> Result<(), RegionError> {
+        let (port, _) = self.delayed[self.head];
+        self.free.free(port.into())?;
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Ok(())
+    }
+    fn next_random(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::PortAllocator;
+    use crate::{BTreeStorage, RegionError};
+
+    #[test]
+    fn port_test() {
+        let mut ports =
+            PortAllocator::<_, 2>::new(BTreeStorage::new(), 49152..=49155, 10, 42).unwrap();
+        assert_eq!(ports.allocate(80, 0), Ok(()));
+        assert_eq!(ports.allocate(80, 0), Err(RegionError::NotCovered));
+        // Case 1: ephemeral ports stay in their range and wrap around it
+        let mut taken: alloc::vec::Vec<_> = (0..4)
+            .map(|_| ports.allocate_ephemeral(0).unwrap())
+            .collect();
+        taken.sort_unstable();
+        assert_eq!(taken, [49152, 49153, 49154, 49155]);
+        assert_eq!(ports.allocate_ephemeral(0), None);
+        // Case 2: freed ports wait out the reuse delay
+        assert_eq!(ports.free(49153, 5), Ok(()));
+        assert_eq!(ports.free(49153, 5), Err(RegionError::Overlapping));
+        assert_eq!(ports.allocate_ephemeral(14), None);
+        assert!(!ports.is_free(49153, 14));
+        assert_eq!(ports.allocate_ephemeral(15), Some(49153));
+        // Case 3: the oldest waiting port is released early when the queue is full
+        [80, 49152, 49155]
+            .iter()
+            .for_each(|&p| ports.free(p, 20).unwrap());
+        assert_eq!(ports.delayed(), 2);
+        assert!(ports.is_free(80, 20));
+        assert_eq!(ports.free(0, 20), Err(RegionError::Overlapping));
+    }
+}
@@ -0,0 +1,190 @@
+//! A fixed binary layout for handing a region set over through a plain buffer.
+//!
+//! The layout, version 1, is little-endian throughout:
+//!
+//! | Offset      | Size | Field                                   |
+//! |-------------|------|-----------------------------------------|
+//! | 0           | 4    | Magic, the bytes `RGNS`                 |
+//! | 4           | 4    | Version, 1                              |
+//! | 8           | 4    | Region count `n`                        |
+//! | 12          | 4    | Reserved, 0                             |
+//! | 16 + 16 * i | 8    | Base of region `i`                      |
+//! | 24 + 16 * i | 8    | Size of region `i`                      |
+//!
+//! Regions are written in ascending order. Bases and sizes take 64 bits on every target,
+//! so a 32-bit loader and a 64-bit kernel read the same bytes.
+
+use crate::{RegionAllocator, RegionError, RegionStorage};
+use core::convert::{TryFrom, TryInto};
+use core::fmt;
+
+const MAGIC: &[u8; 4] = b"RGNS";
+const VERSION: u32 = 1;
+const HEADER: usize = 16;
+const ENTRY: usize = 16;
+
+/// An error writing or reading the raw layout or the compact encoding.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RawError {
+    /// The buffer is shorter than the `needed` bytes.
+    BufferTooShort { needed: usize },
+    /// The buffer does not start with the magic bytes.
+    Magic,
+    /// The layout version is not one this crate reads.
+    Version(u32),
+    /// The input ends in the middle of a number.
+    Truncated,
+    /// A number or region does not fit in the address space.
+    Overflow,
+    /// Adding a region to the set failed.
+    Region(RegionError),
+}
+
+impl fmt::Display for RawError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RawError::BufferTooShort { needed } => {
+                write!(f, "buffer is too short, {} bytes are needed", needed)
+            }
+            RawError::Magic => f.write_str("buffer does not hold a region set"),
+            RawError::Version(v) => write!(f, "unsupported layout version {}", v),
+            RawError::Truncated => f.write_str("input ends in the middle of a number"),
+            RawError::Overflow => f.write_str("region overflows the address space"),
+            RawError::Region(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl core::error::Error for RawError {}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Return the bytes the raw layout of a set of `regions` regions takes, to size a
+    /// static buffer.
+    pub const fn raw_len_for(regions: usize) -> usize {
+        HEADER + ENTRY * regions
+    }
+    /// Return the bytes [`RegionAllocator::write_to`] needs.
+    pub fn raw_len(&self) -> usize {
+        Self::raw_len_for(self.regions.len())
+    }
+    /// Write the set to the start of `buf` in the raw layout, returning the bytes
+    /// written. The buffer is left untouched if it is too short.
+    pub fn write_to(&self, buf: &mut [u8]) -> Result<usize, RawError> {
+        let needed = self.raw_len();
+        let count = u32::try_from(self.regions.len()).map_err(|_| RawError::Overflow)?;
+        let buf = buf
+            .get_mut(..needed)
+            .ok_or(RawError::BufferTooShort { needed })?;
+        let (header, entries) = buf.split_at_mut(HEADER);
+        header[..4].copy_from_slice(MAGIC);
+        header[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        header[8..12].copy_from_slice(&count.to_le_bytes());
+        header[12..].fill(0);
+        for (entry, r) in entries.chunks_exact_mut(ENTRY).zip(self.regions.range(..)) {
+            entry[..8].copy_from_slice(&(r.base as u64).to_le_bytes());
+            entry[8..].copy_from_slice(&(r.size as u64).to_le_bytes());
+        }
+        Ok(needed)
+    }
+}
+
+impl<S: RegionStorage + Default> RegionAllocator<S> {
+    /// Read a set written by [`RegionAllocator::write_to`] from the start of `bytes`.
+    ///
+    /// The regions are added one by one, so a hand-made buffer with regions out of order,
+    /// overlapping or touching still yields a canonical set. Settings are not part of the
+    /// layout and start at their defaults.
+    pub fn read_from(bytes: &[u8]) -> Result<Self, RawError> {
+        let short = |needed| RawError::BufferTooShort { needed };
+        let header = bytes.get(..HEADER).ok_or(short(HEADER))?;
+        if &header[..4] != MAGIC {
+            return Err(RawError::Magic);
+        }
+        match u32_at(header, 4) {
+            VERSION => {}
+            v => return Err(RawError::Version(v)),
+        }
+        let needed = usize::try_from(u32_at(header, 8))
+            .ok()
+            .and_then(|n| n.checked_mul(ENTRY))
+            .and_then(|n| n.checked_add(HEADER))
+            .ok_or(RawError::Overflow)?;
+        let entries = bytes.get(HEADER..needed).ok_or(short(needed))?;
+        let mut regions = RegionAllocator::default();
+        for entry in entries.chunks_exact(ENTRY) {
+            let base = usize::try_from(u64_at(entry, 0)).map_err(|_| RawError::Overflow)?;
+            let size = usize::try_from(u64_at(entry, 8)).map_err(|_| RawError::Overflow)?;
+            base.checked_add(size).ok_or(RawError::Overflow)?;
+            regions.try_add(base, size).map_err(RawError::Region)?;
+        }
+        Ok(regions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RawError;
+    use crate::{RegionError, StaticRegionAllocator};
+
+    type Regions = StaticRegionAllocator<4>;
+
+    #[test]
+    fn raw_test() {
+        let mut regions = Regions::default();
+        regions.add(0x1000, 0x9e000);
+        regions.add(0x100000, 0x7f00000);
+        let mut buf = [0xff; Regions::raw_len_for(4)];
+        assert_eq!(regions.raw_len(), 48);
+        assert_eq!(
+            regions.write_to(&mut buf[..47]),
+            Err(RawError::BufferTooShort { needed: 48 })
+        );
+        assert_eq!(buf[0], 0xff);
+        assert_eq!(regions.write_to(&mut buf), Ok(48));
+        assert_eq!(&buf[..12], b"RGNS\x01\0\0\0\x02\0\0\0");
+        assert_eq!(&buf[16..24], &0x1000u64.to_le_bytes());
+        let read = Regions::read_from(&buf).unwrap();
+        assert!(read == regions);
+        // Case 2: hand-made entries out of order and touching are merged
+        let mut buf = [0; 48];
+        buf[..12].copy_from_slice(b"RGNS\x01\0\0\0\x02\0\0\0");
+        buf[16..24].copy_from_slice(&0x2000u64.to_le_bytes());
+        buf[24..32].copy_from_slice(&0x1000u64.to_le_bytes());
+        buf[32..40].copy_from_slice(&0x1000u64.to_le_bytes());
+        buf[40..48].copy_from_slice(&0x1000u64.to_le_bytes());
+        let read = Regions::read_from(&buf).unwrap();
+        assert_eq!(read.len(), 1);
+        assert!(read.check_region(0x1000, 0x2000));
+        // Case 3: malformed buffers
+        assert_eq!(
+            Regions::read_from(&buf[..40]).map(drop),
+            Err(RawError::BufferTooShort { needed: 48 })
+        );
+        buf[4] = 2;
+        assert_eq!(
+            Regions::read_from(&buf).map(drop),
+            Err(RawError::Version(2))
+        );
+        buf[0] = 0;
+        assert_eq!(Regions::read_from(&buf).map(drop), Err(RawError::Magic));
+        let mut full = [0; Regions::raw_len_for(5)];
+        full[..12].copy_from_slice(b"RGNS\x01\0\0\0\x05\0\0\0");
+        for i in 0..5 {
+            let entry = &mut full[16 + 16 * i..32 + 16 * i];
+            entry[..8].copy_from_slice(&(0x2000 * i as u64).to_le_bytes());
+            entry[8..].copy_from_slice(&0x1000u64.to_le_bytes());
+        }
+        assert_eq!(
+            Regions::read_from(&full).map(drop),
+            Err(RawError::Region(RegionError::Capacity))
+        );
+    }
+}
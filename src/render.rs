@@ -0,0 +1,250 @@
+//! Drawings of a region set as a bar proportional to the address space it spans.
+
+use crate::{Region, RegionAllocator, RegionStorage};
+use core::fmt;
+
+/// A drawing of a region set as a proportional bar, made by
+/// [`RegionAllocator::address_map`].
+///
+/// Its [`Display`](fmt::Display) prints one character per column of the bar, `#` where
+/// the whole column is in the set, `+` where part of it is and `.` where none is, between
+/// the start and end addresses of the window drawn:
+///
+/// ```text
+/// 0x0 [##..++######] 0x10000
+///      ^low  ^kernel
+/// ```
+///
+/// The labels, if any, go on a second line under the columns of their addresses.
+#[derive(Clone, Copy, Debug)]
+pub struct AddressMap<'a, S: RegionStorage> {
+    regions: &'a RegionAllocator<S>,
+    width: usize,
+    window: Option<Region>,
+    labels: &'a [(usize, &'a str)],
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Return a drawing of the set `width` columns wide, spanning from its lowest to its
+    /// highest address.
+    pub fn address_map(&self, width: usize) -> AddressMap<'_, S> {
+        AddressMap {
+            regions: self,
+            width,
+            window: None,
+            labels: &[],
+        }
+    }
+}
+
+impl<'a, S: RegionStorage> AddressMap<'a, S> {
+    /// Draw the `size` bytes from `base` instead of the span of the set, such as the
+    /// whole physical address space.
+    pub fn with_window(mut self, base: usize, size: usize) -> Self {
+        self.window = Some(Region { base, size });
+        self
+    }
+    /// Mark addresses with labels, which must be in ascending order.
+    ///
+    /// Labels outside the window, or that would overwrite the previous one, are left out.
+    pub fn with_labels(mut self, labels: &'a [(usize, &'a str)]) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// Return the window drawn, empty if the set is.
+    fn window(&self) -> Region {
+        let regions = &self.regions.regions;
+        self.window.unwrap_or_else(|| {
+            match (regions.range(..).next(), regions.range(..).next_back()) {
+                (Some(first), Some(last)) => Region {
+                    base: first.base,
+                    size: last.end() - first.base,
+                },
+                _ => Region { base: 0, size: 0 },
+            }
+        })
+    }
+    /// Return the number of columns, no more than the bytes in the window.
+    fn columns(&self, window: &Region) -> usize {
+        self.width.min(window.size)
+    }
+    /// Return the part of the window column `i` of `columns` covers.
+    fn column(window: &Region, columns: usize, i: usize) -> Region {
+        let offset = |i: usize| (window.size as u128 * i as u128 / columns as u128) as usize;
+        Region {
+            base: window.base + offset(i),
+            size: offset(i + 1) - offset(i),
+        }
+    }
+    /// Return the bytes of `range` that are in the set.
+    fn covered(&self, range: &Region) -> usize {
+        let regions = &self.regions;
+        let first = regions
+            .find_internal(range.base)
+            .map_or(range.base, |r| r.base);
+        let inside = regions.regions.range(first..range.end());
+        inside
+            .filter_map(|r| r.intersect(range))
+            .map(|r| r.size)
+            .sum()
+    }
+    /// Return the column `addr` falls in, if it is in the window.
+    fn column_of(window: &Region, columns: usize, addr: usize) -> Option<usize> {
+        let offset = addr.checked_sub(window.base).filter(|&o| o < window.size)?;
+        Some((offset as u128 * columns as u128 / window.size as u128) as usize)
+    }
+
+    /// Write the drawing as an SVG image: the window as a grey bar, the regions as green
+    /// rectangles over it and the labels as ticks with their text under the bar.
+    #[cfg(feature = "std")]
+    pub fn write_svg<W: std::io::Write>(&self, mut out: W) -> std::io::Result<()> {
+        const WIDTH: f64 = 1000.0;
+        let window = self.window();
+        let x = |addr: usize| match window.size {
+            0 => 0.0,
+            size => (addr - window.base) as f64 / size as f64 * WIDTH,
+        };
+        writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {} 60">"#,
+            WIDTH
+        )?;
+        writeln!(
+            out,
+            r##"<rect width="{}" height="30" fill="#ccc"/>"##,
+            WIDTH
+        )?;
+        let end = window.base.saturating_add(window.size);
+        let regions = self.regions.regions.range(..);
+        for r in regions.filter_map(|r| r.intersect(&window)) {
+            let (left, right) = (x(r.base), x(r.end()));
+            writeln!(
+                out,
+                r##"<rect x="{:.2}" width="{:.2}" height="30" fill="#4a4"><title>{:#x}..{:#x}</title></rect>"##,
+                left,
+                right - left,
+                r.base,
+                r.end()
+            )?;
+        }
+        for &(addr, label) in self.labels {
+            if addr < window.base || addr >= end {
+                continue;
+            }
+            let left = x(addr);
+            writeln!(
+                out,
+                r#"<line x1="{0:.2}" x2="{0:.2}" y1="30" y2="40" stroke="black"/><text x="{0:.2}" y="54" font-size="12">{1}</text>"#,
+                left,
+                Escaped(label)
+            )?;
+        }
+        writeln!(out, "</svg>")
+    }
+}
+
+impl<S: RegionStorage> fmt::Display for AddressMap<'_, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let window = self.window();
+        let columns = self.columns(&window);
+        write!(f, "{:#x} [", window.base)?;
+        for i in 0..columns {
+            let column = Self::column(&window, columns, i);
+            f.write_str(match self.covered(&column) {
+                0 => ".",
+                bytes if bytes == column.size => "#",
+                _ => "+",
+            })?;
+        }
+        write!(f, "] {:#x}", window.base as u128 + window.size as u128)?;
+        if self.labels.is_empty() {
+            return Ok(());
+        }
+        // Labels start under the first column, after the base and " ["
+        let digits = (usize::BITS - window.base.leading_zeros())
+            .div_ceil(4)
+            .max(1);
+        let indent = digits as usize + 4;
+        f.write_str("\n")?;
+        let mut at = 0;
+        for &(addr, label) in self.labels {
+            let col = match Self::column_of(&window, columns, addr) {
+                Some(col) if indent + col >= at => indent + col,
+                _ => continue,
+            };
+            write!(f, "{:pad$}^{}", "", label, pad = col - at)?;
+            at = col + 1 + label.chars().count();
+        }
+        Ok(())
+    }
+}
+
+/// Formats text with the characters XML reserves escaped.
+#[cfg(feature = "std")]
+struct Escaped<'a>(&'a str);
+
+#[cfg(feature = "std")]
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '&' => f.write_str("&amp;")?,
+                '"' => f.write_str("&quot;")?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use crate::RegionAllocator;
+    use alloc::format;
+
+    #[test]
+    fn address_map_test() {
+        let mut regions = RegionAllocator::new();
+        assert_eq!(format!("{}", regions.address_map(8)), "0x0 [] 0x0");
+        regions.add(0x1000, 0x2000);
+        regions.add(0x5000, 0x800);
+        regions.add(0x7000, 0x2000);
+        // Case 1: whole, partial and empty columns over the span of the set
+        assert_eq!(
+            format!("{}", regions.address_map(8)),
+            "0x1000 [##..+.##] 0x9000"
+        );
+        // Case 2: a wider window, with labels under their columns
+        let labels = [(0x1000, "low"), (0x2000, "gone"), (0x7000, "high")];
+        let map = regions
+            .address_map(16)
+            .with_window(0, 0x10000)
+            .with_labels(&labels);
+        assert_eq!(
+            format!("{}", map),
+            "0x0 [.##..+.##.......] 0x10000\n      ^low  ^high"
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn svg_test() {
+        use alloc::vec::Vec;
+
+        let mut regions = RegionAllocator::new();
+        regions.add(0x1000, 0x1000);
+        regions.add(0x3000, 0x1000);
+        let mut svg = Vec::new();
+        let labels = [(0x3000, "a<b")];
+        let map = regions.address_map(0).with_labels(&labels);
+        map.write_svg(&mut svg).unwrap();
+        let svg = core::str::from_utf8(&svg).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(r#"<rect x="0.00" width="333.33""#));
+        assert!(svg.contains(r#"<rect x="666.67" width="333.33""#));
+        assert!(svg.contains(">a&lt;b</text>"));
+    }
+}
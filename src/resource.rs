@@ -0,0 +1,262 @@
+//! A tree of nested resource claims, such as `/proc/iomem` or `/proc/ioports`.
+
+use crate::{RegionAllocator, RegionError};
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// A handle on a claim in a [`ResourceTree`].
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ResourceId(usize);
+
+#[derive(Clone, Debug)]
+struct Node<T> {
+    base: usize,
+    size: usize,
+    data: T,
+    parent: Option<ResourceId>,
+    /// Sorted by base.
+    children: Vec<ResourceId>,
+    /// The part of the claim no child covers.
+    free: RegionAllocator,
+}
+
+/// A tree of claimed ranges, each of which may hold claims of its own.
+///
+/// A claim lies within its parent and never overlaps its siblings, as with
+/// `request_region` in Linux: a host bridge window holds the BARs behind it, and a
+/// BAR may hold the registers a driver claims. Each claim keeps the ranges no child
+/// covers as a [`RegionAllocator`], which finds conflicts and free space.
+#[derive(Clone, Debug)]
+pub struct ResourceTree<T> {
+    nodes: Vec<Option<Node<T>>>,
+    vacant: Vec<usize>,
+}
+
+impl<T> ResourceTree<T> {
+    /// Create a tree whose root claims `size` bytes from `base`.
+    pub fn new(base: usize, size: usize, data: T) -> Self {
+        let mut free = RegionAllocator::new();
+        free.add(base, size);
+        let root = Node {
+            base,
+            size,
+            data,
+            parent: None,
+            children: Vec::new(),
+            free,
+        };
+        ResourceTree {
+            nodes: alloc::vec![Some(root)],
+            vacant: Vec::new(),
+        }
+    }
+    /// Return the root claim.
+    pub const fn root(&self) -> ResourceId {
+        ResourceId(0)
+    }
+    fn node(&self, id: ResourceId) -> Option<&Node<T>> {
+        self.nodes.get(id.0)?.as_ref()
+    }
+    /// Return the data of a claim, or `None` if it was released.
+    pub fn get(&self, id: ResourceId) -> Option<&T> {
+        self.node(id).map(|n| &n.data)
+    }
+    /// Return the data of a claim mutably, or `None` if it was released.
+    pub fn get_mut(&mut self, id: ResourceId) -> Option<&mut T> {
+        self.nodes.get_mut(id.0)?.as_mut().map(|n| &mut n.data)
+    }
+    /// Return the range of a claim, or `None` if it was released.
+    pub fn range(&self, id: ResourceId) -> Option<Range<usize>> {
+        self.node(id).map(|n| n.base..n.base + n.size)
+    }
+    /// Return the parent of a claim, or `None` for the root.
+    pub fn parent(&self, id: ResourceId) -> Option<ResourceId> {
+        self.node(id)?.parent
+    }
+    /// Return the children of a claim, in ascending order.
+    pub fn children(&self, id: ResourceId) -> &[ResourceId] {
+        self.node(id).map_or(&[], |n| &n.children)
+    }
+    /// Return the ranges of a claim no child covers, or `None` if it was released.
+    pub fn free(&self, id: ResourceId) -> Option<&RegionAllocator> {
+        self.node(id).map(|n| &n.free)
+    }
+    /// Claim `size` bytes from `base` within `parent`.
+    ///
+    /// Fail with [`RegionError::NotCovered`] if the range leaves `parent`, or
+    /// with [`RegionError::Overlapping`] if it overlaps a sibling; [`ResourceTree::conflict`]
+    /// tells which.
+    pub fn request(
+        &mut self,
+        parent: ResourceId,
+        base: usize,
+        size: usize,
+        data: T,
+    ) -> Result<ResourceId, RegionError> {
+        let node = self.node(parent).ok_or(RegionError::NotCovered)?;
+        let end = base.checked_add(size).ok_or(RegionError::Overflow)?;
+        if size == 0 {
+            return Err(RegionError::EmptyRange);
+        }
+        if base < node.base || end > node.base + node.size {
+            return Err(RegionError::NotCovered);
+        }
+        let node = self.nodes[parent.0].as_mut().unwrap();
+        node.free
+            .subtract_checked(base, size)
+            .map_err(|_| RegionError::Overlapping)?;
+        Ok(self.insert(parent, base, size, data))
+    }
+    /// Claim `size` bytes aligned to `alignment` anywhere no child of `parent` covers,
+    /// like `allocate_resource` in Linux.
+    pub fn allocate(
+        &mut self,
+        parent: ResourceId,
+        size: usize,
+        alignment: usize,
+        data: T,
+    ) -> Result<ResourceId, RegionError> {
+        let node = self.nodes.get_mut(parent.0).and_then(Option::as_mut);
+        let node = node.ok_or(RegionError::NotCovered)?;
+        if size == 0 {
+            return Err(RegionError::EmptyRange);
+        }
+        let (base, _) = node.free.allocate_by_size(size, alignment)?;
+        Ok(self.insert(parent, base, size, data))
+    }
+    fn insert(&mut self, parent: ResourceId, base: usize, size: usize, data: T) -> ResourceId {
+        let mut free = RegionAllocator::new();
+        free.add(base, size);
+        let node = Node {
+            base,
+            size,
+            data,
+            parent: Some(parent),
+            children: Vec::new(),
+            free,
+        };
+        let id = match self.vacant.pop() {
+            Some(i) => {
+                self.nodes[i] = Some(node);
+                ResourceId(i)
+            }
+            None => {
+                self.nodes.push(Some(node));
+                ResourceId(self.nodes.len() - 1)
+            }
+        };
+        let children = &self.nodes[parent.0].as_ref().unwrap().children;
+        let i = children.partition_point(|&c| self.node(c).unwrap().base < base);
+        self.nodes[parent.0]
+            .as_mut()
+            .unwrap()
+            .children
+            .insert(i, id);
+        id
+    }
+    /// Return the child of `parent` overlapping `size` bytes from `base`, if any.
+    pub fn conflict(&self, parent: ResourceId, base: usize, size: usize) -> Option<ResourceId> {
+        let end = base.saturating_add(size);
+        let children = self.children(parent);
+        let i = children.partition_point(|&c| self.node(c).unwrap().base < end);
+        let last = *children[..i].last()?;
+        let node = self.node(last).unwrap();
+        (node.base + node.size > base).then_some(last)
+    }
+    /// Return the innermost claim covering `addr`, if the root covers it.
+    pub fn find(&self, addr: usize) -> Option<ResourceId> {
+        let root = self.root();
+        if !self.range(root)?.contains(&addr) {
+            return None;
+        }
+        let mut id = root;
+        while let Some(child) = self.conflict(id, addr, 1) {
+            id = child;
+        }
+        Some(id)
+    }
+    /// Release a claim without children, returning its data.
+    ///
+    /// Return `None` if the claim is the root, has children or was already released.
+    pub fn release(&mut self, id: ResourceId) -> Option<T> {
+        let node = self.node(id)?;
+        let parent = node.parent.filter(|_| node.children.is_empty())?;
+        let node = self.nodes[id.0].take().unwrap();
+        self.vacant.push(id.0);
+        let parent = self.nodes[parent.0].as_mut().unwrap();
+        parent.children.retain(|&c| c != id);
+        parent.free.add(node.base, node.size);
+        Some(node.data)
+    }
+    /// Iterate over every claim depth-first, in ascending order among siblings, with its
+    /// depth below the root.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, ResourceId)> + '_ {
+        let mut stack = alloc::vec![(0, self.root())];
+        core::iter::from_fn(move || {
+            let (depth, id) = stack.pop()?;
+            let children = self.children(id).iter().rev();
+            stack.extend(children.map(|&c| (depth + 1, c)));
+            Some((depth, id))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResourceTree;
+    use crate::RegionError;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn resource_test() {
+        let mut iomem = ResourceTree::new(0, 0x1_0000_0000, "PCI mem");
+        let root = iomem.root();
+        let bridge = iomem
+            .request(root, 0xc000_0000, 0x1000_0000, "PCI Bus 0000:00")
+            .unwrap();
+        // Case 1: claims nest within their parent and never overlap siblings
+        let bar = iomem
+            .request(bridge, 0xc000_0000, 0x1000, "0000:00:02.0")
+            .unwrap();
+        let regs = iomem.request(bar, 0xc000_0100, 0x100, "e1000e").unwrap();
+        assert_eq!(
+            iomem.request(bridge, 0xc000_0800, 0x1000, "overlap"),
+            Err(RegionError::Overlapping)
+        );
+        assert_eq!(iomem.conflict(bridge, 0xc000_0800, 0x1000), Some(bar));
+        assert_eq!(
+            iomem.request(bar, 0xc000_0f00, 0x200, "outside"),
+            Err(RegionError::NotCovered)
+        );
+        // Case 2: free space in a claim is found and claimed
+        let other = iomem
+            .allocate(bridge, 0x4000, 0x4000, "0000:00:03.0")
+            .unwrap();
+        assert_eq!(iomem.range(other), Some(0xc000_4000..0xc000_8000));
+        assert_eq!(iomem.find(0xc000_0180), Some(regs));
+        assert_eq!(iomem.find(0xc000_0200), Some(bar));
+        assert_eq!(iomem.find(0x1000), Some(root));
+        // Case 3: the tree walks depth-first
+        let walk: Vec<_> = iomem
+            .iter()
+            .map(|(d, id)| (d, *iomem.get(id).unwrap()))
+            .collect();
+        assert_eq!(
+            walk,
+            [
+                (0, "PCI mem"),
+                (1, "PCI Bus 0000:00"),
+                (2, "0000:00:02.0"),
+                (3, "e1000e"),
+                (2, "0000:00:03.0")
+            ]
+        );
+        // Case 4: only claims without children are released
+        assert_eq!(iomem.release(bar), None);
+        assert_eq!(iomem.release(regs), Some("e1000e"));
+        assert_eq!(iomem.release(bar), Some("0000:00:02.0"));
+        assert_eq!(iomem.get(bar), None);
+        assert!(iomem.request(bridge, 0xc000_0800, 0x1000, "again").is_ok());
+        assert_eq!(iomem.release(root), None);
+    }
+}
@@ -0,0 +1,149 @@
+//! A ring allocator over a single region.
+
+/// A circular allocator over `[base, base + size)` freed in allocation order, with up
+/// to `N` allocations outstanding.
+///
+/// Allocations are placed at the head and wrap around to the start of the region when
+/// the end is reached. Completing an allocation frees it together with every older one,
+/// which matches how DMA rings report progress.
+#[derive(Clone, Debug)]
+pub struct RingRegion<const N: usize> {
+    base: usize,
+    end: usize,
+    head: usize,
+    tail: usize,
+    /// End of the allocations above the tail while the head has wrapped around.
+    wrap: usize,
+    wrapped: bool,
+    /// Bases and ends of the outstanding allocations, oldest first from `first`.
+    pending: [(usize, usize); N],
+    first: usize,
+    len: usize,
+}
+
+impl<const N: usize> RingRegion<N> {
+    /// Create an empty [`RingRegion`] over `[base, base + size)`,
+    /// typically a region taken from a [`RegionAllocator`](crate::RegionAllocator).
+    pub fn new(base: usize, size: usize) -> Option<Self> {
+        let end = base.checked_add(size)?;
+        Some(RingRegion {
+            base,
+            end,
+            head: base,
+            tail: base,
+            wrap: end,
+            wrapped: false,
+            pending: [(0, 0); N],
+            first: 0,
+            len: 0,
+        })
+    }
+    /// Check whether no allocation is outstanding.
+    pub fn is_empty(&self) -> bool {
+        !self.wrapped && self.head == self.tail
+    }
+    /// Return number of bytes between the tail and the head, including padding.
+    pub fn used(&self) -> usize {
+        if self.wrapped {
+            self.wrap - self.tail + self.head - self.base
+        } else {
+            self.head - self.tail
+        }
+    }
+    /// Allocate `size` contiguous bytes aligned to a given power of 2 at the head.
+    ///
+    /// Return `None` if there is no room, or if `N` allocations are outstanding.
+    pub fn allocate(&mut self, size: usize, alignment: usize) -> Option<usize> {
+        if !alignment.is_power_of_two() || self.len == N {
+            return None;
+        }
+        if self.is_empty() {
+            self.head = self.base;
+            self.tail = self.base;
+        }
+        let fit = |from: usize, limit: usize| {
+            let base = from.checked_add(alignment - 1)? & !(alignment - 1);
+            Some(base).filter(|&b| b.checked_add(size).is_some_and(|e| e <= limit))
+        };
+        let base = if self.wrapped {
+            fit(self.head, self.tail)?
+        } else if let Some(base) = fit(self.head, self.end) {
+            base
+        } else {
+            let base = fit(self.base, self.tail)?;
+            self.wrap = self.head;
+            self.wrapped = true;
+            base
+        };
+        self.head = base + size;
+        self.pending[(self.first + self.len) % N] = (base, self.head);
+        self.len += 1;
+        Some(base)
+    }
+    /// Free the allocation `[base, base + size)` and all allocations made before it.
+    /// Return false, leaving the ring unchanged, if it is not an outstanding allocation.
+    pub fn complete(&mut self, base: usize, size: usize) -> bool {
+        let end = match base.checked_add(size) {
+            Some(end) => end,
+            None => return false,
+        };
+        let found = (0..self.len).find(|k| self.pending[(self.first + k) % N] == (base, end));
+        let Some(k) = found else {
+            return false;
+        };
+        self.first = (self.first + k + 1) % N;
+        self.len -= k + 1;
+        if self.wrapped && self.tail <= base && end <= self.wrap {
+            self.tail = end;
+            if self.tail == self.wrap {
+                self.tail = self.base;
+                self.wrapped = false;
+            }
+        } else if self.wrapped && self.base <= base && end <= self.head {
+            self.tail = end;
+            self.wrapped = false;
+        } else {
+            self.tail = end;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RingRegion;
+
+    #[test]
+    fn ring_test() {
+        let mut ring = RingRegion::<4>::new(0x1000, 0x100).unwrap();
+        let a = ring.allocate(0x60, 1).unwrap();
+        let b = ring.allocate(0x60, 1).unwrap();
+        assert_eq!((a, b), (0x1000, 0x1060));
+        // Case 1: full until the oldest completes
+        assert_eq!(ring.allocate(0x60, 1), None);
+        assert!(ring.complete(a, 0x60));
+        // Case 2: wrapping around to the start
+        let c = ring.allocate(0x50, 0x20).unwrap();
+        assert_eq!(c, 0x1000);
+        assert_eq!(ring.used(), 0xb0);
+        assert_eq!(ring.allocate(0x40, 1), None);
+        // Case 3: completing a later allocation frees the older ones
+        assert!(!ring.complete(0x1000, 0x80));
+        assert!(ring.complete(c, 0x50));
+        assert!(ring.is_empty());
+        assert_eq!(ring.allocate(0x100, 1), Some(0x1000));
+        // Case 4: only outstanding allocations complete, and only once
+        assert!(ring.complete(0x1000, 0x100));
+        assert!(!ring.complete(0x1000, 0x100));
+        let d = ring.allocate(0x40, 1).unwrap();
+        assert!(!ring.complete(d, 0x20));
+        assert!(ring.complete(d, 0x40));
+        assert!(!ring.complete(d, 0x40));
+        assert!(ring.is_empty());
+        // Case 5: no more than `N` allocations are outstanding
+        let e: [_; 4] = core::array::from_fn(|_| ring.allocate(0x10, 1).unwrap());
+        assert_eq!(ring.allocate(0x10, 1), None);
+        assert!(ring.complete(e[1], 0x10));
+        assert_eq!(ring.used(), 0x20);
+    }
+}
@@ -0,0 +1,69 @@
+//! `serde` support, with a region set written as a list of `(base, size)` pairs.
+
+use crate::{RegionAllocator, RegionStorage};
+use core::fmt;
+use core::marker::PhantomData;
+use serde::de::{Error, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Serialize the regions as a sequence of `(base, size)` pairs in ascending order.
+///
+/// Settings are not serialized.
+impl<S: RegionStorage> Serialize for RegionAllocator<S> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_seq(self.regions.range(..).map(|r| (r.base, r.size)))
+    }
+}
+
+/// Deserialize a sequence of `(base, size)` pairs, adding them one by one.
+///
+/// Pairs may come in any order, overlap or touch; they are merged into a canonical set
+/// as [`RegionAllocator::add`] merges them. Settings start at their defaults.
+impl<'de, S: RegionStorage + Default> Deserialize<'de> for RegionAllocator<S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Pairs<S>(PhantomData<S>);
+
+        impl<'de, S: RegionStorage + Default> Visitor<'de> for Pairs<S> {
+            type Value = RegionAllocator<S>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a sequence of (base, size) pairs")
+            }
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut regions = RegionAllocator::default();
+                while let Some((base, size)) = seq.next_element::<(usize, usize)>()? {
+                    regions.try_add(base, size).map_err(A::Error::custom)?;
+                }
+                Ok(regions)
+            }
+        }
+
+        deserializer.deserialize_seq(Pairs(PhantomData))
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use crate::{RegionAllocator, StaticRegionAllocator};
+    use alloc::string::ToString;
+
+    #[test]
+    fn serde_test() {
+        let mut regions = RegionAllocator::new();
+        regions.add(0x100000, 0x7f00000);
+        regions.add(0x1000, 0x9e000);
+        let json = serde_json::to_string(&regions).unwrap();
+        assert_eq!(json, "[[4096,647168],[1048576,133169152]]");
+        let back: RegionAllocator = serde_json::from_str(&json).unwrap();
+        assert!(back == regions);
+        // Case 2: unsorted, overlapping and touching pairs are canonicalized
+        let messy: RegionAllocator =
+            serde_json::from_str("[[8192,4096],[4096,4096],[4608,512],[65536,16]]").unwrap();
+        assert_eq!(messy.len(), 2);
+        assert!(messy.check_region(0x1000, 0x2000));
+        // Case 3: errors from the set surface as deserialization errors
+        let e = serde_json::from_str::<StaticRegionAllocator<1>>("[[0,1],[2,1]]").unwrap_err();
+        assert!(e.to_string().starts_with("storage is out of capacity"));
+        assert!(serde_json::from_str::<RegionAllocator>("[[1,-1]]").is_err());
+    }
+}
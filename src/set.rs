@@ -0,0 +1,615 @@
+//! Set operations between region sets.
+
+use crate::{CapacityError, Region, RegionAllocator, RegionError, RegionStorage};
+use core::cmp::max;
+use core::iter;
+
+impl<S: RegionStorage + Default> RegionAllocator<S> {
+    /// Return the ranges present in both `self` and `other`.
+    ///
+    /// The result uses a new storage of the same kind and the settings of `self`;
+    /// [`CapacityError`] is returned if it cannot hold the intersection.
+    pub fn intersection<T: RegionStorage>(
+        &self,
+        other: &RegionAllocator<T>,
+    ) -> Result<Self, CapacityError> {
+        let mut result = self.empty_like();
+        let mut a = self.regions.range(..).peekable();
+        let mut b = other.regions.range(..).peekable();
+        while let (Some(x), Some(y)) = (a.peek(), b.peek()) {
+            if let Some(both) = x.intersect(y) {
+                result.push(both)?;
+            }
+            match x.end() <= y.end() {
+                true => a.next(),
+                false => b.next(),
+            };
+        }
+        Ok(result)
+    }
+    /// Return the ranges present in `self`, `other`, or both, merged as [`RegionAllocator::add`]
+    /// would merge them.
+    ///
+    /// The result uses a new storage of the same kind and the settings of `self`;
+    /// [`CapacityError`] is returned if it cannot hold the union.
+    pub fn union<T: RegionStorage>(
+        &self,
+        other: &RegionAllocator<T>,
+    ) -> Result<Self, CapacityError> {
+        let mut result = self.empty_like();
+        for r in merge(self.regions.range(..), other.regions.range(..)) {
+            result.push(r)?;
+        }
+        Ok(result)
+    }
+    /// Move all regions of `other` into `self`, leaving `other` empty.
+    ///
+    /// Both sets are walked once in order and the merged set is built in a fresh storage,
+    /// instead of adding the regions of `other` one by one. On [`CapacityError`] neither
+    /// set is changed.
+    pub fn append<T: RegionStorage + Default>(
+        &mut self,
+        other: &mut RegionAllocator<T>,
+    ) -> Result<(), CapacityError> {
+        let union = self.union(other)?;
+        self.regions = union.regions;
+        self.rebuilt();
+        other.regions = T::default();
+        other.cursor = None;
+        other.rebuilt();
+        Ok(())
+    }
+    /// Return the ranges present in `self` but not in `other`.
+    ///
+    /// The result uses a new storage of the same kind and the settings of `self`;
+    /// [`CapacityError`] is returned if it cannot hold the difference.
+    pub fn difference<T: RegionStorage>(
+        &self,
+        other: &RegionAllocator<T>,
+    ) -> Result<Self, CapacityError> {
+        let mut result = self.empty_like();
+        let mut holes = other.regions.range(..).peekable();
+        for r in self.regions.range(..) {
+            let mut base = r.base;
+            while let Some(hole) = holes.peek().filter(|hole| hole.base < r.end()) {
+                if hole.base > base {
+                    result.push(Region {
+                        base,
+                        size: hole.base - base,
+                    })?;
+                }
+                base = max(base, hole.end());
+                if hole.end() > r.end() {
+                    break;
+                }
+                holes.next();
+            }
+            if base < r.end() {
+                result.push(Region {
+                    base,
+                    size: r.end() - base,
+                })?;
+            }
+        }
+        Ok(result)
+    }
+    /// Remove every range of `other` from `self` in one pass over both sets, rather than
+    /// subtracting the regions of `other` one by one. On [`CapacityError`] `self` is unchanged.
+    pub fn subtract_all<T: RegionStorage>(
+        &mut self,
+        other: &RegionAllocator<T>,
+    ) -> Result<(), CapacityError> {
+        self.regions = self.difference(other)?.regions;
+        self.rebuilt();
+        Ok(())
+    }
+    /// Return the ranges of `[universe_base, universe_base + universe_size)` that are not
+    /// in the set, such as the free ranges of a map of reserved ones.
+    ///
+    /// The complement of the complement is the part of the set inside the universe.
+    /// Fails with [`RegionError::Overflow`] if the universe does not fit in the address space,
+    /// and with [`RegionError::Capacity`] if the result does not fit in the storage.
+    pub fn complement(
+        &self,
+        universe_base: usize,
+        universe_size: usize,
+    ) -> Result<Self, RegionError> {
+        let end = universe_base
+            .checked_add(universe_size)
+            .ok_or(RegionError::Overflow)?;
+        let mut result = self.empty_like();
+        let mut base = universe_base;
+        let first = self.find_internal(universe_base).map_or(base, |r| r.base);
+        for r in self.regions.range(first..end) {
+            if r.base > base {
+                result.push(Region {
+                    base,
+                    size: r.base - base,
+                })?;
+            }
+            base = max(base, r.end());
+        }
+        if base < end {
+            result.push(Region {
+                base,
+                size: end - base,
+            })?;
+        }
+        Ok(result)
+    }
+    /// Move every range at or above `addr` into a new set, splitting the region that
+    /// straddles `addr`.
+    ///
+    /// The new set uses a new storage of the same kind and the settings of `self`.
+    /// On [`CapacityError`] `self` is unchanged.
+    pub fn split_off(&mut self, addr: usize) -> Result<Self, CapacityError> {
+        let mut upper = self.empty_like();
+        let straddling = self.find_internal(addr).filter(|r| r.end() > addr);
+        let first = straddling.map_or(addr, |r| r.base);
+        for r in self.regions.range(first..) {
+            let base = max(r.base, addr);
+            upper.push(Region {
+                base,
+                size: r.end() - base,
+            })?;
+        }
+        let lower = straddling.filter(|r| r.base < addr).map(|r| Region {
+            base: r.base,
+            size: addr - r.base,
+        });
+        self.regions.splice(first..=usize::MAX, lower.as_slice())?;
+        self.rebuilt();
+        Ok(upper)
+    }
+    /// Discard every range outside `[base, base + size)`, trimming the regions that
+    /// straddle its ends, such as above the highest usable physical address.
+    ///
+    /// The regions inside are copied into a new storage of the same kind that then replaces
+    /// the current one. Fails with [`RegionError::Overflow`] if the window does not fit in the
+    /// address space and with [`RegionError::Capacity`] if a trimmed region cannot be stored,
+    /// leaving the set unchanged.
+    pub fn clamp(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        let end = base.checked_add(size).ok_or(RegionError::Overflow)?;
+        let mut clamped = self.empty_like();
+        let first = self.find_internal(base).map_or(base, |r| r.base);
+        let window = Region { base, size };
+        for r in self.regions.range(first..end) {
+            if let Some(r) = r.intersect(&window) {
+                clamped.push(r)?;
+            }
+        }
+        self.regions = clamped.regions;
+        self.rebuilt();
+        Ok(())
+    }
+    /// Shift every region by `offset`, such as between physical addresses and their
+    /// direct-map view.
+    ///
+    /// Fails with [`RegionError::Overflow`] if a region would move out of the address space,
+    /// and with [`RegionError::Capacity`] if the new storage cannot hold the regions,
+    /// leaving the set unchanged.
+    pub fn rebase(&mut self, offset: isize) -> Result<(), RegionError> {
+        let shift = |addr: usize| addr.checked_add_signed(offset).ok_or(RegionError::Overflow);
+        if let (Some(first), Some(last)) = (
+            self.regions.range(..).next(),
+            self.regions.range(..).next_back(),
+        ) {
+            shift(first.base)?;
+            shift(last.end())?;
+        }
+        let mut rebased = self.empty_like();
+        for r in self.regions.range(..) {
+            rebased.push(Region {
+                base: shift(r.base)?,
+                size: r.size,
+            })?;
+        }
+        self.regions = rebased.regions;
+        self.rebuilt();
+        self.cursor = self.cursor.and_then(|cursor| shift(cursor).ok());
+        Ok(())
+    }
+    /// Create an empty set with the settings of `self`.
+    fn empty_like(&self) -> Self {
+        RegionAllocator::with_storage(S::default())
+            .with_endpoints(self.endpoints)
+            .with_zero_size(self.zero_size)
+            .with_granule(self.granule())
+            .with_preserved_spans(self.preserve)
+            .with_fit_policy(self.fit_policy)
+            .with_region_limit(self.region_limit)
+    }
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Check whether every range of the set is also in `other`.
+    ///
+    /// Like the other set operations this compares the addresses covered, counting the end
+    /// of each region out whatever the [`Endpoints`](crate::Endpoints) of either set.
+    pub fn is_subset<T: RegionStorage>(&self, other: &RegionAllocator<T>) -> bool {
+        let mut covers = other.regions.range(..).peekable();
+        self.regions.range(..).all(|r| {
+            while covers.next_if(|c| c.end() <= r.base).is_some() {}
+            covers.peek().is_some_and(|c| c.covers(&r))
+        })
+    }
+    /// Check whether every range of `other` is also in the set.
+    pub fn is_superset<T: RegionStorage>(&self, other: &RegionAllocator<T>) -> bool {
+        other.is_subset(self)
+    }
+    /// Check whether no range is in both the set and `other`.
+    pub fn is_disjoint<T: RegionStorage>(&self, other: &RegionAllocator<T>) -> bool {
+        let mut a = self.regions.range(..).peekable();
+        let mut b = other.regions.range(..).peekable();
+        while let (Some(x), Some(y)) = (a.peek(), b.peek()) {
+            if x.overlaps(y) {
+                return false;
+            }
+            match x.end() <= y.end() {
+                true => a.next(),
+                false => b.next(),
+            };
+        }
+        true
+    }
+    /// Keep only the regions for which `f(base, size)` returns `true`, visiting them in
+    /// ascending order.
+    ///
+    /// Each rejected region is removed as soon as `f` returns, so if `f` panics the regions
+    /// rejected before stay removed.
+    pub fn retain(&mut self, mut f: impl FnMut(usize, usize) -> bool) {
+        let mut next = 0;
+        loop {
+            let Some(r) = self.regions.range(next..).next() else {
+                break;
+            };
+            // Regions do not touch, so the following one starts above the end
+            next = r.end();
+            if !f(r.base, r.size) {
+                self.regions
+                    .splice(r.base..=r.base, &[])
+                    .expect("removing a region needs no room");
+                self.spliced(r.size, 0);
+                self.notify(|o| o.subtracted(r));
+            }
+        }
+    }
+    /// Append a region above and not adjacent to every region in the set.
+    fn push(&mut self, r: Region) -> Result<(), CapacityError> {
+        self.check_limit(0, 1)?;
+        self.regions.splice(r.base..=r.base, &[r])
+    }
+}
+
+/// Merge two streams of sorted, disjoint regions into one, coalescing overlapping and
+/// adjacent regions.
+fn merge(
+    a: impl Iterator<Item = Region>,
+    b: impl Iterator<Item = Region>,
+) -> impl Iterator<Item = Region> {
+    let (mut a, mut b) = (a.peekable(), b.peekable());
+    let mut lowest = move || match (a.peek(), b.peek()) {
+        (Some(x), Some(y)) if y.base < x.base => b.next(),
+        (Some(_), _) => a.next(),
+        (None, _) => b.next(),
+    };
+    let mut pending = lowest();
+    iter::from_fn(move || {
+        let mut merged = pending?;
+        loop {
+            pending = lowest();
+            match pending {
+                Some(r) if r.base <= merged.end() => {
+                    merged.size = max(merged.end(), r.end()) - merged.base;
+                }
+                _ => return Some(merged),
+            }
+        }
+    })
+}
+
+/// Two sets are equal if they hold the same regions, whatever their storages and settings.
+impl<S: RegionStorage, T: RegionStorage> PartialEq<RegionAllocator<T>> for RegionAllocator<S> {
+    fn eq(&self, other: &RegionAllocator<T>) -> bool {
+        self.regions.len() == other.regions.len()
+            && self.regions.range(..).eq(other.regions.range(..))
+    }
+}
+
+/// Sets are always merged, so equal sets cover the same bytes and vice versa.
+impl<S: RegionStorage> Eq for RegionAllocator<S> {}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use crate::{ArrayStorage, RegionAllocator, RegionError, RegionStorage};
+    use alloc::vec::Vec;
+
+    fn regions<S: RegionStorage>(alloc: &RegionAllocator<S>) -> Vec<(usize, usize)> {
+        alloc.regions.range(..).map(|r| (r.base, r.size)).collect()
+    }
+
+    #[test]
+    fn intersection_test() {
+        let mut ram = RegionAllocator::new();
+        ram.add(0, 0x1000);
+        ram.add(0x2000, 0x3000);
+        ram.add(0x8000, 0x1000);
+        let mut hotplug = RegionAllocator::new();
+        hotplug.add(0x800, 0x2000);
+        hotplug.add(0x4000, 0x5000);
+        let both = ram.intersection(&hotplug).unwrap();
+        assert_eq!(
+            regions(&both),
+            [
+                (0x800, 0x800),
+                (0x2000, 0x800),
+                (0x4000, 0x1000),
+                (0x8000, 0x1000)
+            ]
+        );
+        assert_eq!(
+            regions(&hotplug.intersection(&ram).unwrap()),
+            regions(&both)
+        );
+        assert!(ram
+            .intersection(&RegionAllocator::new())
+            .unwrap()
+            .is_empty());
+        // Bounded storages report when the intersection does not fit
+        let mut small = RegionAllocator::with_storage(ArrayStorage::<2>::new());
+        small.add(0, 0x10000);
+        let split = small.intersection(&ram).map(|r| regions(&r));
+        assert!(split.is_err());
+        small.subtract(0x1000, 0x4000);
+        assert_eq!(
+            small.intersection(&hotplug).map(|r| regions(&r)),
+            Ok(Vec::from([(0x800, 0x800), (0x5000, 0x4000)]))
+        );
+    }
+
+    #[test]
+    fn union_test() {
+        let mut node0 = RegionAllocator::new();
+        node0.add(0, 0x1000);
+        node0.add(0x2000, 0x1000);
+        node0.add(0x8000, 0x1000);
+        let mut node1 = RegionAllocator::new();
+        node1.add(0x1000, 0x800);
+        node1.add(0x2800, 0x1000);
+        node1.add(0x6000, 0x1000);
+        let all = node0.union(&node1).unwrap();
+        assert_eq!(
+            regions(&all),
+            [
+                (0, 0x1800),
+                (0x2000, 0x1800),
+                (0x6000, 0x1000),
+                (0x8000, 0x1000)
+            ]
+        );
+        // The same as adding every region in turn
+        let mut added = node0.union(&RegionAllocator::new()).unwrap();
+        node1
+            .regions
+            .range(..)
+            .for_each(|r| added.add(r.base, r.size));
+        assert_eq!(regions(&added), regions(&all));
+        // Case 2: appending consumes the other set
+        node0.append(&mut node1).unwrap();
+        assert_eq!(regions(&node0), regions(&all));
+        assert!(node1.is_empty());
+        // Case 3: a failed append changes neither set
+        let mut small = RegionAllocator::with_storage(ArrayStorage::<2>::new());
+        small.add(0x100, 0x100);
+        assert!(small.append(&mut node0).is_err());
+        assert_eq!(regions(&small), [(0x100, 0x100)]);
+        assert_eq!(node0.len(), 4);
+    }
+
+    #[test]
+    fn difference_test() {
+        let mut usable = RegionAllocator::new();
+        usable.add(0, 0x4000);
+        usable.add(0x6000, 0x2000);
+        usable.add(0x9000, 0x1000);
+        let mut reserved = RegionAllocator::new();
+        reserved.add(0x1000, 0x1000);
+        reserved.add(0x3000, 0x3800);
+        reserved.add(0x7000, 0x100);
+        reserved.add(0xa000, 0x1000);
+        let free = usable.difference(&reserved).unwrap();
+        assert_eq!(
+            regions(&free),
+            [
+                (0, 0x1000),
+                (0x2000, 0x1000),
+                (0x6800, 0x800),
+                (0x7100, 0xf00),
+                (0x9000, 0x1000)
+            ]
+        );
+        // The same as subtracting every region in turn
+        let mut subtracted = usable.union(&RegionAllocator::new()).unwrap();
+        reserved
+            .regions
+            .range(..)
+            .for_each(|r| subtracted.subtract(r.base, r.size));
+        assert_eq!(regions(&subtracted), regions(&free));
+        assert!(reserved.difference(&reserved).unwrap().is_empty());
+        // Case 2: in place
+        usable.subtract_all(&reserved).unwrap();
+        assert_eq!(regions(&usable), regions(&free));
+    }
+
+    #[test]
+    fn complement_test() {
+        let mut reserved = RegionAllocator::new();
+        reserved.add(0, 0x1000);
+        reserved.add(0x3000, 0x1000);
+        reserved.add(0x9000, 0x2000);
+        let free = reserved.complement(0x800, 0x9000).unwrap();
+        assert_eq!(regions(&free), [(0x1000, 0x2000), (0x4000, 0x5000)]);
+        // Back again, clipped to the universe
+        let back = free.complement(0x800, 0x9000).unwrap();
+        assert_eq!(
+            regions(&back),
+            [(0x800, 0x800), (0x3000, 0x1000), (0x9000, 0x800)]
+        );
+        // Case 2: whole address space and degenerate universes
+        let all = RegionAllocator::new().complement(0, usize::MAX).unwrap();
+        assert_eq!(regions(&all), [(0, usize::MAX)]);
+        assert!(reserved.complement(0x2000, 0).unwrap().is_empty());
+        assert!(reserved.complement(0x3800, 0x100).unwrap().is_empty());
+        assert_eq!(
+            reserved.complement(usize::MAX, 2).map(|r| r.len()),
+            Err(RegionError::Overflow)
+        );
+    }
+
+    #[test]
+    fn comparison_test() {
+        let mut ram = RegionAllocator::new();
+        ram.add(0, 0x4000);
+        ram.add(0x8000, 0x4000);
+        let mut part = RegionAllocator::with_storage(ArrayStorage::<4>::new());
+        part.add(0x1000, 0x1000);
+        part.add(0x3000, 0x1000);
+        part.add(0x8000, 0x1000);
+        assert!(part.is_subset(&ram) && ram.is_superset(&part));
+        assert!(!ram.is_subset(&part) && !part.is_disjoint(&ram));
+        assert!(ram != part);
+        // Case 2: a range spanning a gap is not covered
+        part.add(0x3800, 0x4800);
+        assert!(!part.is_subset(&ram));
+        // Case 3: touching sets are disjoint, and equality ignores the storage
+        let mut rest = ram.complement(0, 0x10000).unwrap();
+        assert!(rest.is_disjoint(&ram) && ram.is_disjoint(&rest));
+        assert!(RegionAllocator::new().is_subset(&rest));
+        rest.add(0x3000, 0x1000);
+        assert!(!rest.is_disjoint(&ram));
+        let copy = ram.union(&RegionAllocator::with_storage(ArrayStorage::<2>::new()));
+        assert!(copy.is_ok_and(|copy| copy == ram));
+    }
+
+    #[test]
+    fn retain_test() {
+        let mut ram = RegionAllocator::with_storage(ArrayStorage::<8>::new());
+        ram.add(0, 0x800);
+        ram.add(0x1000, 0x3000);
+        ram.add(0x5000, 0x100);
+        ram.add(0x8000, 0x1000);
+        ram.add(usize::MAX - 0x1000, 0x1000);
+        ram.retain(|_, size| size >= 0x1000);
+        assert_eq!(
+            regions(&ram),
+            [
+                (0x1000, 0x3000),
+                (0x8000, 0x1000),
+                (usize::MAX - 0x1000, 0x1000)
+            ]
+        );
+        // Case 2: restricting to a span
+        ram.retain(|base, size| base + size <= 0x1_0000);
+        assert_eq!(regions(&ram), [(0x1000, 0x3000), (0x8000, 0x1000)]);
+        let mut visited = Vec::new();
+        ram.retain(|base, _| {
+            visited.push(base);
+            false
+        });
+        assert_eq!(visited, [0x1000, 0x8000]);
+        assert!(ram.is_empty());
+    }
+
+    #[test]
+    fn clone_hash_test() {
+        extern crate std;
+        use std::collections::HashSet;
+
+        let mut a = RegionAllocator::new();
+        a.add(0, 0x1000);
+        a.add(0x2000, 0x1000);
+        // The same bytes, added in pieces
+        let mut b = RegionAllocator::new();
+        b.add(0x2800, 0x800);
+        b.add(0, 0x800);
+        b.add(0x800, 0x800);
+        b.add(0x2000, 0x800);
+        let c = a.clone();
+        a.subtract(0, 0x1000);
+        let cache = HashSet::from([a, b, c]);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn split_off_test() {
+        let mut low = RegionAllocator::new();
+        low.add(0x1000, 0x1000);
+        low.add(0x6000_0000, 0x4000_0000);
+        low.add(0xc000_0000, 0x1000);
+        let before = low.union(&RegionAllocator::new()).unwrap();
+        let high = low.split_off(0x8000_0000).unwrap();
+        assert_eq!(
+            regions(&low),
+            [(0x1000, 0x1000), (0x6000_0000, 0x2000_0000)]
+        );
+        assert_eq!(
+            regions(&high),
+            [(0x8000_0000, 0x2000_0000), (0xc000_0000, 0x1000)]
+        );
+        assert!(low.union(&high).is_ok_and(|all| all == before));
+        // Case 2: splitting at a base or past the end leaves no piece behind
+        let rest = low.split_off(0x6000_0000).unwrap();
+        assert_eq!(regions(&low), [(0x1000, 0x1000)]);
+        assert_eq!(rest.len(), 1);
+        assert!(low.split_off(0x1_0000).unwrap().is_empty());
+        assert_eq!(low.len(), 1);
+    }
+
+    #[test]
+    fn clamp_test() {
+        let mut ram = RegionAllocator::new();
+        ram.add(0, 0x1000);
+        ram.add(0x2000, 0x2000);
+        ram.add(0x6000, 0x1000);
+        ram.add(0x8000, 0x2000);
+        ram.clamp(0x3000, 0x6000).unwrap();
+        assert_eq!(
+            regions(&ram),
+            [(0x3000, 0x1000), (0x6000, 0x1000), (0x8000, 0x1000)]
+        );
+        // Case 2: windows with nothing to trim, outside the set, or overflowing
+        ram.clamp(0x3000, 0x6000).unwrap();
+        assert_eq!(ram.len(), 3);
+        assert_eq!(ram.clamp(usize::MAX, 1), Err(RegionError::Overflow));
+        assert_eq!(ram.len(), 3);
+        ram.clamp(0x4000, 0x2000).unwrap();
+        assert!(ram.is_empty());
+    }
+
+    #[test]
+    fn rebase_test() {
+        const DIRECT_MAP: usize = 0x4000_0000;
+        let mut ram = RegionAllocator::new();
+        ram.add(0x1000, 0x1000);
+        ram.add(0x3000, 0x2000);
+        assert_eq!(ram.allocate_by_size(0x800, 0x800), Ok((0x1000, 0x800)));
+        ram.rebase(DIRECT_MAP as isize).unwrap();
+        assert_eq!(
+            regions(&ram),
+            [(DIRECT_MAP + 0x1800, 0x800), (DIRECT_MAP + 0x3000, 0x2000)]
+        );
+        // The cursor moves along
+        assert_eq!(
+            ram.allocate_by_size(0x800, 1),
+            Ok((DIRECT_MAP + 0x1800, 0x800))
+        );
+        ram.rebase(-(DIRECT_MAP as isize)).unwrap();
+        assert_eq!(regions(&ram), [(0x3000, 0x2000)]);
+        // Case 2: shifting out of the address space changes nothing
+        assert_eq!(ram.rebase(-0x4000), Err(RegionError::Overflow));
+        ram.add(usize::MAX - 0x1000, 0x1000);
+        assert_eq!(ram.rebase(1), Err(RegionError::Overflow));
+        assert_eq!(ram.len(), 2);
+        assert!(ram.check_region(0x3000, 0x2000));
+    }
+}
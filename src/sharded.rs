@@ -0,0 +1,401 @@
+//! An allocator whose address space is striped across independently locked shards.
+
+use crate::locked::LockGuard;
+use crate::{LockedRegionAllocator, Region, RegionError, RegionStorage};
+
+/// `N` [`LockedRegionAllocator`]s, each owning every `N`th stripe of the address space.
+///
+/// Address `addr` belongs to shard `(addr / stripe_size) % N`, so regions are split at
+/// stripe boundaries when they are added. An allocation locks a single shard, starting
+/// from a caller-chosen one such as the current CPU's, and only falls back to locking
+/// every shard when no shard alone has room, which is always the case for requests
+/// spanning several stripes.
+pub struct ShardedRegionAllocator<S, const N: usize> {
+    shards: [LockedRegionAllocator<S>; N],
+    stripe_shift: u32,
+}
+
+impl<S: RegionStorage, const N: usize> ShardedRegionAllocator<S, N> {
+    /// Create a [`ShardedRegionAllocator`] on top of `N` empty shards.
+    ///
+    /// Return `None` if `stripe_size` is not a power of 2 or `N` is 0.
+    pub fn with_shards(shards: [LockedRegionAllocator<S>; N], stripe_size: usize) -> Option<Self> {
+        if !stripe_size.is_power_of_two() || N == 0 {
+            return None;
+        }
+        Some(ShardedRegionAllocator {
+            shards,
+            stripe_shift: stripe_size.trailing_zeros(),
+        })
+    }
+    /// Create a [`ShardedRegionAllocator`] with default storages.
+    pub fn new(stripe_size: usize) -> Option<Self>
+    where
+        S: Default,
+    {
+        Self::with_shards(
+            core::array::from_fn(|_| LockedRegionAllocator::default()),
+            stripe_size,
+        )
+    }
+    /// Return the shard with a given index.
+    pub fn shard(&self, index: usize) -> &LockedRegionAllocator<S> {
+        &self.shards[index]
+    }
+    /// Return index of the shard owning `addr`.
+    pub fn shard_of(&self, addr: usize) -> usize {
+        (addr >> self.stripe_shift) % N
+    }
+    /// Return number of regions in all shards, counting a region split across stripes once per stripe.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.len()).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|s| s.is_empty())
+    }
+    /// Add a region like [`RegionAllocator::add`](crate::RegionAllocator::add).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `base + size` overflows or a shard's storage runs out of capacity.
+    pub fn add(&self, base: usize, size: usize) {
+        if let Err(e) = self.try_add(base, size) {
+            panic!("cannot add region: {:?}", e);
+        }
+    }
+    /// Add a region, failing if `base + size` overflows or a shard's storage is full.
+    /// Pieces in stripes before the failing one stay added.
+    pub fn try_add(&self, base: usize, size: usize) -> Result<(), RegionError> {
+        base.checked_add(size).ok_or(RegionError::Overflow)?;
+        self.pieces(base, size)
+            .try_for_each(|(i, r)| self.shards[i].try_add(r.base, r.size))
+    }
+    /// Subtract a region like [`RegionAllocator::subtract`](crate::RegionAllocator::subtract).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `base + size` overflows or a shard's storage runs out of capacity.
+    pub fn subtract(&self, base: usize, size: usize) {
+        if let Err(e) = self.try_subtract(base, size) {
+            panic!("cannot subtract region: {:?}", e);
+        }
+    }
+    /// Subtract a region, failing if `base + size` overflows or a shard's storage is full.
+    /// Pieces in stripes before the failing one stay subtracted.
+    pub fn try_subtract(&self, base: usize, size: usize) -> Result<(), RegionError> {
+        base.checked_add(size).ok_or(RegionError::Overflow)?;
+        self.pieces(base, size)
+            .try_for_each(|(i, r)| self.shards[i].try_subtract(r.base, r.size))
+    }
+    /// Allocate `[base, base + size)` if it is wholly covered, locking every shard it spans.
+    ///
+    /// Each shard counts the piece it gives as an allocation, and a failure is counted by
+    /// the shard owning `base`.
+    pub fn allocate_by_addr(&self, base: usize, size: usize) -> Result<(), RegionError> {
+        base.checked_add(size).ok_or(RegionError::Overflow)?;
+        let mut guards = self.lock_spanned(base, size);
+        let taken = self.take_covered(&mut guards, base, size);
+        if let (Err(e), Some(guard)) = (taken, &mut guards[self.shard_of(base)]) {
+            guard.record(size, Err(e));
+        }
+        taken
+    }
+    /// Allocate a region aligned to a given power of 2, trying shard `hint % N` first,
+    /// then the following shards, and finally ranges spanning several shards.
+    pub fn allocate_by_size(
+        &self,
+        size: usize,
+        alignment: usize,
+        hint: usize,
+    ) -> Result<(usize, usize), RegionError> {
+        if !alignment.is_power_of_two() {
+            return Err(RegionError::InvalidAlignment);
+        }
+        if size <= 1 << self.stripe_shift {
+            let found = (0..N).find_map(|k| {
+                let shard = &self.shards[(hint + k) % N];
+                shard.allocate_by_size(size, alignment).ok()
+            });
+            if let Some(found) = found {
+                return Ok(found);
+            }
+        }
+        self.allocate_spanning(size, alignment - 1)
+    }
+    /// Find if any region perfectly match a given range, looking through every shard it spans.
+    pub fn check_region(&self, base: usize, size: usize) -> bool {
+        let guards = self.lock_all();
+        let mut end = base;
+        self.start_of(&guards, base) == base && self.extend(&guards, &mut end) && end - base == size
+    }
+
+    /// Split `[base, base + size)` at stripe boundaries.
+    fn pieces(&self, base: usize, size: usize) -> impl Iterator<Item = (usize, Region)> + '_ {
+        let end = base.saturating_add(size);
+        let stripe = 1usize << self.stripe_shift;
+        let mut next = base;
+        core::iter::from_fn(move || {
+            if next >= end {
+                return None;
+            }
+            let stop = (next | (stripe - 1)).saturating_add(1).min(end);
+            let piece = Region {
+                base: next,
+                size: stop - next,
+            };
+            next = stop;
+            Some((self.shard_of(piece.base), piece))
+        })
+    }
+    /// Lock every shard in index order, so concurrent callers cannot deadlock.
+    fn lock_all(&self) -> [LockGuard<'_, S>; N] {
+        core::array::from_fn(|i| self.shards[i].lock())
+    }
+    /// Lock the shards `[base, base + size)` spans, in index order like
+    /// [`ShardedRegionAllocator::lock_all`].
+    fn lock_spanned(&self, base: usize, size: usize) -> [Option<LockGuard<'_, S>>; N] {
+        let mut spanned = [false; N];
+        for (i, _) in self.pieces(base, size).take(N) {
+            spanned[i] = true;
+        }
+        core::array::from_fn(|i| spanned[i].then(|| self.shards[i].lock()))
+    }
+    /// Return the guard of shard `i`, which the caller locked.
+    fn locked<'g, 'a>(
+        guards: &'g mut [Option<LockGuard<'a, S>>; N],
+        i: usize,
+    ) -> &'g mut LockGuard<'a, S> {
+        guards[i].as_mut().expect("spanned shards are locked")
+    }
+    /// Take `[base, base + size)` from the locked shards if they wholly cover it.
+    fn take_covered(
+        &self,
+        guards: &mut [Option<LockGuard<'_, S>>; N],
+        base: usize,
+        size: usize,
+    ) -> Result<(), RegionError> {
+        let shard = |i: usize| guards[i].as_ref().expect("spanned shards are locked");
+        let (mut full, mut some) = (true, false);
+        for (i, r) in self.pieces(base, size) {
+            let guard = shard(i);
+            let covering = guard.find_internal(r.base).filter(|f| f.end() > r.base);
+            full &= covering.is_some_and(|f| r.end() <= f.end());
+            some |= covering.is_some() || guard.regions.range(r.base..r.end()).next().is_some();
+        }
+        match (full, some) {
+            (true, _) => self.take(guards, base, size),
+            (false, true) => {
+                // The lowest gap, joined across the shard boundaries it runs into
+                let mut gaps = self
+                    .pieces(base, size)
+                    .filter_map(|(i, r)| shard(i).first_gap(r.base, r.end()));
+                let mut gap = gaps.next().ok_or(RegionError::NotCovered)?;
+                for next in gaps {
+                    if next.base != gap.end() {
+                        break;
+                    }
+                    gap.size += next.size;
+                }
+                Err(RegionError::PartiallyCovered(gap))
+            }
+            (false, false) => Err(RegionError::NotCovered),
+        }
+    }
+    /// Return the base of the run of regions, continued across stripe boundaries,
+    /// that ends with the region starting at `base`.
+    fn start_of(&self, guards: &[LockGuard<'_, S>; N], mut base: usize) -> usize {
+        while base != 0 && base & ((1 << self.stripe_shift) - 1) == 0 {
+            match guards[self.shard_of(base - 1)]
+                .regions
+                .range(..base)
+                .next_back()
+            {
+                Some(p) if p.end() == base => base = p.base,
+                _ => break,
+            }
+        }
+        base
+    }
+    /// Move `end` past the region starting there and every region continuing it in
+    /// the next stripes. Return false if no region starts at `end`.
+    fn extend(&self, guards: &[LockGuard<'_, S>; N], end: &mut usize) -> bool {
+        let mut found = false;
+        while let Some(r) = guards[self.shard_of(*end)]
+            .regions
+            .range(*end..=*end)
+            .next()
+        {
+            found = true;
+            *end = r.end();
+            if *end & ((1 << self.stripe_shift) - 1) != 0 {
+                break;
+            }
+        }
+        found
+    }
+    fn allocate_spanning(&self, size: usize, align: usize) -> Result<(usize, usize), RegionError> {
+        let guards = self.lock_all();
+        let base = guards
+            .iter()
+            .flat_map(|g| g.regions.range(..))
+            .find_map(|r| {
+                // Only start from regions not continuing one in the previous stripe
+                if self.start_of(&guards, r.base) != r.base {
+                    return None;
+                }
+                let mut end = r.base;
+                self.extend(&guards, &mut end);
+                let span = Region {
+                    base: r.base,
+                    size: end - r.base,
+                };
+                span.fit(size, align)
+            })
+            .ok_or(RegionError::NoFit)?;
+        self.take(&mut guards.map(Some), base, size)?;
+        Ok((base, size))
+    }
+    /// Subtract `[base, base + size)` from the locked shards, giving the pieces already
+    /// taken back if a shard fails, so a failed allocation leaves every shard as it was.
+    /// Once every piece is taken, each shard counts its own as an allocation.
+    fn take(
+        &self,
+        guards: &mut [Option<LockGuard<'_, S>>; N],
+        base: usize,
+        size: usize,
+    ) -> Result<(), RegionError> {
+        for (k, (i, r)) in self.pieces(base, size).enumerate() {
+            if let Err(e) = Self::locked(guards, i).try_subtract(r.base, r.size) {
+                for (i, r) in self.pieces(base, size).take(k) {
+                    // Merging a piece back into what it was cut from needs no room
+                    let _ = Self::locked(guards, i).try_add(r.base, r.size);
+                }
+                return Err(e);
+            }
+        }
+        for (i, r) in self.pieces(base, size) {
+            Self::locked(guards, i).record(r.size, Ok(r));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    extern crate std;
+
+    use super::ShardedRegionAllocator;
+    use crate::{Region, RegionError};
+    use std::vec::Vec;
+
+    #[test]
+    fn sharded_test() {
+        let sharded = ShardedRegionAllocator::<crate::BTreeStorage, 4>::new(0x1000).unwrap();
+        sharded.add(0x800, 0x8000);
+        assert_eq!(sharded.len(), 9);
+        assert!(sharded.shard(1).check_region(0x1000, 0x1000));
+        assert!(sharded.check_region(0x800, 0x8000));
+        // Case 1: small requests stay in the hinted shard
+        assert_eq!(
+            sharded.allocate_by_size(0x100, 0x100, 2),
+            Ok((0x2000, 0x100))
+        );
+        assert_eq!(
+            sharded.allocate_by_size(0x100, 0x100, 6),
+            Ok((0x2100, 0x100))
+        );
+        // Case 2: large requests span shards
+        assert_eq!(
+            sharded.allocate_by_size(0x3000, 0x1000, 0),
+            Ok((0x3000, 0x3000))
+        );
+        assert!(sharded.check_region(0x6000, 0x2800));
+        assert!(!sharded.check_region(0x6000, 0x1000));
+        assert_eq!(sharded.allocate_by_addr(0x7000, 0x1800), Ok(()));
+        assert_eq!(
+            sharded.allocate_by_addr(0x5000, 0x2000),
+            Err(RegionError::PartiallyCovered(Region {
+                base: 0x5000,
+                size: 0x1000
+            }))
+        );
+        // Case 3: threads hinting different shards
+        let bases: Vec<_> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4)
+                .map(|cpu| {
+                    let sharded = &sharded;
+                    scope.spawn(move || {
+                        (0..4)
+                            .filter_map(|_| sharded.allocate_by_size(0x100, 0x100, cpu).ok())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .flat_map(|t| t.join().unwrap())
+                .collect()
+        });
+        assert_eq!(bases.len(), 16);
+        sharded.subtract(0, 0x10000);
+        assert!(sharded.is_empty());
+    }
+
+    #[test]
+    fn spanned_shards_test() {
+        let sharded = ShardedRegionAllocator::<crate::BTreeStorage, 4>::new(0x1000).unwrap();
+        sharded.add(0, 0x8000);
+        // Case 1: allocating at an address locks only the shards it spans
+        {
+            let _held = sharded.shard(2).lock();
+            assert_eq!(sharded.allocate_by_addr(0x1000, 0x800), Ok(()));
+            assert_eq!(sharded.allocate_by_addr(0x3800, 0x1000), Ok(()));
+        }
+        // Case 2: each shard counts its piece of an allocation spanning several
+        assert_eq!(
+            sharded.allocate_by_size(0x3000, 0x1000, 0),
+            Ok((0x5000, 0x3000))
+        );
+        let allocated = core::array::from_fn(|i| sharded.shard(i).stats().allocated_bytes);
+        assert_eq!(allocated, [0x800, 0x1800, 0x1000, 0x1800]);
+        let allocations = core::array::from_fn(|i| sharded.shard(i).stats().allocations);
+        assert_eq!(allocations, [1, 2, 1, 2]);
+        // Case 3: failures are counted by the shard owning the base
+        assert_eq!(
+            sharded.allocate_by_addr(0x1000, 0x100),
+            Err(RegionError::NotCovered)
+        );
+        assert_eq!(sharded.shard(1).stats().failures, 1);
+    }
+
+    #[test]
+    fn failed_spanning_test() {
+        use crate::{IntrusiveStorage, LockedRegionAllocator, RegionAllocator};
+
+        #[repr(align(0x100))]
+        struct Memory([u8; 0x400]);
+
+        let mut memory = Memory([0; 0x400]);
+        let base = memory.0.as_mut_ptr() as usize;
+        // SAFETY: `memory` outlives the allocator and is not used otherwise.
+        let shards = core::array::from_fn(|_| {
+            LockedRegionAllocator::new(RegionAllocator::with_storage(unsafe {
+                IntrusiveStorage::new()
+            }))
+        });
+        let sharded = ShardedRegionAllocator::<_, 2>::with_shards(shards, 0x100).unwrap();
+        sharded.add(base, 0x400);
+        // The second shard cannot keep a remainder too small for a node, so the piece
+        // taken from the first one is given back
+        assert_eq!(
+            sharded.allocate_by_size(0x1f0, 1, 0),
+            Err(RegionError::Capacity)
+        );
+        assert_eq!(
+            sharded.allocate_by_addr(base, 0x1f0),
+            Err(RegionError::Capacity)
+        );
+        assert!(sharded.check_region(base, 0x400));
+        assert_eq!(sharded.allocate_by_size(0x200, 1, 0), Ok((base, 0x200)));
+    }
+}
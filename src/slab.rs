@@ -0,0 +1,165 @@
+//! A slab cache of fixed-size objects backed by a [`RegionAllocator`].
+
+use crate::{RegionAllocator, RegionStorage};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec;
+use alloc::vec::Vec;
+
+const BITS: usize = u64::BITS as usize;
+
+#[derive(Clone, Debug)]
+struct Slab {
+    /// One bit per object, set if the object is free.
+    free: Vec<u64>,
+    in_use: usize,
+}
+
+/// A cache serving objects of one size from slabs taken from a [`RegionAllocator`].
+///
+/// Slabs are `slab_size` bytes, aligned to their size so the slab of an object is
+/// found by masking its address. Slabs left empty by frees stay cached until
+/// [`SlabCache::shrink`] returns them to the region set.
+#[derive(Clone, Debug)]
+pub struct SlabCache {
+    object_size: usize,
+    slab_size: usize,
+    slabs: BTreeMap<usize, Slab>,
+    /// Slabs with at least one free object.
+    partial: BTreeSet<usize>,
+}
+
+impl SlabCache {
+    /// Create an empty [`SlabCache`] for objects of `object_size` bytes aligned to
+    /// `alignment`, carved from slabs of `slab_size` bytes.
+    ///
+    /// Both `alignment` and `slab_size` must be powers of 2, and a slab must hold at least one object.
+    pub fn new(object_size: usize, alignment: usize, slab_size: usize) -> Option<Self> {
+        if !alignment.is_power_of_two() || !slab_size.is_power_of_two() {
+            return None;
+        }
+        let object_size = object_size.max(1).checked_add(alignment - 1)? & !(alignment - 1);
+        if object_size > slab_size {
+            return None;
+        }
+        Some(SlabCache {
+            object_size,
+            slab_size,
+            slabs: BTreeMap::new(),
+            partial: BTreeSet::new(),
+        })
+    }
+    /// Return size of an object, rounded up to its alignment.
+    pub fn object_size(&self) -> usize {
+        self.object_size
+    }
+    /// Return number of slabs held, including empty ones.
+    pub fn slabs(&self) -> usize {
+        self.slabs.len()
+    }
+    /// Return number of objects handed out.
+    pub fn in_use(&self) -> usize {
+        self.slabs.values().map(|s| s.in_use).sum()
+    }
+    /// Allocate an object, taking a new slab from `regions` if all slabs are full.
+    pub fn allocate<S: RegionStorage>(
+        &mut self,
+        regions: &mut RegionAllocator<S>,
+    ) -> Option<usize> {
+        let base = match self.partial.first() {
+            Some(&base) => base,
+            None => self.grow(regions)?,
+        };
+        let slab = self.slabs.get_mut(&base)?;
+        let (word, bits) = slab.free.iter_mut().enumerate().find(|(_, w)| **w != 0)?;
+        let bit = bits.trailing_zeros() as usize;
+        *bits &= !(1 << bit);
+        slab.in_use += 1;
+        if slab.free.iter().all(|&w| w == 0) {
+            self.partial.remove(&base);
+        }
+        Some(base + (word * BITS + bit) * self.object_size)
+    }
+    /// Free an object returned by [`SlabCache::allocate`].
+    /// Return false if `addr` is not an object in use.
+    pub fn deallocate(&mut self, addr: usize) -> bool {
+        let base = addr & !(self.slab_size - 1);
+        let offset = addr - base;
+        let slab = match self.slabs.get_mut(&base) {
+            Some(slab) if offset.is_multiple_of(self.object_size) => slab,
+            _ => return false,
+        };
+        let i = offset / self.object_size;
+        if i >= self.slab_size / self.object_size || slab.free[i / BITS] & (1 << (i % BITS)) != 0 {
+            return false;
+        }
+        slab.free[i / BITS] |= 1 << (i % BITS);
+        slab.in_use -= 1;
+        self.partial.insert(base);
+        true
+    }
+    /// Return every empty slab to `regions`.
+    pub fn shrink<S: RegionStorage>(&mut self, regions: &mut RegionAllocator<S>) {
+        let empty: Vec<usize> = self
+            .slabs
+            .iter()
+            .filter(|(_, s)| s.in_use == 0)
+            .map(|(&base, _)| base)
+            .collect();
+        for base in empty {
+            if regions.deallocate(base, self.slab_size).is_err() {
+                return;
+            }
+            self.slabs.remove(&base);
+            self.partial.remove(&base);
+        }
+    }
+
+    fn grow<S: RegionStorage>(&mut self, regions: &mut RegionAllocator<S>) -> Option<usize> {
+        let (base, _) = regions
+            .allocate_by_size(self.slab_size, self.slab_size)
+            .ok()?;
+        let objects = self.slab_size / self.object_size;
+        let mut free = vec![!0u64; objects.div_ceil(BITS)];
+        if !objects.is_multiple_of(BITS) {
+            free[objects / BITS] = (1 << (objects % BITS)) - 1;
+        }
+        self.slabs.insert(base, Slab { free, in_use: 0 });
+        self.partial.insert(base);
+        Some(base)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SlabCache;
+    use crate::RegionAllocator;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn slab_test() {
+        let mut regions = RegionAllocator::new();
+        regions.add(0x1000, 0x2000);
+        let mut cache = SlabCache::new(0x300, 0x100, 0x1000).unwrap();
+        assert_eq!(cache.object_size(), 0x300);
+        // Five objects fit in a slab; the sixth takes a second one
+        let objects: Vec<_> = (0..6)
+            .map(|_| cache.allocate(&mut regions).unwrap())
+            .collect();
+        assert_eq!(objects[..2], [0x1000, 0x1300]);
+        assert_eq!(objects[5], 0x2000);
+        assert_eq!(cache.allocate(&mut regions), Some(0x2300));
+        assert!(regions.is_empty());
+        // Case 1: invalid frees are rejected
+        assert!(!cache.deallocate(0x1100));
+        assert!(cache.deallocate(0x2000));
+        assert!(!cache.deallocate(0x2000));
+        // Case 2: only empty slabs go back
+        assert!(cache.deallocate(0x2300));
+        cache.shrink(&mut regions);
+        assert_eq!(cache.slabs(), 1);
+        assert_eq!(cache.in_use(), 5);
+        assert!(regions.check_region(0x2000, 0x1000));
+        assert_eq!(regions.stats().allocated_bytes, 0x1000);
+        assert_eq!(cache.allocate(&mut regions), Some(0x2000));
+    }
+}
@@ -0,0 +1,205 @@
+//! Lock-free readers of a region set published RCU-style by a writer.
+
+use crate::{Endpoints, LockedRegionAllocator, Region, RegionAllocator, RegionStorage};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+/// An immutable copy of a region set, sorted by base.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Snapshot {
+    regions: Vec<Region>,
+    endpoints: Endpoints,
+}
+
+impl Snapshot {
+    /// Copy the regions of `regions`.
+    pub fn new<S: RegionStorage>(regions: &RegionAllocator<S>) -> Self {
+        Snapshot {
+            regions: regions.regions.range(..).collect(),
+            endpoints: regions.endpoints,
+        }
+    }
+    /// Return the regions, sorted by base.
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+    /// Return number of regions in the set.
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+    /// Find if any region perfectly match a given range.
+    pub fn check_region(&self, base: usize, size: usize) -> bool {
+        let i = self.regions.partition_point(|r| r.base < base);
+        self.regions.get(i) == Some(&Region { base, size })
+    }
+    /// Check whether the point is covered, under the endpoint semantics of the
+    /// allocator the snapshot was taken from.
+    pub fn check_point(&self, addr: usize) -> bool {
+        let i = self.regions.partition_point(|r| r.base <= addr);
+        let end = match i {
+            0 => return false,
+            _ => self.regions[i - 1].end(),
+        };
+        match self.endpoints {
+            Endpoints::HalfOpen => addr < end,
+            Endpoints::Closed => addr <= end,
+        }
+    }
+}
+
+/// A [`LockedRegionAllocator`] whose set is also published as a [`Snapshot`] that readers
+/// query without taking the lock.
+///
+/// Every [`SnapshotRegionAllocator::update`] rebuilds the snapshot after mutating the set,
+/// swaps it in, and waits for readers still holding the previous one before freeing it,
+/// so reads never wait on the writer while writes pay for the copy. This suits sets that
+/// are queried far more often than they change.
+///
+/// Readers are counted by the parity of the epoch they started in, which each update
+/// advances, so an update only waits for readers that began before it: those starting
+/// later see the new snapshot and cannot hold the writer up.
+pub struct SnapshotRegionAllocator<S = crate::BTreeStorage> {
+    writer: LockedRegionAllocator<S>,
+    current: AtomicPtr<Snapshot>,
+    epoch: AtomicUsize,
+    readers: [AtomicUsize; 2],
+}
+
+impl<S: RegionStorage> SnapshotRegionAllocator<S> {
+    /// Wrap a [`RegionAllocator`], publishing its current set.
+    pub fn new(inner: RegionAllocator<S>) -> Self {
+        let snapshot = Box::new(Snapshot::new(&inner));
+        SnapshotRegionAllocator {
+            writer: LockedRegionAllocator::new(inner),
+            current: AtomicPtr::new(Box::into_raw(snapshot)),
+            epoch: AtomicUsize::new(0),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+        }
+    }
+    /// Query the latest published [`Snapshot`] without locking.
+    ///
+    /// Writers wait for `f` to return before freeing the snapshot it sees, so keep it short.
+    pub fn read<R>(&self, f: impl FnOnce(&Snapshot) -> R) -> R {
+        struct Reading<'a>(&'a AtomicUsize);
+        impl Drop for Reading<'_> {
+            fn drop(&mut self) {
+                self.0.fetch_sub(1, Ordering::Release);
+            }
+        }
+        let _reading = loop {
+            let epoch = self.epoch.load(Ordering::SeqCst);
+            let readers = &self.readers[epoch % 2];
+            readers.fetch_add(1, Ordering::SeqCst);
+            let reading = Reading(readers);
+            // Counted in an epoch that has not ended, which the next update waits for
+            if self.epoch.load(Ordering::SeqCst) == epoch {
+                break reading;
+            }
+        };
+        // SAFETY: the update unpublishing the snapshot loaded waits for this epoch.
+        f(unsafe { &*self.current.load(Ordering::SeqCst) })
+    }
+    /// Mutate the set under the writer lock, then publish the result.
+    pub fn update<R>(&self, f: impl FnOnce(&mut RegionAllocator<S>) -> R) -> R {
+        let mut writer = self.writer.lock();
+        let result = f(&mut writer);
+        let snapshot = Box::into_raw(Box::new(Snapshot::new(&writer)));
+        let old = self.current.swap(snapshot, Ordering::SeqCst);
+        // Readers that may hold `old` confirmed their epoch before the swap, so before it
+        // ends here; those of earlier epochs were waited for by earlier updates
+        let epoch = self.epoch.fetch_add(1, Ordering::SeqCst);
+        while self.readers[epoch % 2].load(Ordering::SeqCst) != 0 {
+            spin_loop();
+        }
+        // SAFETY: `old` is unpublished and no reader holds it anymore.
+        drop(unsafe { Box::from_raw(old) });
+        result
+    }
+}
+
+impl<S> Drop for SnapshotRegionAllocator<S> {
+    fn drop(&mut self) {
+        // SAFETY: no reader outlives the borrow of `self`.
+        drop(unsafe { Box::from_raw(*self.current.get_mut()) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::SnapshotRegionAllocator;
+    use crate::RegionAllocator;
+    use core::hint::spin_loop;
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use std::vec::Vec;
+
+    #[test]
+    fn snapshot_test() {
+        let mut regions = RegionAllocator::new();
+        regions.add(0x1000, 0x1000);
+        let shared = SnapshotRegionAllocator::new(regions);
+        assert!(shared.read(|s| s.check_region(0x1000, 0x1000)));
+        // Readers run without the lock while a writer publishes new versions
+        let seen: Vec<_> = std::thread::scope(|scope| {
+            let readers: Vec<_> = (0..3)
+                .map(|_| scope.spawn(|| (0..1000).map(|_| shared.read(|s| s.len())).max()))
+                .collect();
+            for i in 1..100 {
+                shared.update(|r| r.add(0x1000 + i * 0x2000, 0x1000));
+            }
+            readers.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        assert!(seen.iter().all(|&n| n.is_some_and(|n| n <= 100)));
+        shared.update(|r| r.subtract(0x1800, 0x10000));
+        assert!(shared.read(|s| s.check_point(0x1000) && !s.check_point(0x2000)));
+        assert!(shared.read(|s| s.check_point(0x11800) && !s.check_point(0x11000)));
+        assert_eq!(shared.read(|s| s.len()), 93);
+    }
+
+    #[test]
+    fn grace_period_test() {
+        let shared = SnapshotRegionAllocator::new(RegionAllocator::new());
+        let [old_reading, new_reading, old_done, new_done] =
+            [(); 4].map(|_| AtomicBool::new(false));
+        let wait = |flag: &AtomicBool| {
+            while !flag.load(Ordering::SeqCst) {
+                spin_loop();
+            }
+        };
+        std::thread::scope(|scope| {
+            let old = scope.spawn(|| {
+                shared.read(|_| {
+                    old_reading.store(true, Ordering::SeqCst);
+                    wait(&old_done);
+                })
+            });
+            wait(&old_reading);
+            let writer = scope.spawn(|| shared.update(|r| r.add(0x1000, 0x1000)));
+            while shared.epoch.load(Ordering::SeqCst) == 0 {
+                spin_loop();
+            }
+            // A reader starting after the swap sees the new set
+            let new = scope.spawn(|| {
+                shared.read(|s| {
+                    new_reading.store(true, Ordering::SeqCst);
+                    wait(&new_done);
+                    s.len()
+                })
+            });
+            wait(&new_reading);
+            assert!(!writer.is_finished());
+            // The update waits only for readers that may hold the previous snapshot
+            old_done.store(true, Ordering::SeqCst);
+            writer.join().unwrap();
+            old.join().unwrap();
+            new_done.store(true, Ordering::SeqCst);
+            assert_eq!(new.join().unwrap(), 1);
+        });
+    }
+}
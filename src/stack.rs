@@ -0,0 +1,115 @@
+//! A stack (LIFO) allocator over a single region.
+
+/// A position in a [`StackRegion`] to [rewind](StackRegion::rewind) to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Marker(usize);
+
+/// An allocation from a [`StackRegion`], to be given back to
+/// [`StackRegion::deallocate`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct StackAllocation {
+    pub base: usize,
+    pub size: usize,
+    /// The top of the stack before the allocation and its alignment padding.
+    below: usize,
+}
+
+/// An allocator over `[base, base + size)` whose allocations are freed in reverse order.
+///
+/// Both allocation and free are O(1). Besides freeing the most recent allocation,
+/// everything allocated after a [`Marker`] can be released at once.
+#[derive(Clone, Debug)]
+pub struct StackRegion {
+    base: usize,
+    end: usize,
+    top: usize,
+}
+
+impl StackRegion {
+    /// Create an empty [`StackRegion`] over `[base, base + size)`,
+    /// typically a region taken from a [`RegionAllocator`](crate::RegionAllocator).
+    pub fn new(base: usize, size: usize) -> Option<Self> {
+        Some(StackRegion {
+            base,
+            end: base.checked_add(size)?,
+            top: base,
+        })
+    }
+    /// Return number of bytes in use, including alignment padding.
+    pub fn used(&self) -> usize {
+        self.top - self.base
+    }
+    /// Return number of bytes above the top of the stack.
+    pub fn remaining(&self) -> usize {
+        self.end - self.top
+    }
+    /// Push `size` bytes aligned to a given power of 2.
+    pub fn allocate(&mut self, size: usize, alignment: usize) -> Option<StackAllocation> {
+        if !alignment.is_power_of_two() {
+            return None;
+        }
+        let base = self.top.checked_add(alignment - 1)? & !(alignment - 1);
+        let top = base.checked_add(size)?;
+        if top > self.end {
+            return None;
+        }
+        let below = self.top;
+        self.top = top;
+        Some(StackAllocation { base, size, below })
+    }
+    /// Pop the most recent allocation, along with the padding that aligned it.
+    /// Return false, leaving the stack unchanged, if it is not the most recent one.
+    pub fn deallocate(&mut self, allocation: StackAllocation) -> bool {
+        let StackAllocation { base, size, below } = allocation;
+        if below < self.base || below > base || base.checked_add(size) != Some(self.top) {
+            return false;
+        }
+        self.top = below;
+        true
+    }
+    /// Return a marker for the current top of the stack.
+    pub fn marker(&self) -> Marker {
+        Marker(self.top)
+    }
+    /// Free everything allocated since `marker` was taken.
+    /// Markers above the current top, which were already rewound past, are ignored.
+    pub fn rewind(&mut self, marker: Marker) {
+        if self.base <= marker.0 && marker.0 <= self.top {
+            self.top = marker.0;
+        }
+    }
+    /// Free every allocation.
+    pub fn reset(&mut self) {
+        self.top = self.base;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StackRegion;
+
+    #[test]
+    fn stack_test() {
+        let mut stack = StackRegion::new(0x1000, 0x100).unwrap();
+        let a = stack.allocate(0x10, 1).unwrap();
+        let mark = stack.marker();
+        let b = stack.allocate(0x8, 0x40).unwrap();
+        assert_eq!((a.base, b.base), (0x1000, 0x1040));
+        // Case 1: out-of-order free is refused, and popping drops the padding too
+        assert!(!stack.deallocate(a));
+        assert!(stack.deallocate(b));
+        assert_eq!(stack.used(), 0x10);
+        assert!(!stack.deallocate(b));
+        // Case 2: rewinding to a marker
+        stack.allocate(0x20, 1).unwrap();
+        stack.allocate(0x20, 1).unwrap();
+        stack.rewind(mark);
+        assert_eq!(stack.used(), 0x10);
+        assert!(stack.deallocate(a));
+        assert_eq!(stack.used(), 0);
+        // Case 3: exhaustion
+        assert_eq!(stack.allocate(0x101, 1), None);
+        assert_eq!(stack.allocate(0x100, 1).map(|a| a.base), Some(0x1000));
+        assert_eq!(stack.remaining(), 0);
+    }
+}
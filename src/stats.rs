@@ -0,0 +1,388 @@
+//! Counters of the allocations made from a region set.
+
+use crate::{Region, RegionAllocator, RegionError, RegionStorage};
+
+/// A summary of the allocations made from a [`RegionAllocator`] since it was created or
+/// its counters were last reset.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RegionStats {
+    /// Number of successful allocations.
+    pub allocations: u64,
+    /// Number of allocations that failed.
+    pub failures: u64,
+    /// Bytes in the set.
+    pub free_bytes: usize,
+    /// Bytes handed out by allocations and not given back by
+    /// [`RegionAllocator::deallocate`].
+    pub allocated_bytes: usize,
+    /// The highest value `allocated_bytes` reached.
+    pub peak_allocated_bytes: usize,
+}
+
+/// Indicators of how scattered the free bytes of a [`RegionAllocator`] are.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Fragmentation {
+    /// Number of free regions.
+    pub regions: usize,
+    /// Bytes in the set.
+    pub free_bytes: usize,
+    /// Size of the largest free region.
+    pub largest: usize,
+    /// Mean size of the free regions, rounded down.
+    pub mean: usize,
+    /// Median size of the free regions, the lower one of the middle two for an even count.
+    pub median: usize,
+}
+
+/// How close to exhaustion a [`RegionAllocator`] ran since it was created or its
+/// watermarks were last reset.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Watermarks {
+    /// The fewest bytes left in the set after a change that removed some, or the bytes
+    /// now in the set if none did since.
+    pub min_free_bytes: usize,
+    /// The most regions the set held at once.
+    pub max_regions: usize,
+}
+
+/// The counters kept inline in every [`RegionAllocator`].
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Counters {
+    allocations: u64,
+    failures: u64,
+    allocated: usize,
+    peak: usize,
+    /// Bytes in the set, `None` until counted again after the set was rebuilt.
+    free: Option<usize>,
+    min_free: Option<usize>,
+    max_regions: usize,
+}
+
+impl Counters {
+    pub(crate) const fn new() -> Self {
+        Counters {
+            allocations: 0,
+            failures: 0,
+            allocated: 0,
+            peak: 0,
+            free: None,
+            min_free: None,
+            max_regions: 0,
+        }
+    }
+    /// Count an allocation of `size` bytes, or a failed one if `None`.
+    fn record(&mut self, size: Option<usize>) {
+        match size {
+            Some(size) => {
+                self.allocations += 1;
+                self.allocated = self.allocated.saturating_add(size);
+                self.peak = self.peak.max(self.allocated);
+            }
+            None => self.failures += 1,
+        }
+    }
+    /// Turn a counted allocation of `size` bytes into a failed one, once it is given back.
+    pub(crate) fn revert(&mut self, size: usize) {
+        self.allocations -= 1;
+        self.failures += 1;
+        self.allocated = self.allocated.saturating_sub(size);
+    }
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Count an allocation of `size` bytes that took `taken` or failed and tell the
+    /// observer.
+    pub(crate) fn record(&mut self, size: usize, taken: Result<Region, RegionError>) {
+        self.counters.record(taken.ok().map(|r| r.size));
+        match taken {
+            Ok(region) => self.notify(|o| o.allocated(region)),
+            Err(e) => self.notify(|o| o.allocation_failed(size, e)),
+        }
+    }
+    /// Account for a splice that replaced regions of `removed` bytes with `added` bytes,
+    /// lowering the free bytes watermark if the set shrank.
+    pub(crate) fn spliced(&mut self, removed: usize, added: usize) {
+        if let Some(free) = &mut self.counters.free {
+            *free = *free - removed + added;
+        }
+        if removed > added {
+            self.lower_min_free();
+        }
+        self.count_regions();
+    }
+    /// Account for a change to the set that was not made through a single splice, after
+    /// which the free bytes are counted again, lowering the watermark if they went down.
+    pub(crate) fn rebuilt(&mut self) {
+        let before = self.counters.free.take();
+        if before.is_some_and(|before| self.free_bytes() < before) {
+            self.lower_min_free();
+        }
+        self.count_regions();
+        self.notify(|o| o.rebuilt());
+    }
+    fn lower_min_free(&mut self) {
+        let free = self.free_bytes();
+        let min = self.counters.min_free.map_or(free, |min| min.min(free));
+        self.counters.min_free = Some(min);
+    }
+    fn count_regions(&mut self) {
+        let max = &mut self.counters.max_regions;
+        *max = (*max).max(self.regions.len());
+    }
+    fn free_bytes(&mut self) -> usize {
+        let regions = &self.regions;
+        *self
+            .counters
+            .free
+            .get_or_insert_with(|| regions.range(..).map(|r| r.size).sum())
+    }
+    /// Give back a region handed out by an allocation.
+    ///
+    /// This is [`RegionAllocator::add_checked`] under the name used by allocators, and
+    /// the only way of freeing that [`RegionAllocator::stats`] counts.
+    pub fn deallocate(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        let freed = self.add_checked(base, size);
+        #[cfg(feature = "log")]
+        if let Err(e) = &freed {
+            log::warn!("cannot free {:#x} bytes at {:#x}: {}", size, base, e);
+        }
+        freed?;
+        self.counters.allocated = self.counters.allocated.saturating_sub(size);
+        Ok(())
+    }
+    /// Return the allocation counters along with the bytes now free.
+    ///
+    /// Free bytes are kept up to date as regions are added and subtracted, but counted by
+    /// visiting every region after a set operation that rebuilds the set.
+    pub fn stats(&self) -> RegionStats {
+        let c = &self.counters;
+        RegionStats {
+            allocations: c.allocations,
+            failures: c.failures,
+            free_bytes: c
+                .free
+                .unwrap_or_else(|| self.regions.range(..).map(|r| r.size).sum()),
+            allocated_bytes: c.allocated,
+            peak_allocated_bytes: c.peak,
+        }
+    }
+    /// Return the fragmentation indicators of the set.
+    ///
+    /// This visits every region a few dozen times to find the median without allocating.
+    pub fn fragmentation(&self) -> Fragmentation {
+        let sizes = || self.regions.range(..).map(|r| r.size);
+        let regions = self.regions.len();
+        let free_bytes = sizes().sum();
+        let largest = sizes().max().unwrap_or(0);
+        // The median is the smallest size with at least half the regions at most that big
+        let (mut low, mut high) = (0, largest);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match sizes().filter(|&s| s <= mid).count() < regions.div_ceil(2) {
+                true => low = mid + 1,
+                false => high = mid,
+            }
+        }
+        Fragmentation {
+            regions,
+            free_bytes,
+            largest,
+            mean: free_bytes.checked_div(regions).unwrap_or(0),
+            median: low,
+        }
+    }
+    /// Return the number of free regions of each power-of-two size class, entry `i`
+    /// counting sizes from `1 << i` up to, not including, `2 << i`.
+    ///
+    /// This is [`RegionStorage::histogram`], which a [`SizeClassStorage`] keeps up to date
+    /// as the set changes and other storages compute by visiting every region.
+    ///
+    /// [`SizeClassStorage`]: crate::SizeClassStorage
+    pub fn free_histogram(&self) -> [usize; usize::BITS as usize] {
+        self.regions.histogram()
+    }
+    /// Return the unusable free space index for requests of `size` bytes: the fraction
+    /// of free bytes in regions too small to hold one, 0 without free bytes.
+    pub fn unusable_index(&self, size: usize) -> f64 {
+        let sizes = || self.regions.range(..).map(|r| r.size);
+        let free: usize = sizes().sum();
+        let unusable: usize = sizes().filter(|&s| s < size).sum();
+        match free {
+            0 => 0.0,
+            _ => unusable as f64 / free as f64,
+        }
+    }
+    /// Reset the allocation and failure counts, and the peak to the bytes still allocated.
+    pub fn reset_stats(&mut self) {
+        let c = &mut self.counters;
+        c.allocations = 0;
+        c.failures = 0;
+        c.peak = c.allocated;
+    }
+    /// Return the lowest free bytes and the highest region count reached.
+    pub fn watermarks(&self) -> Watermarks {
+        let c = &self.counters;
+        Watermarks {
+            min_free_bytes: c.min_free.unwrap_or_else(|| self.stats().free_bytes),
+            max_regions: c.max_regions.max(self.regions.len()),
+        }
+    }
+    /// Reset the watermarks to the current state of the set, such as once it is populated
+    /// at boot or after a post-mortem report.
+    pub fn reset_watermarks(&mut self) {
+        self.counters.min_free = None;
+        self.counters.max_regions = self.regions.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fragmentation, RegionStats, Watermarks};
+    use crate::{RegionError, StaticRegionAllocator};
+
+    #[test]
+    fn stats_test() {
+        let mut regions = StaticRegionAllocator::<4>::default();
+        regions.add(0x1000, 0x4000);
+        assert_eq!(
+            regions.allocate_by_size(0x1000, 0x1000),
+            Ok((0x1000, 0x1000))
+        );
+        assert_eq!(regions.allocate_by_addr(0x3000, 0x2000), Ok(()));
+        assert_eq!(regions.allocate_by_size(0x2000, 1), Err(RegionError::NoFit));
+        // Case 1: allocations, failures and bytes
+        let stats = RegionStats {
+            allocations: 2,
+            failures: 1,
+            free_bytes: 0x1000,
+            allocated_bytes: 0x3000,
+            peak_allocated_bytes: 0x3000,
+        };
+        assert_eq!(regions.stats(), stats);
+        // Case 2: deallocating lowers the bytes allocated but not the peak
+        assert_eq!(regions.deallocate(0x3000, 0x2000), Ok(()));
+        assert_eq!(
+            regions.deallocate(0x3000, 0x2000),
+            Err(RegionError::Overlapping)
+        );
+        assert_eq!(regions.stats().allocated_bytes, 0x1000);
+        assert_eq!(regions.stats().peak_allocated_bytes, 0x3000);
+        // Case 3: a rolled back allocation counts as failed
+        let failed = regions.allocate_with(0x1000, 1, |_, _| Err::<(), _>(RegionError::NoFit));
+        assert_eq!(failed, Err(RegionError::NoFit));
+        assert_eq!(regions.stats().allocations, 2);
+        assert_eq!(regions.stats().failures, 2);
+        // Case 4: resetting keeps the bytes still allocated
+        regions.reset_stats();
+        let stats = RegionStats {
+            free_bytes: 0x3000,
+            allocated_bytes: 0x1000,
+            peak_allocated_bytes: 0x1000,
+            ..RegionStats::default()
+        };
+        assert_eq!(regions.stats(), stats);
+    }
+
+    #[test]
+    fn watermarks_test() {
+        let mut regions = StaticRegionAllocator::<4>::default();
+        regions.add(0, 0x1000);
+        regions.add(0x2000, 0x3000);
+        let fresh = Watermarks {
+            min_free_bytes: 0x4000,
+            max_regions: 2,
+        };
+        assert_eq!(regions.watermarks(), fresh);
+        // Case 1: the lowest free bytes after an allocation and the most regions
+        assert_eq!(regions.allocate_by_addr(0x3000, 0x1000), Ok(()));
+        assert_eq!(regions.allocate_by_size(0x800, 0x800), Ok((0, 0x800)));
+        assert_eq!(regions.deallocate(0x3000, 0x1000), Ok(()));
+        assert_eq!(regions.deallocate(0, 0x800), Ok(()));
+        let low = Watermarks {
+            min_free_bytes: 0x2800,
+            max_regions: 3,
+        };
+        assert_eq!(regions.watermarks(), low);
+        assert_eq!(regions.stats().free_bytes, 0x4000);
+        // Case 2: free bytes are counted again after the set is rebuilt
+        assert_eq!(regions.clamp(0x800, 0x4000), Ok(()));
+        assert_eq!(regions.allocate_by_size(0x800, 0x800), Ok((0x800, 0x800)));
+        assert_eq!(regions.watermarks().min_free_bytes, 0x2800);
+        assert_eq!(regions.stats().free_bytes, 0x2800);
+        // Case 3: resetting starts from the current state
+        regions.reset_watermarks();
+        let reset = Watermarks {
+            min_free_bytes: 0x2800,
+            max_regions: 1,
+        };
+        assert_eq!(regions.watermarks(), reset);
+        // Case 4: lows left by subtractions and set operations count too
+        regions.subtract(0x2000, 0x1000);
+        regions.add(0x2000, 0x1000);
+        assert_eq!(regions.watermarks().min_free_bytes, 0x1800);
+        assert_eq!(regions.clamp(0x3000, 0x800), Ok(()));
+        regions.add(0, 0x3000);
+        assert_eq!(regions.watermarks().min_free_bytes, 0x800);
+    }
+
+    #[test]
+    fn fragmentation_test() {
+        let mut regions = StaticRegionAllocator::<8>::default();
+        assert_eq!(regions.fragmentation(), Fragmentation::default());
+        assert_eq!(regions.unusable_index(1), 0.0);
+        [
+            (0, 0x1000),
+            (0x2000, 0x1000),
+            (0x4000, 0x2000),
+            (0x8000, 0x4000),
+        ]
+        .iter()
+        .for_each(|&(base, size)| regions.add(base, size));
+        // Case 1: counts and sizes
+        let fragmentation = Fragmentation {
+            regions: 4,
+            free_bytes: 0x8000,
+            largest: 0x4000,
+            mean: 0x2000,
+            median: 0x1000,
+        };
+        assert_eq!(regions.fragmentation(), fragmentation);
+        regions.add(0x10000, 0x3000);
+        assert_eq!(regions.fragmentation().median, 0x2000);
+        // Case 2: the share of free bytes too small for a request
+        assert_eq!(regions.unusable_index(0x1000), 0.0);
+        assert_eq!(
+            regions.unusable_index(0x2000),
+            0x2000 as f64 / 0xb000 as f64
+        );
+        assert_eq!(regions.unusable_index(0x5000), 1.0);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn free_histogram_test() {
+        use crate::{RegionAllocator, SizeClassStorage};
+
+        let mut scanned = StaticRegionAllocator::<8>::default();
+        let mut bucketed = RegionAllocator::with_storage(SizeClassStorage::new());
+        for &(base, size) in &[
+            (0, 1),
+            (0x10, 3),
+            (0x20, 2),
+            (0x1000, 0x1000),
+            (0x4000, 0x1fff),
+        ] {
+            scanned.add(base, size);
+            bucketed.add(base, size);
+        }
+        let mut expected = [0; usize::BITS as usize];
+        expected[..13].copy_from_slice(&[1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        assert_eq!(scanned.free_histogram(), expected);
+        assert_eq!(bucketed.free_histogram(), expected);
+        // Case 1: buckets follow merging
+        bucketed.add(0x2000, 0x2000);
+        expected[12] = 0;
+        expected[14] = 1;
+        assert_eq!(bucketed.free_histogram(), expected);
+    }
+}
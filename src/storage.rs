@@ -0,0 +1,303 @@
+//! Containers that hold the region set of a [`RegionAllocator`](crate::RegionAllocator).
+
+use crate::Region;
+use core::cmp::max;
+use core::fmt;
+use core::ops::{Bound, RangeBounds, RangeInclusive};
+
+#[cfg(feature = "alloc")]
+mod arena;
+mod array;
+#[cfg(feature = "alloc")]
+mod cow;
+#[cfg(feature = "alloc")]
+mod frozen;
+mod intrusive;
+#[cfg(feature = "alloc")]
+mod size_class;
+mod slice;
+#[cfg(feature = "soa")]
+mod soa;
+#[cfg(feature = "alloc")]
+mod tree;
+#[cfg(feature = "alloc")]
+mod vec;
+
+#[cfg(feature = "alloc")]
+pub use arena::{ArenaIter, ArenaStorage};
+pub use array::ArrayStorage;
+#[cfg(feature = "alloc")]
+pub use cow::CowStorage;
+#[cfg(feature = "alloc")]
+pub use frozen::FrozenStorage;
+pub use intrusive::{IntrusiveIter, IntrusiveStorage};
+#[cfg(feature = "alloc")]
+pub use size_class::SizeClassStorage;
+pub use slice::SliceStorage;
+#[cfg(feature = "soa")]
+pub use soa::SoaStorage;
+#[cfg(feature = "alloc")]
+pub use tree::BTreeStorage;
+#[cfg(feature = "alloc")]
+pub use vec::VecStorage;
+
+/// The error returned when a storage has no room for more regions, either because it is
+/// bounded or because the heap could not grow it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CapacityError;
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("storage is out of capacity")
+    }
+}
+
+impl core::error::Error for CapacityError {}
+
+/// An ordered container of disjoint regions.
+///
+/// The allocator keeps all merging and splitting logic to itself and only relies on
+/// a storage to look regions up by base address and to replace a run of them.
+pub trait RegionStorage {
+    /// Iterator over stored regions in ascending address order.
+    type Iter<'a>: DoubleEndedIterator<Item = Region>
+    where
+        Self: 'a;
+
+    /// Return number of regions stored.
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Iterate over the regions whose base lies in `bases`.
+    fn range<R: RangeBounds<usize>>(&self, bases: R) -> Self::Iter<'_>;
+    /// Remove all regions whose base lies in `bases`, then insert `with`.
+    ///
+    /// `with` is sorted and disjoint from every region left in the storage.
+    /// If the result does not fit, the storage is left unchanged and [`CapacityError`] is returned.
+    ///
+    /// The replacement is atomic: implementations must not remove any region before the
+    /// insertion is certain to succeed, and must restore the previous contents if a panic
+    /// unwinds through them, so that no region is ever lost.
+    fn splice(
+        &mut self,
+        bases: RangeInclusive<usize>,
+        with: &[Region],
+    ) -> Result<(), CapacityError>;
+    /// Find a base for `size` bytes aligned to `align + 1`, where `align + 1` is a power of 2.
+    ///
+    /// The default implementation returns the lowest fitting base.
+    fn find_fit(&self, size: usize, align: usize) -> Option<usize> {
+        self.range(..).find_map(|r| r.fit(size, align))
+    }
+    /// Count the regions of each size class, entry `i` counting sizes from `1 << i` up to,
+    /// not including, `2 << i`.
+    ///
+    /// The default implementation visits every region.
+    fn histogram(&self) -> [usize; usize::BITS as usize] {
+        let mut counts = [0; usize::BITS as usize];
+        for r in self.range(..) {
+            counts[r.size.ilog2() as usize] += 1;
+        }
+        counts
+    }
+}
+
+/// A [`RegionStorage`] whose operations never allocate from the heap.
+///
+/// Only storages of this kind can back the allocations made from interrupt context
+/// by [`LockedRegionAllocator::try_allocate_by_size`](crate::LockedRegionAllocator::try_allocate_by_size).
+pub trait HeapFreeStorage: RegionStorage {}
+
+/// Return the index range of `items`, sorted by `base`, whose bases lie in `bases`.
+fn index_range<T, R, F>(items: &[T], bases: &R, base: F) -> (usize, usize)
+where
+    R: RangeBounds<usize>,
+    F: Fn(&T) -> usize,
+{
+    let start = match bases.start_bound() {
+        Bound::Included(&b) => items.partition_point(|x| base(x) < b),
+        Bound::Excluded(&b) => items.partition_point(|x| base(x) <= b),
+        Bound::Unbounded => 0,
+    };
+    let end = match bases.end_bound() {
+        Bound::Included(&b) => items.partition_point(|x| base(x) <= b),
+        Bound::Excluded(&b) => items.partition_point(|x| base(x) < b),
+        Bound::Unbounded => items.len(),
+    };
+    (start, max(start, end))
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::{
+        ArenaStorage, BTreeStorage, CowStorage, RegionStorage, SizeClassStorage, VecStorage,
+    };
+    use crate::{RegionAllocator, RegionError};
+    use alloc::vec::Vec;
+
+    fn regions<S: RegionStorage>(alloc: &RegionAllocator<S>) -> Vec<(usize, usize)> {
+        alloc.regions.range(..).map(|r| (r.base, r.size)).collect()
+    }
+
+    /// Run the same random operations against `storage` and a [`BTreeStorage`].
+    /// Allocation results are only compared if `same_fit` is set.
+    fn agree_with_tree<S: RegionStorage>(storage: S, same_fit: bool) {
+        let mut tree = RegionAllocator::<BTreeStorage>::default();
+        let mut vec = RegionAllocator::with_storage(storage);
+        let mut seed = 0x2545_f491_u32;
+        for _ in 0..2000 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let base = (seed % 1000) as usize;
+            let size = (seed >> 10) as usize % 64;
+            match seed >> 28 {
+                0..=6 => {
+                    tree.add(base, size + 1);
+                    vec.add(base, size + 1);
+                }
+                7..=12 => {
+                    tree.subtract(base, size);
+                    vec.subtract(base, size);
+                }
+                _ if same_fit => assert_eq!(
+                    tree.allocate_by_size(size + 1, 1 << (seed % 4)),
+                    vec.allocate_by_size(size + 1, 1 << (seed % 4))
+                ),
+                _ => continue,
+            }
+            assert_eq!(regions(&tree), regions(&vec));
+        }
+    }
+
+    #[test]
+    fn vec_storage() {
+        agree_with_tree(VecStorage::with_capacity(8), true);
+    }
+
+    #[test]
+    fn cow_storage() {
+        agree_with_tree(CowStorage::new(VecStorage::new()), true);
+        let mut base = RegionAllocator::with_storage(CowStorage::<BTreeStorage>::default());
+        base.add(0x1000, 0x4000);
+        // Clones share the storage until they change, leaving the others as they were
+        let mut plan = base.clone();
+        assert!(base.regions.is_shared());
+        assert_eq!(plan.allocate_by_size(0x1000, 0x1000), Ok((0x1000, 0x1000)));
+        assert!(!base.regions.is_shared());
+        assert_eq!(regions(&base), [(0x1000, 0x4000)]);
+        assert_eq!(regions(&plan), [(0x2000, 0x3000)]);
+    }
+
+    #[test]
+    fn arena_storage() {
+        agree_with_tree(ArenaStorage::new(), true);
+        let mut alloc = RegionAllocator::with_storage(ArenaStorage::with_capacity(4));
+        for i in 0..100 {
+            alloc.add(i * 100, 50);
+            alloc.subtract(i * 100, 50);
+            alloc.add(0, 10);
+            alloc.add(20, 10);
+            alloc.add(10, 10);
+            alloc.subtract(0, 30);
+        }
+        assert!(alloc.is_empty());
+        assert_eq!(alloc.regions.capacity(), 4);
+        // Churn with every node in use does not grow the pool either
+        let mut alloc = RegionAllocator::with_storage(ArenaStorage::with_capacity(3));
+        let capacity = alloc.regions.capacity();
+        for i in 0..capacity {
+            alloc.add(i * 100, 50);
+        }
+        for i in 0..100 {
+            let base = i % capacity * 100;
+            alloc.subtract(base, 50);
+            alloc.add(base, 50);
+            alloc.add(base + 50, 10);
+            alloc.subtract(base + 40, 20);
+        }
+        assert_eq!(alloc.len(), capacity);
+        assert!(alloc.check_region(0, 40));
+        assert_eq!(alloc.regions.capacity(), capacity);
+    }
+
+    #[test]
+    fn intrusive_storage() {
+        use super::IntrusiveStorage;
+
+        let mut memory = [0usize; 1024];
+        let top = memory.as_mut_ptr() as usize;
+        let len = core::mem::size_of_val(&memory);
+        // SAFETY: `memory` outlives the allocator and is not used otherwise.
+        let mut alloc = RegionAllocator::with_storage(unsafe { IntrusiveStorage::new() });
+        let mut tree = RegionAllocator::new();
+        let mut seed = 0x9e37_79b9_u32;
+        for _ in 0..2000 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let base = (seed as usize % (len / 64)) * 64;
+            let size = ((seed >> 20) as usize % 8 + 1) * 64;
+            let size = size.min(len - base);
+            if seed >> 31 == 0 {
+                alloc.add(top + base, size);
+                tree.add(top + base, size);
+            } else {
+                alloc.subtract(top + base, size);
+                tree.subtract(top + base, size);
+            }
+            assert_eq!(regions(&alloc), regions(&tree));
+        }
+        // Pieces too small for a node are rejected
+        alloc.add(top, len);
+        assert_eq!(
+            alloc.try_subtract(top + 8, len - 16),
+            Err(RegionError::Capacity)
+        );
+        assert!(alloc.check_region(top, len));
+    }
+
+    #[cfg(feature = "soa")]
+    #[test]
+    fn soa_storage() {
+        agree_with_tree(super::SoaStorage::new(), true);
+    }
+
+    #[test]
+    fn size_class_storage() {
+        agree_with_tree(SizeClassStorage::new(), false);
+        let mut alloc = RegionAllocator::with_storage(SizeClassStorage::new());
+        alloc.add(0, 10);
+        alloc.add(100, 1000);
+        alloc.add(2000, 100);
+        // The lower but larger region at 100 is left intact
+        assert_eq!(alloc.allocate_by_size(64, 1), Ok((2000, 64)));
+        assert_eq!(alloc.allocate_by_size(36, 1), Ok((2064, 36)));
+        assert_eq!(alloc.allocate_by_size(8, 1), Ok((0, 8)));
+        assert_eq!(alloc.allocate_by_size(512, 256), Ok((256, 512)));
+        assert_eq!(alloc.allocate_by_size(2048, 1), Err(RegionError::NoFit));
+    }
+
+    #[test]
+    fn staged_rollback() {
+        extern crate std;
+        use super::tree::{stage, unstage, Staged};
+        use crate::Region;
+        use alloc::collections::BTreeMap;
+
+        let mut map = BTreeMap::from([(0, 10), (20, 30), (40, 50)]);
+        let before = map.clone();
+        let with = [Region { base: 0, size: 5 }, Region { base: 8, size: 2 }];
+        // A panic between staging and committing restores the map
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut staged = Staged::new(&mut map, |m| unstage(m, with.iter().copied()));
+            stage(&mut staged, with.iter().copied());
+            assert_eq!(staged.len(), 4);
+            panic!("interrupted");
+        }));
+        assert!(result.is_err());
+        assert_eq!(map, before);
+    }
+}
@@ -0,0 +1,239 @@
+use super::{CapacityError, RegionStorage};
+use crate::Region;
+use alloc::vec::Vec;
+use core::ops::{Bound, RangeBounds, RangeInclusive};
+
+const NIL: usize = usize::MAX;
+
+#[derive(Clone, Debug)]
+struct Node {
+    region: Region,
+    prev: usize,
+    next: usize,
+}
+
+/// A storage keeping regions in a linked list whose nodes live in an internal pool.
+///
+/// Nodes released by merging and splitting go to a free list and are reused by later
+/// insertions, so once the pool has grown to the peak region count (or was created
+/// with [`ArenaStorage::with_capacity`]) no operation touches the global heap.
+/// Lookups walk the list, which suits the short sets found in memory maps.
+#[derive(Clone, Debug)]
+pub struct ArenaStorage {
+    nodes: Vec<Node>,
+    head: usize,
+    tail: usize,
+    free: usize,
+    len: usize,
+}
+
+impl ArenaStorage {
+    /// Create an empty [`ArenaStorage`].
+    pub fn new() -> Self {
+        ArenaStorage {
+            nodes: Vec::new(),
+            head: NIL,
+            tail: NIL,
+            free: NIL,
+            len: 0,
+        }
+    }
+    /// Create an empty [`ArenaStorage`] with a pool of `capacity` nodes.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut storage = ArenaStorage::new();
+        storage.reserve(capacity);
+        storage
+    }
+    /// Make sure at least `additional` more regions can be stored without allocating.
+    pub fn reserve(&mut self, additional: usize) {
+        let pooled = self.nodes.len() - self.len;
+        if additional > pooled {
+            self.nodes.reserve(additional - pooled);
+        }
+    }
+    /// Return number of nodes the pool can hold without allocating.
+    pub fn capacity(&self) -> usize {
+        self.nodes.capacity()
+    }
+
+    /// Find the first node whose base is not below `bound`.
+    fn lower(&self, bound: Bound<&usize>) -> usize {
+        let mut i = self.head;
+        while i != NIL {
+            let base = self.nodes[i].region.base;
+            let inside = match bound {
+                Bound::Included(&b) => base >= b,
+                Bound::Excluded(&b) => base > b,
+                Bound::Unbounded => true,
+            };
+            if inside {
+                break;
+            }
+            i = self.nodes[i].next;
+        }
+        i
+    }
+    /// Find the last node whose base is not above `bound`.
+    fn upper(&self, bound: Bound<&usize>) -> usize {
+        let mut i = self.tail;
+        while i != NIL {
+            let base = self.nodes[i].region.base;
+            let inside = match bound {
+                Bound::Included(&b) => base <= b,
+                Bound::Excluded(&b) => base < b,
+                Bound::Unbounded => true,
+            };
+            if inside {
+                break;
+            }
+            i = self.nodes[i].prev;
+        }
+        i
+    }
+    fn alloc_node(&mut self, region: Region) -> usize {
+        let node = Node {
+            region,
+            prev: NIL,
+            next: NIL,
+        };
+        if self.free == NIL {
+            self.nodes.push(node);
+            self.nodes.len() - 1
+        } else {
+            let i = self.free;
+            self.free = self.nodes[i].next;
+            self.nodes[i] = node;
+            i
+        }
+    }
+    fn free_node(&mut self, i: usize) {
+        self.nodes[i].next = self.free;
+        self.free = i;
+    }
+}
+
+impl Default for ArenaStorage {
+    fn default() -> Self {
+        ArenaStorage::new()
+    }
+}
+
+/// Iterator over a range of an [`ArenaStorage`].
+pub struct ArenaIter<'a> {
+    storage: &'a ArenaStorage,
+    front: usize,
+    back: usize,
+}
+
+impl Iterator for ArenaIter<'_> {
+    type Item = Region;
+
+    fn next(&mut self) -> Option<Region> {
+        if self.front == NIL {
+            return None;
+        }
+        let node = &self.storage.nodes[self.front];
+        if self.front == self.back {
+            self.front = NIL;
+            self.back = NIL;
+        } else {
+            self.front = node.next;
+        }
+        Some(node.region)
+    }
+}
+
+impl DoubleEndedIterator for ArenaIter<'_> {
+    fn next_back(&mut self) -> Option<Region> {
+        if self.back == NIL {
+            return None;
+        }
+        let node = &self.storage.nodes[self.back];
+        if self.front == self.back {
+            self.front = NIL;
+            self.back = NIL;
+        } else {
+            self.back = node.prev;
+        }
+        Some(node.region)
+    }
+}
+
+impl RegionStorage for ArenaStorage {
+    type Iter<'a> = ArenaIter<'a>;
+
+    fn len(&self) -> usize {
+        self.len
+    }
+    fn range<R: RangeBounds<usize>>(&self, bases: R) -> Self::Iter<'_> {
+        let front = self.lower(bases.start_bound());
+        let back = self.upper(bases.end_bound());
+        let empty = front == NIL || back == NIL || {
+            let (f, b) = (&self.nodes[front].region, &self.nodes[back].region);
+            f.base > b.base
+        };
+        if empty {
+            ArenaIter {
+                storage: self,
+                front: NIL,
+                back: NIL,
+            }
+        } else {
+            ArenaIter {
+                storage: self,
+                front,
+                back,
+            }
+        }
+    }
+    fn splice(
+        &mut self,
+        bases: RangeInclusive<usize>,
+        with: &[Region],
+    ) -> Result<(), CapacityError> {
+        let first = self.lower(bases.start_bound());
+        let mut removed = 0;
+        let mut i = first;
+        while i != NIL && self.nodes[i].region.base <= *bases.end() {
+            removed += 1;
+            i = self.nodes[i].next;
+        }
+        // Reserve first so that no node is unlinked before the new ones are sure to fit,
+        // counting the pooled nodes and those about to be released
+        let pooled = self.nodes.len() - self.len + removed;
+        self.nodes
+            .try_reserve(with.len().saturating_sub(pooled))
+            .map_err(|_| CapacityError)?;
+        let mut next = first;
+        let prev = match next {
+            NIL => self.tail,
+            i => self.nodes[i].prev,
+        };
+        while next != NIL && self.nodes[next].region.base <= *bases.end() {
+            let i = next;
+            next = self.nodes[i].next;
+            self.free_node(i);
+            self.len -= 1;
+        }
+        let mut last = prev;
+        for r in with {
+            let i = self.alloc_node(*r);
+            self.nodes[i].prev = last;
+            match last {
+                NIL => self.head = i,
+                l => self.nodes[l].next = i,
+            }
+            last = i;
+            self.len += 1;
+        }
+        match last {
+            NIL => self.head = next,
+            l => self.nodes[l].next = next,
+        }
+        match next {
+            NIL => self.tail = last,
+            n => self.nodes[n].prev = last,
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+use super::{index_range, CapacityError, HeapFreeStorage, RegionStorage};
+use crate::Region;
+use core::iter::Copied;
+use core::ops::{RangeBounds, RangeInclusive};
+use core::slice;
+
+/// A storage holding up to `N` regions inline, without any heap allocation.
+///
+/// Operations that would need more than `N` regions fail with [`CapacityError`].
+#[derive(Clone, Debug)]
+pub struct ArrayStorage<const N: usize> {
+    regions: [Region; N],
+    len: usize,
+}
+
+impl<const N: usize> ArrayStorage<N> {
+    /// Create an empty [`ArrayStorage`].
+    pub const fn new() -> Self {
+        ArrayStorage {
+            regions: [Region { base: 0, size: 0 }; N],
+            len: 0,
+        }
+    }
+}
+
+impl<const N: usize> Default for ArrayStorage<N> {
+    fn default() -> Self {
+        ArrayStorage::new()
+    }
+}
+
+impl<const N: usize> RegionStorage for ArrayStorage<N> {
+    type Iter<'a> = Copied<slice::Iter<'a, Region>>;
+
+    fn len(&self) -> usize {
+        self.len
+    }
+    fn range<R: RangeBounds<usize>>(&self, bases: R) -> Self::Iter<'_> {
+        let regions = &self.regions[..self.len];
+        let (start, end) = index_range(regions, &bases, |r| r.base);
+        regions[start..end].iter().copied()
+    }
+    fn splice(
+        &mut self,
+        bases: RangeInclusive<usize>,
+        with: &[Region],
+    ) -> Result<(), CapacityError> {
+        let (start, end) = index_range(&self.regions[..self.len], &bases, |r| r.base);
+        let len = self.len - (end - start) + with.len();
+        if len > N {
+            return Err(CapacityError);
+        }
+        self.regions.copy_within(end..self.len, start + with.len());
+        self.regions[start..start + with.len()].copy_from_slice(with);
+        self.len = len;
+        Ok(())
+    }
+}
+
+impl<const N: usize> HeapFreeStorage for ArrayStorage<N> {}
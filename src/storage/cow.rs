@@ -0,0 +1,56 @@
+use super::{BTreeStorage, CapacityError, RegionStorage};
+use crate::Region;
+use alloc::sync::Arc;
+use core::ops::{RangeBounds, RangeInclusive};
+
+/// A storage shared by its clones until one of them changes, which then copies it.
+///
+/// A [`RegionAllocator`](crate::RegionAllocator) over a [`CowStorage`] clones in constant
+/// time, so trying out several layouts on clones of one set only copies the sets the
+/// layouts change, once each. Queries and fit searches go to the wrapped storage.
+#[derive(Clone, Debug, Default)]
+pub struct CowStorage<S = BTreeStorage> {
+    inner: Arc<S>,
+}
+
+impl<S: RegionStorage + Clone> CowStorage<S> {
+    /// Wrap a storage, not shared yet.
+    pub fn new(storage: S) -> Self {
+        CowStorage {
+            inner: Arc::new(storage),
+        }
+    }
+    /// Check whether another clone still shares the storage, so that the next change
+    /// copies it.
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.inner) > 1
+    }
+}
+
+impl<S: RegionStorage + Clone> RegionStorage for CowStorage<S> {
+    type Iter<'a>
+        = S::Iter<'a>
+    where
+        S: 'a;
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+    fn range<R: RangeBounds<usize>>(&self, bases: R) -> Self::Iter<'_> {
+        self.inner.range(bases)
+    }
+    fn splice(
+        &mut self,
+        bases: RangeInclusive<usize>,
+        with: &[Region],
+    ) -> Result<(), CapacityError> {
+        // A failed splice leaves the copy, if one was made, equal to the shared storage
+        Arc::make_mut(&mut self.inner).splice(bases, with)
+    }
+    fn find_fit(&self, size: usize, align: usize) -> Option<usize> {
+        self.inner.find_fit(size, align)
+    }
+    fn histogram(&self) -> [usize; usize::BITS as usize] {
+        self.inner.histogram()
+    }
+}
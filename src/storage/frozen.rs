@@ -0,0 +1,44 @@
+use super::{index_range, CapacityError, RegionStorage};
+use crate::Region;
+use alloc::sync::Arc;
+use core::iter::Copied;
+use core::ops::{RangeBounds, RangeInclusive};
+use core::slice;
+
+/// A storage whose regions never change once it is created, shared by its clones.
+///
+/// It backs a [`FrozenRegionSet`](crate::FrozenRegionSet). Cloning it only bumps a
+/// reference count, and splicing always fails with [`CapacityError`].
+#[derive(Clone, Debug, Default)]
+pub struct FrozenStorage {
+    regions: Arc<[Region]>,
+}
+
+impl FrozenStorage {
+    /// Create a storage holding `regions`, which are expected to be sorted, disjoint and
+    /// non-adjacent.
+    pub fn new(regions: impl IntoIterator<Item = Region>) -> Self {
+        FrozenStorage {
+            regions: regions.into_iter().collect(),
+        }
+    }
+    /// Return the regions, sorted by base.
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+}
+
+impl RegionStorage for FrozenStorage {
+    type Iter<'a> = Copied<slice::Iter<'a, Region>>;
+
+    fn len(&self) -> usize {
+        self.regions.len()
+    }
+    fn range<R: RangeBounds<usize>>(&self, bases: R) -> Self::Iter<'_> {
+        let (start, end) = index_range(&self.regions, &bases, |r| r.base);
+        self.regions[start..end].iter().copied()
+    }
+    fn splice(&mut self, _: RangeInclusive<usize>, _: &[Region]) -> Result<(), CapacityError> {
+        Err(CapacityError)
+    }
+}
@@ -0,0 +1,205 @@
+use super::{CapacityError, HeapFreeStorage, RegionStorage};
+use crate::Region;
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+use core::ops::{Bound, RangeBounds, RangeInclusive};
+use core::ptr;
+
+/// List node written at the base of every free region.
+struct Node {
+    size: usize,
+    prev: *mut Node,
+    next: *mut Node,
+}
+
+/// A storage keeping its list nodes inside the free memory it tracks.
+///
+/// No memory outside the tracked regions is needed, which makes this storage suitable
+/// for a primary frame allocator. Every stored region must start at an address aligned
+/// for a pointer and be large enough for a node ([`IntrusiveStorage::MIN_SIZE`] bytes);
+/// operations that would store a smaller piece fail with [`CapacityError`], so keep
+/// all operations page-granular.
+#[derive(Debug)]
+pub struct IntrusiveStorage {
+    head: *mut Node,
+    tail: *mut Node,
+    len: usize,
+}
+
+// SAFETY: the nodes are only reachable through the storage.
+unsafe impl Send for IntrusiveStorage {}
+
+impl IntrusiveStorage {
+    /// Smallest region that can be stored.
+    pub const MIN_SIZE: usize = size_of::<Node>();
+
+    /// Create an empty [`IntrusiveStorage`].
+    ///
+    /// # Safety
+    ///
+    /// Every region added to the storage must be valid for reads and writes and
+    /// must not be accessed by anything else for as long as it stays in the set.
+    pub const unsafe fn new() -> Self {
+        IntrusiveStorage {
+            head: ptr::null_mut(),
+            tail: ptr::null_mut(),
+            len: 0,
+        }
+    }
+    fn storable(r: &Region) -> bool {
+        r.size >= Self::MIN_SIZE && r.base.is_multiple_of(align_of::<Node>())
+    }
+    /// Find the first node whose base is not below `bound`.
+    fn lower(&self, bound: Bound<&usize>) -> *mut Node {
+        let mut node = self.head;
+        while !node.is_null() {
+            let base = node as usize;
+            let inside = match bound {
+                Bound::Included(&b) => base >= b,
+                Bound::Excluded(&b) => base > b,
+                Bound::Unbounded => true,
+            };
+            if inside {
+                break;
+            }
+            // SAFETY: linked nodes live in regions owned by the storage.
+            node = unsafe { (*node).next };
+        }
+        node
+    }
+    /// Find the last node whose base is not above `bound`.
+    fn upper(&self, bound: Bound<&usize>) -> *mut Node {
+        let mut node = self.tail;
+        while !node.is_null() {
+            let base = node as usize;
+            let inside = match bound {
+                Bound::Included(&b) => base <= b,
+                Bound::Excluded(&b) => base < b,
+                Bound::Unbounded => true,
+            };
+            if inside {
+                break;
+            }
+            // SAFETY: linked nodes live in regions owned by the storage.
+            node = unsafe { (*node).prev };
+        }
+        node
+    }
+}
+
+/// Iterator over a range of an [`IntrusiveStorage`].
+pub struct IntrusiveIter<'a> {
+    front: *const Node,
+    back: *const Node,
+    _storage: PhantomData<&'a IntrusiveStorage>,
+}
+
+impl IntrusiveIter<'_> {
+    fn take(&mut self, node: *const Node) -> Region {
+        // SAFETY: linked nodes live in regions owned by the storage,
+        // which is borrowed for the lifetime of the iterator.
+        let n = unsafe { &*node };
+        if self.front == self.back {
+            self.front = ptr::null();
+            self.back = ptr::null();
+        } else if node == self.front {
+            self.front = n.next;
+        } else {
+            self.back = n.prev;
+        }
+        Region {
+            base: node as usize,
+            size: n.size,
+        }
+    }
+}
+
+impl Iterator for IntrusiveIter<'_> {
+    type Item = Region;
+
+    fn next(&mut self) -> Option<Region> {
+        if self.front.is_null() {
+            return None;
+        }
+        Some(self.take(self.front))
+    }
+}
+
+impl DoubleEndedIterator for IntrusiveIter<'_> {
+    fn next_back(&mut self) -> Option<Region> {
+        if self.back.is_null() {
+            return None;
+        }
+        Some(self.take(self.back))
+    }
+}
+
+impl RegionStorage for IntrusiveStorage {
+    type Iter<'a> = IntrusiveIter<'a>;
+
+    fn len(&self) -> usize {
+        self.len
+    }
+    fn range<R: RangeBounds<usize>>(&self, bases: R) -> Self::Iter<'_> {
+        let front = self.lower(bases.start_bound());
+        let back = self.upper(bases.end_bound());
+        let empty = front.is_null() || back.is_null() || front as usize > back as usize;
+        IntrusiveIter {
+            front: if empty { ptr::null() } else { front },
+            back: if empty { ptr::null() } else { back },
+            _storage: PhantomData,
+        }
+    }
+    fn splice(
+        &mut self,
+        bases: RangeInclusive<usize>,
+        with: &[Region],
+    ) -> Result<(), CapacityError> {
+        if !with.iter().all(Self::storable) {
+            return Err(CapacityError);
+        }
+        let mut next = self.lower(bases.start_bound());
+        // SAFETY: linked nodes live in regions owned by the storage, and the regions
+        // in `with` are parts of removed regions or newly added memory.
+        unsafe {
+            let prev = if next.is_null() {
+                self.tail
+            } else {
+                (*next).prev
+            };
+            while !next.is_null() && next as usize <= *bases.end() {
+                next = (*next).next;
+                self.len -= 1;
+            }
+            let mut last = prev;
+            for r in with {
+                let node = r.base as *mut Node;
+                node.write(Node {
+                    size: r.size,
+                    prev: last,
+                    next: ptr::null_mut(),
+                });
+                if last.is_null() {
+                    self.head = node;
+                } else {
+                    (*last).next = node;
+                }
+                last = node;
+                self.len += 1;
+            }
+            if last.is_null() {
+                self.head = next;
+            } else {
+                (*last).next = next;
+            }
+            if next.is_null() {
+                self.tail = last;
+            } else {
+                (*next).prev = last;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl HeapFreeStorage for IntrusiveStorage {}
@@ -0,0 +1,90 @@
+use super::tree::{commit, stage, to_region, unstage, Staged};
+use super::{BTreeStorage, CapacityError, RegionStorage};
+use crate::Region;
+use alloc::collections::BTreeMap;
+use core::ops::{RangeBounds, RangeInclusive};
+
+/// Number of power-of-two size classes.
+const CLASSES: usize = usize::BITS as usize;
+
+fn size_class(size: usize) -> usize {
+    match size {
+        0 => 0,
+        _ => size.ilog2() as usize,
+    }
+}
+
+/// A storage that additionally buckets regions by power-of-two size class.
+///
+/// [`RegionStorage::find_fit`] starts from the bucket of the requested size instead of
+/// scanning the whole set, so it returns a fitting base rather than the lowest one.
+/// Buckets are updated in [`RegionStorage::splice`] and thus always agree with merging.
+#[derive(Clone, Debug)]
+pub struct SizeClassStorage {
+    regions: BTreeStorage,
+    classes: [BTreeMap<usize, usize>; CLASSES],
+}
+
+impl SizeClassStorage {
+    /// Create an empty [`SizeClassStorage`].
+    pub fn new() -> Self {
+        SizeClassStorage {
+            regions: BTreeStorage::new(),
+            classes: core::array::from_fn(|_| BTreeMap::new()),
+        }
+    }
+}
+
+impl Default for SizeClassStorage {
+    fn default() -> Self {
+        SizeClassStorage::new()
+    }
+}
+
+impl RegionStorage for SizeClassStorage {
+    type Iter<'a> = <BTreeStorage as RegionStorage>::Iter<'a>;
+
+    fn len(&self) -> usize {
+        self.regions.len()
+    }
+    fn range<R: RangeBounds<usize>>(&self, bases: R) -> Self::Iter<'_> {
+        self.regions.range(bases)
+    }
+    fn splice(
+        &mut self,
+        bases: RangeInclusive<usize>,
+        with: &[Region],
+    ) -> Result<(), CapacityError> {
+        let old = self.regions.range(bases.clone());
+        let touched = old
+            .chain(with.iter().copied())
+            .fold(0usize, |mask, r| mask | 1 << size_class(r.size));
+        let classes = || (0..CLASSES).filter(move |c| touched >> c & 1 != 0);
+        let pieces = || with.iter().copied();
+        let class = |c| pieces().filter(move |r| size_class(r.size) == c);
+        // Stage every map before committing any, so that an unwind leaves all of them unchanged
+        let mut staged = Staged::new(self, |s| {
+            unstage(&mut s.regions.regions, pieces());
+            classes().for_each(|c| unstage(&mut s.classes[c], class(c)));
+        });
+        stage(&mut staged.regions.regions, pieces());
+        for c in classes() {
+            stage(&mut staged.classes[c], class(c));
+        }
+        let s = staged.finish();
+        commit(&mut s.regions.regions, bases.clone(), pieces());
+        for c in classes() {
+            commit(&mut s.classes[c], bases.clone(), class(c));
+        }
+        Ok(())
+    }
+    fn find_fit(&self, size: usize, align: usize) -> Option<usize> {
+        self.classes[size_class(size)..]
+            .iter()
+            .find_map(|class| class.iter().find_map(|e| to_region(e).fit(size, align)))
+    }
+    /// Read the counts off the buckets, without visiting any region.
+    fn histogram(&self) -> [usize; CLASSES] {
+        core::array::from_fn(|c| self.classes[c].len())
+    }
+}
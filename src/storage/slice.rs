@@ -0,0 +1,67 @@
+use super::{index_range, CapacityError, HeapFreeStorage, RegionStorage};
+use crate::Region;
+use core::iter::Copied;
+use core::mem::MaybeUninit;
+use core::ops::{RangeBounds, RangeInclusive};
+use core::slice;
+
+/// A storage using a caller-provided scratch buffer, for bring-up before the heap exists.
+///
+/// Once a heap is available, move the regions into a heap-backed storage with
+/// [`RegionAllocator::into_storage`](crate::RegionAllocator::into_storage) and release the buffer.
+#[derive(Debug)]
+pub struct SliceStorage<'a> {
+    buf: &'a mut [MaybeUninit<Region>],
+    len: usize,
+}
+
+impl<'a> SliceStorage<'a> {
+    /// Create an empty [`SliceStorage`] holding up to `buf.len()` regions.
+    pub const fn new(buf: &'a mut [MaybeUninit<Region>]) -> Self {
+        SliceStorage { buf, len: 0 }
+    }
+    /// Return number of regions the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+    fn regions(&self) -> &[Region] {
+        // SAFETY: the first `len` elements are always initialized, and
+        // `MaybeUninit<Region>` has the same layout as `Region`.
+        unsafe { slice::from_raw_parts(self.buf.as_ptr() as *const Region, self.len) }
+    }
+}
+
+impl RegionStorage for SliceStorage<'_> {
+    type Iter<'b>
+        = Copied<slice::Iter<'b, Region>>
+    where
+        Self: 'b;
+
+    fn len(&self) -> usize {
+        self.len
+    }
+    fn range<R: RangeBounds<usize>>(&self, bases: R) -> Self::Iter<'_> {
+        let regions = self.regions();
+        let (start, end) = index_range(regions, &bases, |r| r.base);
+        regions[start..end].iter().copied()
+    }
+    fn splice(
+        &mut self,
+        bases: RangeInclusive<usize>,
+        with: &[Region],
+    ) -> Result<(), CapacityError> {
+        let (start, end) = index_range(self.regions(), &bases, |r| r.base);
+        let len = self.len - (end - start) + with.len();
+        if len > self.buf.len() {
+            return Err(CapacityError);
+        }
+        self.buf.copy_within(end..self.len, start + with.len());
+        for (slot, r) in self.buf[start..].iter_mut().zip(with) {
+            *slot = MaybeUninit::new(*r);
+        }
+        self.len = len;
+        Ok(())
+    }
+}
+
+impl HeapFreeStorage for SliceStorage<'_> {}
@@ -0,0 +1,87 @@
+use super::tree::{to_region, Entry};
+use super::{index_range, CapacityError, RegionStorage};
+use crate::Region;
+use alloc::vec::Vec;
+use core::iter::{Map, Zip};
+use core::ops::{RangeBounds, RangeInclusive};
+use core::slice;
+
+/// Number of regions tested per step of [`SoaStorage::find_fit`].
+const LANES: usize = 8;
+
+/// A storage keeping bases and ends of regions in two parallel sorted arrays.
+///
+/// [`RegionStorage::find_fit`] tests a whole chunk of regions at once without branches,
+/// which the compiler turns into SIMD code. This pays off for large sets that are
+/// searched far more often than they are modified.
+#[derive(Clone, Debug, Default)]
+pub struct SoaStorage {
+    bases: Vec<usize>,
+    ends: Vec<usize>,
+}
+
+impl SoaStorage {
+    /// Create an empty [`SoaStorage`].
+    pub fn new() -> Self {
+        SoaStorage::default()
+    }
+    /// Create an empty [`SoaStorage`] with room for `capacity` regions.
+    pub fn with_capacity(capacity: usize) -> Self {
+        SoaStorage {
+            bases: Vec::with_capacity(capacity),
+            ends: Vec::with_capacity(capacity),
+        }
+    }
+}
+
+/// Whether `size` bytes aligned to `align + 1` fit into `[base, end)`, without branches.
+fn fits(base: usize, end: usize, size: usize, align: usize) -> bool {
+    let (aligned, o1) = base.overflowing_add(align);
+    let aligned = aligned & !align;
+    let (last, o2) = aligned.overflowing_add(size);
+    !o1 & !o2 & (last <= end)
+}
+
+impl RegionStorage for SoaStorage {
+    type Iter<'a> = Map<Zip<slice::Iter<'a, usize>, slice::Iter<'a, usize>>, fn(Entry) -> Region>;
+
+    fn len(&self) -> usize {
+        self.bases.len()
+    }
+    fn range<R: RangeBounds<usize>>(&self, bases: R) -> Self::Iter<'_> {
+        let (start, end) = index_range(&self.bases, &bases, |&b| b);
+        let pairs = self.bases[start..end].iter().zip(&self.ends[start..end]);
+        pairs.map(to_region as fn(Entry) -> Region)
+    }
+    fn splice(
+        &mut self,
+        bases: RangeInclusive<usize>,
+        with: &[Region],
+    ) -> Result<(), CapacityError> {
+        // Reserve first so that neither splice can fail, keeping the two columns in step
+        self.bases
+            .try_reserve(with.len())
+            .map_err(|_| CapacityError)?;
+        self.ends
+            .try_reserve(with.len())
+            .map_err(|_| CapacityError)?;
+        let (start, end) = index_range(&self.bases, &bases, |&b| b);
+        self.bases.splice(start..end, with.iter().map(|r| r.base));
+        self.ends.splice(start..end, with.iter().map(|r| r.end()));
+        Ok(())
+    }
+    fn find_fit(&self, size: usize, align: usize) -> Option<usize> {
+        let chunks = self.bases.chunks(LANES).zip(self.ends.chunks(LANES));
+        for (bases, ends) in chunks {
+            let mut mask = 0u32;
+            for (lane, (&b, &e)) in bases.iter().zip(ends).enumerate() {
+                mask |= (fits(b, e, size, align) as u32) << lane;
+            }
+            if mask != 0 {
+                let base = bases[mask.trailing_zeros() as usize];
+                return Some((base + align) & !align);
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,127 @@
+use super::{CapacityError, RegionStorage};
+use crate::Region;
+use alloc::collections::btree_map::{self, BTreeMap};
+use core::iter::Map;
+use core::ops::{Deref, DerefMut, RangeBounds, RangeInclusive};
+
+/// The default storage, backed by a [`BTreeMap`] from base to end.
+///
+/// Keying by base alone keeps a single entry per base address and lets
+/// lookups use plain address ranges.
+#[derive(Clone, Debug, Default)]
+pub struct BTreeStorage {
+    pub(super) regions: BTreeMap<usize, usize>,
+}
+
+impl BTreeStorage {
+    /// Create an empty [`BTreeStorage`].
+    pub const fn new() -> Self {
+        BTreeStorage {
+            regions: BTreeMap::new(),
+        }
+    }
+}
+
+pub(super) type Entry<'a> = (&'a usize, &'a usize);
+
+pub(super) fn to_region((&base, &end): Entry) -> Region {
+    Region {
+        base,
+        size: end - base,
+    }
+}
+
+impl RegionStorage for BTreeStorage {
+    type Iter<'a> = Map<btree_map::Range<'a, usize, usize>, fn(Entry) -> Region>;
+
+    fn len(&self) -> usize {
+        self.regions.len()
+    }
+    fn range<R: RangeBounds<usize>>(&self, bases: R) -> Self::Iter<'_> {
+        self.regions
+            .range(bases)
+            .map(to_region as fn(Entry) -> Region)
+    }
+    fn splice(
+        &mut self,
+        bases: RangeInclusive<usize>,
+        with: &[Region],
+    ) -> Result<(), CapacityError> {
+        let pieces = || with.iter().copied();
+        let mut staged = Staged::new(&mut self.regions, |map| unstage(map, pieces()));
+        stage(&mut staged, pieces());
+        commit(staged.finish(), bases, pieces());
+        Ok(())
+    }
+}
+
+/// Undoes a partially applied update if a panic unwinds before [`Staged::finish`].
+pub(super) struct Staged<'a, T, F: FnMut(&mut T)> {
+    target: Option<&'a mut T>,
+    undo: F,
+}
+
+impl<'a, T, F: FnMut(&mut T)> Staged<'a, T, F> {
+    pub(super) fn new(target: &'a mut T, undo: F) -> Self {
+        Staged {
+            target: Some(target),
+            undo,
+        }
+    }
+    /// Keep the update and release the target.
+    pub(super) fn finish(mut self) -> &'a mut T {
+        self.target.take().unwrap()
+    }
+}
+
+impl<T, F: FnMut(&mut T)> Deref for Staged<'_, T, F> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.target.as_ref().unwrap()
+    }
+}
+
+impl<T, F: FnMut(&mut T)> DerefMut for Staged<'_, T, F> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.target.as_mut().unwrap()
+    }
+}
+
+impl<T, F: FnMut(&mut T)> Drop for Staged<'_, T, F> {
+    fn drop(&mut self) {
+        if let Some(target) = self.target.take() {
+            (self.undo)(target);
+        }
+    }
+}
+
+// A map is updated in two steps so that a panic never loses regions. `stage` inserts the
+// missing bases of `with`, which is the only step that allocates, marking them with their
+// base as end; no stored region is empty, so `unstage` tells them apart and removes them.
+// `commit` then sets the real ends and drops stale entries without allocating.
+
+pub(super) fn stage(map: &mut BTreeMap<usize, usize>, with: impl Iterator<Item = Region>) {
+    for r in with {
+        map.entry(r.base).or_insert(r.base);
+    }
+}
+
+pub(super) fn unstage(map: &mut BTreeMap<usize, usize>, with: impl Iterator<Item = Region>) {
+    for r in with {
+        if map.get(&r.base) == Some(&r.base) {
+            map.remove(&r.base);
+        }
+    }
+}
+
+pub(super) fn commit(
+    map: &mut BTreeMap<usize, usize>,
+    bases: RangeInclusive<usize>,
+    with: impl Iterator<Item = Region> + Clone,
+) {
+    for r in with.clone() {
+        map.insert(r.base, r.end());
+    }
+    map.extract_if(bases, |&base, _| !with.clone().any(|r| r.base == base))
+        .for_each(drop);
+}
@@ -0,0 +1,53 @@
+use super::{index_range, CapacityError, RegionStorage};
+use crate::Region;
+use alloc::vec::Vec;
+use core::iter::Copied;
+use core::ops::{RangeBounds, RangeInclusive};
+use core::slice;
+
+/// A storage keeping regions in a sorted [`Vec`] searched by binary search.
+///
+/// For the small sets typical of boot memory maps this is more cache-friendly than
+/// [`BTreeStorage`](super::BTreeStorage) and does not allocate once enough capacity is reserved.
+#[derive(Clone, Debug, Default)]
+pub struct VecStorage {
+    regions: Vec<Region>,
+}
+
+impl VecStorage {
+    /// Create an empty [`VecStorage`].
+    pub fn new() -> Self {
+        VecStorage::default()
+    }
+    /// Create an empty [`VecStorage`] with room for `capacity` regions.
+    pub fn with_capacity(capacity: usize) -> Self {
+        VecStorage {
+            regions: Vec::with_capacity(capacity),
+        }
+    }
+}
+
+impl RegionStorage for VecStorage {
+    type Iter<'a> = Copied<slice::Iter<'a, Region>>;
+
+    fn len(&self) -> usize {
+        self.regions.len()
+    }
+    fn range<R: RangeBounds<usize>>(&self, bases: R) -> Self::Iter<'_> {
+        let (start, end) = index_range(&self.regions, &bases, |r| r.base);
+        self.regions[start..end].iter().copied()
+    }
+    fn splice(
+        &mut self,
+        bases: RangeInclusive<usize>,
+        with: &[Region],
+    ) -> Result<(), CapacityError> {
+        // Reserve first so that splicing cannot fail halfway through
+        self.regions
+            .try_reserve(with.len())
+            .map_err(|_| CapacityError)?;
+        let (start, end) = index_range(&self.regions, &bases, |r| r.base);
+        self.regions.splice(start..end, with.iter().copied());
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+//! Groups of changes to a region set made all at once or not at all.
+
+use crate::stats::Counters;
+use crate::{Op, Region, RegionAllocator, RegionError, RegionStorage};
+use alloc::vec::Vec;
+
+/// A group of changes to a [`RegionAllocator`], made by
+/// [`RegionAllocator::begin_transaction`].
+///
+/// Changes apply to the set as they are made, and the operations undoing each one are
+/// logged. [`Transaction::commit`] keeps them; [`Transaction::rollback`], or dropping the
+/// transaction, such as when `?` returns early, replays the log backwards, restoring
+/// the set, the allocation cursor and the counters as they were when it began.
+#[derive(Debug)]
+pub struct Transaction<'a, S: RegionStorage> {
+    regions: &'a mut RegionAllocator<S>,
+    undo: Vec<Op>,
+    cursor: Option<usize>,
+    counters: Counters,
+    committed: bool,
+}
+
+impl<S: RegionStorage> RegionAllocator<S> {
+    /// Begin a group of changes that can be rolled back as one.
+    pub fn begin_transaction(&mut self) -> Transaction<'_, S> {
+        Transaction {
+            cursor: self.cursor,
+            counters: self.counters,
+            regions: self,
+            undo: Vec::new(),
+            committed: false,
+        }
+    }
+}
+
+impl<S: RegionStorage> Transaction<'_, S> {
+    /// Return the set with the changes made so far.
+    pub fn regions(&self) -> &RegionAllocator<S> {
+        self.regions
+    }
+    /// See [`RegionAllocator::try_add`].
+    pub fn add(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        self.apply(Op::Add(Region { base, size })).map(drop)
+    }
+    /// See [`RegionAllocator::try_subtract`].
+    pub fn subtract(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        self.apply(Op::Subtract(Region { base, size })).map(drop)
+    }
+    /// See [`RegionAllocator::allocate_by_addr`].
+    pub fn allocate_by_addr(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        self.apply(Op::AllocateAddr(Region { base, size }))
+            .map(drop)
+    }
+    /// See [`RegionAllocator::allocate_by_size`].
+    pub fn allocate_by_size(
+        &mut self,
+        size: usize,
+        alignment: usize,
+    ) -> Result<(usize, usize), RegionError> {
+        let taken = self.apply(Op::AllocateSize { size, alignment })?;
+        let r = taken.expect("allocations return the region taken");
+        Ok((r.base, r.size))
+    }
+    /// Keep the changes.
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+    /// Undo the changes, as dropping the transaction does.
+    pub fn rollback(self) {}
+
+    fn apply(&mut self, op: Op) -> Result<Option<Region>, RegionError> {
+        self.regions.apply_undoable(op, &mut self.undo)
+    }
+}
+
+impl<S: RegionStorage> Drop for Transaction<'_, S> {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        self.regions.undo(self.undo.drain(..));
+        self.regions.cursor = self.cursor;
+        self.regions.counters = self.counters;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{RegionError, StaticRegionAllocator};
+
+    #[test]
+    fn transaction_test() {
+        let mut regions = StaticRegionAllocator::<4>::default();
+        regions.add(0x1000, 0x1000);
+        regions.add(0x4000, 0x2000);
+        let before = regions.clone();
+        // Case 1: rolling back restores the set, whatever the changes covered
+        let mut tx = regions.begin_transaction();
+        assert_eq!(tx.add(0, 0x5000), Ok(()));
+        assert_eq!(tx.subtract(0x800, 0x4000), Ok(()));
+        assert_eq!(tx.allocate_by_size(0x800, 0x800), Ok((0, 0x800)));
+        assert_eq!(tx.allocate_by_addr(0x4800, 0x1800), Ok(()));
+        assert_eq!(tx.regions().len(), 0);
+        tx.rollback();
+        assert!(regions == before);
+        assert_eq!(regions.stats(), before.stats());
+        // Case 2: a dropped transaction rolls back too
+        let mut reconfigure = || -> Result<(), RegionError> {
+            let mut tx = regions.begin_transaction();
+            tx.subtract(0x1000, 0x1000)?;
+            tx.allocate_by_addr(0x8000, 0x1000)?;
+            tx.commit();
+            Ok(())
+        };
+        assert_eq!(reconfigure(), Err(RegionError::NotCovered));
+        assert!(regions == before);
+        // Case 3: committed changes stay
+        let mut tx = regions.begin_transaction();
+        assert_eq!(tx.allocate_by_addr(0x4000, 0x1000), Ok(()));
+        tx.commit();
+        assert!(!regions.check_point(0x4000));
+        assert_eq!(regions.stats().allocations, 1);
+    }
+}
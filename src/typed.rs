@@ -0,0 +1,175 @@
+//! A [`RegionAllocator`] over an address newtype, so sets of different address spaces
+//! cannot be mixed up.
+
+#[cfg(feature = "alloc")]
+use crate::BTreeStorage;
+use crate::{RegionAllocator, RegionError, RegionStorage};
+use core::fmt;
+use core::marker::PhantomData;
+
+/// An address type, such as a physical or virtual address newtype.
+///
+/// Sizes stay `usize` bytes; only positions are typed.
+pub trait Address: Copy {
+    /// Return the address as a plain number.
+    fn to_usize(self) -> usize;
+    /// Create an address from a plain number that was once returned by
+    /// [`Address::to_usize`] or lies in a region built from such addresses.
+    fn from_usize(addr: usize) -> Self;
+}
+
+impl Address for usize {
+    fn to_usize(self) -> usize {
+        self
+    }
+    fn from_usize(addr: usize) -> Self {
+        addr
+    }
+}
+
+/// A [`RegionAllocator`] taking and returning addresses of type `A`.
+///
+/// Two `TypedRegionAllocator`s over different address types are different types, so a
+/// physical frame set cannot be handed where a virtual address space is expected. The
+/// untyped set stays reachable for the operations not mirrored here.
+pub struct TypedRegionAllocator<
+    A,
+    #[cfg(feature = "alloc")] S = BTreeStorage,
+    #[cfg(not(feature = "alloc"))] S,
+> {
+    inner: RegionAllocator<S>,
+    addr: PhantomData<fn(A) -> A>,
+}
+
+#[cfg(feature = "alloc")]
+impl<A: Address> TypedRegionAllocator<A> {
+    /// Create an empty set backed by a `BTreeStorage`.
+    pub const fn new() -> Self {
+        TypedRegionAllocator::from_untyped(RegionAllocator::new())
+    }
+}
+
+impl<A: Address, S: RegionStorage> TypedRegionAllocator<A, S> {
+    /// Give addresses of `inner` the type `A`.
+    pub const fn from_untyped(inner: RegionAllocator<S>) -> Self {
+        TypedRegionAllocator {
+            inner,
+            addr: PhantomData,
+        }
+    }
+    /// Return the untyped set.
+    pub fn as_untyped(&self) -> &RegionAllocator<S> {
+        &self.inner
+    }
+    /// Return the untyped set, to change it through operations not mirrored here.
+    pub fn as_untyped_mut(&mut self) -> &mut RegionAllocator<S> {
+        &mut self.inner
+    }
+    /// Unwrap the untyped set.
+    pub fn into_untyped(self) -> RegionAllocator<S> {
+        self.inner
+    }
+    /// See [`RegionAllocator::add`].
+    pub fn add(&mut self, base: A, size: usize) {
+        self.inner.add(base.to_usize(), size)
+    }
+    /// See [`RegionAllocator::try_add`].
+    pub fn try_add(&mut self, base: A, size: usize) -> Result<(), RegionError> {
+        self.inner.try_add(base.to_usize(), size)
+    }
+    /// See [`RegionAllocator::subtract`].
+    pub fn subtract(&mut self, base: A, size: usize) {
+        self.inner.subtract(base.to_usize(), size)
+    }
+    /// See [`RegionAllocator::try_subtract`].
+    pub fn try_subtract(&mut self, base: A, size: usize) -> Result<(), RegionError> {
+        self.inner.try_subtract(base.to_usize(), size)
+    }
+    /// See [`RegionAllocator::allocate_by_addr`].
+    pub fn allocate_by_addr(&mut self, base: A, size: usize) -> Result<(), RegionError> {
+        self.inner.allocate_by_addr(base.to_usize(), size)
+    }
+    /// See [`RegionAllocator::allocate_by_size`].
+    pub fn allocate_by_size(
+        &mut self,
+        size: usize,
+        alignment: usize,
+    ) -> Result<(A, usize), RegionError> {
+        let (base, size) = self.inner.allocate_by_size(size, alignment)?;
+        Ok((A::from_usize(base), size))
+    }
+    /// See [`RegionAllocator::check_region`].
+    pub fn check_region(&self, base: A, size: usize) -> bool {
+        self.inner.check_region(base.to_usize(), size)
+    }
+    /// See [`RegionAllocator::check_point`].
+    pub fn check_point(&self, addr: A) -> bool {
+        self.inner.check_point(addr.to_usize())
+    }
+    /// Iterate over the regions as `(base, size)` pairs, in ascending order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (A, usize)> + '_ {
+        self.inner.iter().map(|r| (A::from_usize(r.start), r.len()))
+    }
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+}
+
+impl<A, S: Clone> Clone for TypedRegionAllocator<A, S> {
+    fn clone(&self) -> Self {
+        TypedRegionAllocator {
+            inner: self.inner.clone(),
+            addr: PhantomData,
+        }
+    }
+}
+
+impl<A: Address, S: RegionStorage + Default> Default for TypedRegionAllocator<A, S> {
+    fn default() -> Self {
+        TypedRegionAllocator::from_untyped(RegionAllocator::default())
+    }
+}
+
+impl<A, S: RegionStorage> fmt::Debug for TypedRegionAllocator<A, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::{Address, TypedRegionAllocator};
+    use alloc::vec::Vec;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Phys(usize);
+
+    impl Address for Phys {
+        fn to_usize(self) -> usize {
+            self.0
+        }
+        fn from_usize(addr: usize) -> Self {
+            Phys(addr)
+        }
+    }
+
+    #[test]
+    fn typed_test() {
+        let mut ram = TypedRegionAllocator::<Phys>::new();
+        ram.add(Phys(0x1000), 0x4000);
+        ram.subtract(Phys(0x2000), 0x1000);
+        assert_eq!(
+            ram.allocate_by_size(0x2000, 0x1000),
+            Ok((Phys(0x3000), 0x2000))
+        );
+        assert!(ram.check_region(Phys(0x1000), 0x1000));
+        assert!(!ram.check_point(Phys(0x3000)));
+        assert_eq!(ram.iter().collect::<Vec<_>>(), [(Phys(0x1000), 0x1000)]);
+        // The untyped set is the same one
+        let untyped = ram.into_untyped();
+        assert!(untyped.check_region(0x1000, 0x1000) && untyped.len() == 1);
+    }
+}
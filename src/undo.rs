@@ -0,0 +1,142 @@
+//! Changes to a region set that can be reverted after the fact, latest first.
+
+use crate::stats::Counters;
+use crate::{BTreeStorage, Op, Region, RegionAllocator, RegionError, RegionStorage};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// What reverting a change takes.
+#[derive(Clone, Debug)]
+struct Step {
+    undo: Vec<Op>,
+    cursor: Option<usize>,
+    counters: Counters,
+}
+
+/// A [`RegionAllocator`] remembering how to revert its latest changes, such as to give
+/// back what a device probe took once it fails halfway.
+///
+/// Up to `depth` changes are remembered, the oldest being forgotten first, each as the
+/// operations undoing it. Unlike a [`Transaction`](crate::Transaction), which is
+/// decided on as a whole, changes are reverted one by one with
+/// [`UndoRegionAllocator::undo`], as long as they are remembered.
+#[derive(Clone, Debug)]
+pub struct UndoRegionAllocator<S: RegionStorage = BTreeStorage> {
+    regions: RegionAllocator<S>,
+    history: VecDeque<Step>,
+    depth: usize,
+}
+
+impl<S: RegionStorage> UndoRegionAllocator<S> {
+    /// Wrap a [`RegionAllocator`], remembering up to `depth` changes.
+    pub fn new(regions: RegionAllocator<S>, depth: usize) -> Self {
+        UndoRegionAllocator {
+            regions,
+            history: VecDeque::new(),
+            depth,
+        }
+    }
+    /// Return the set.
+    pub fn regions(&self) -> &RegionAllocator<S> {
+        &self.regions
+    }
+    /// Unwrap the set, forgetting the changes.
+    pub fn into_inner(self) -> RegionAllocator<S> {
+        self.regions
+    }
+    /// Return number of changes that can be reverted.
+    pub fn undoable(&self) -> usize {
+        self.history.len()
+    }
+    /// Forget every change, so none can be reverted any more.
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+    /// See [`RegionAllocator::try_add`].
+    pub fn add(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        self.apply(Op::Add(Region { base, size })).map(drop)
+    }
+    /// See [`RegionAllocator::try_subtract`].
+    pub fn subtract(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        self.apply(Op::Subtract(Region { base, size })).map(drop)
+    }
+    /// See [`RegionAllocator::allocate_by_addr`].
+    pub fn allocate_by_addr(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        self.apply(Op::AllocateAddr(Region { base, size }))
+            .map(drop)
+    }
+    /// See [`RegionAllocator::allocate_by_size`].
+    pub fn allocate_by_size(
+        &mut self,
+        size: usize,
+        alignment: usize,
+    ) -> Result<(usize, usize), RegionError> {
+        let taken = self.apply(Op::AllocateSize { size, alignment })?;
+        let r = taken.expect("allocations return the region taken");
+        Ok((r.base, r.size))
+    }
+    /// Revert the latest change still remembered, restoring the set, the allocation
+    /// cursor and the counters as they were before it. Return `false` if there is none.
+    pub fn undo(&mut self) -> bool {
+        let step = match self.history.pop_back() {
+            Some(step) => step,
+            None => return false,
+        };
+        self.regions.undo(step.undo.into_iter());
+        self.regions.cursor = step.cursor;
+        self.regions.counters = step.counters;
+        true
+    }
+
+    fn apply(&mut self, op: Op) -> Result<Option<Region>, RegionError> {
+        let (cursor, counters) = (self.regions.cursor, self.regions.counters);
+        let mut undo = Vec::new();
+        let taken = self.regions.apply_undoable(op, &mut undo)?;
+        if self.depth > 0 {
+            if self.history.len() == self.depth {
+                self.history.pop_front();
+            }
+            self.history.push_back(Step {
+                undo,
+                cursor,
+                counters,
+            });
+        }
+        Ok(taken)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UndoRegionAllocator;
+    use crate::{RegionAllocator, RegionError};
+
+    #[test]
+    fn undo_test() {
+        let mut boot = RegionAllocator::new();
+        boot.add(0, 0x4000);
+        let mut regions = UndoRegionAllocator::new(boot.clone(), 2);
+        // Case 1: changes are reverted latest first
+        assert_eq!(regions.add(0x2000, 0x4000), Ok(()));
+        assert_eq!(regions.allocate_by_size(0x1000, 0x1000), Ok((0, 0x1000)));
+        assert!(regions.undo());
+        assert!(regions.regions().check_region(0, 0x6000));
+        assert_eq!(regions.regions().stats().allocations, 0);
+        assert!(regions.undo());
+        assert!(regions.regions() == &boot);
+        assert!(!regions.undo());
+        // Case 2: failed changes are not remembered, the oldest ones are forgotten
+        assert_eq!(
+            regions.allocate_by_addr(0x8000, 1),
+            Err(RegionError::NotCovered)
+        );
+        assert_eq!(regions.subtract(0x1000, 0x1000), Ok(()));
+        assert_eq!(regions.subtract(0x3000, 0x1000), Ok(()));
+        assert_eq!(regions.add(0x1800, 0x800), Ok(()));
+        assert_eq!(regions.undoable(), 2);
+        assert!(regions.undo() && regions.undo());
+        assert!(!regions.undo());
+        assert!(!regions.regions().check_point(0x1000));
+        assert!(regions.regions().check_point(0x3000));
+    }
+}
@@ -0,0 +1,113 @@
+//! Interrupt vectors allocated from per-CPU vector spaces, including MSI blocks.
+
+use crate::{BTreeStorage, IdAllocator, RegionError, RegionStorage};
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// An allocator of interrupt vectors with one vector space per CPU.
+///
+/// Multi-message MSI needs a block of vectors whose size is a power of 2 and whose first
+/// vector is a multiple of that size, since the device ORs the message number into the
+/// low bits of the data; [`VectorAllocator::allocate_msi`] hands out such blocks.
+#[derive(Clone, Debug)]
+pub struct VectorAllocator<S: RegionStorage = BTreeStorage> {
+    spaces: Vec<IdAllocator<S>>,
+}
+
+impl VectorAllocator {
+    /// Create `cpus` vector spaces, each holding the vectors in `vectors`.
+    pub fn new(cpus: usize, vectors: Range<usize>) -> Result<Self, RegionError> {
+        let spaces = (0..cpus)
+            .map(|_| {
+                let mut space = IdAllocator::new();
+                space.add_pool(vectors.clone()).map(|_| space)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(VectorAllocator { spaces })
+    }
+}
+
+impl<S: RegionStorage> VectorAllocator<S> {
+    /// Create an allocator from one vector space per CPU.
+    pub fn from_spaces(spaces: Vec<IdAllocator<S>>) -> Self {
+        VectorAllocator { spaces }
+    }
+    /// Return number of CPUs.
+    pub fn cpus(&self) -> usize {
+        self.spaces.len()
+    }
+    /// Return the vector space of `cpu`, to reserve vectors it must not hand out.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cpu` is not below [`VectorAllocator::cpus`].
+    pub fn space_mut(&mut self, cpu: usize) -> &mut IdAllocator<S> {
+        &mut self.spaces[cpu]
+    }
+    /// Return number of free vectors on `cpu`.
+    pub fn free_vectors(&self, cpu: usize) -> usize {
+        self.spaces[cpu].free_ids().iter().map(|r| r.len()).sum()
+    }
+    /// Allocate the lowest free vector on `cpu`.
+    pub fn allocate(&mut self, cpu: usize) -> Option<usize> {
+        self.spaces[cpu].allocate()
+    }
+    /// Allocate an MSI block of `count` vectors on `cpu`, rounded up to a power of 2 and
+    /// aligned to that size.
+    ///
+    /// Return the first vector and the rounded count.
+    pub fn allocate_msi(
+        &mut self,
+        cpu: usize,
+        count: usize,
+    ) -> Result<(usize, usize), RegionError> {
+        let count = count
+            .max(1)
+            .checked_next_power_of_two()
+            .ok_or(RegionError::Overflow)?;
+        let vector = self.spaces[cpu].allocate_range(count, count)?;
+        Ok((vector, count))
+    }
+    /// Allocate an MSI block like [`VectorAllocator::allocate_msi`] on the CPU with the
+    /// most free vectors that has room for it, returning that CPU first.
+    pub fn allocate_msi_any(&mut self, count: usize) -> Result<(usize, usize, usize), RegionError> {
+        let mut cpus: Vec<_> = (0..self.cpus()).collect();
+        cpus.sort_by_key(|&cpu| core::cmp::Reverse(self.free_vectors(cpu)));
+        for cpu in cpus {
+            match self.allocate_msi(cpu, count) {
+                Ok((vector, count)) => return Ok((cpu, vector, count)),
+                Err(RegionError::NoFit) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(RegionError::NoFit)
+    }
+    /// Free `count` vectors from `vector` on `cpu`, as returned by an allocation.
+    pub fn free(&mut self, cpu: usize, vector: usize, count: usize) -> Result<(), RegionError> {
+        self.spaces[cpu].free_range(vector, count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VectorAllocator;
+    use crate::RegionError;
+
+    #[test]
+    fn vector_test() {
+        let mut vectors = VectorAllocator::new(2, 0x20..0x100).unwrap();
+        vectors.space_mut(0).allocate_id(0x80).unwrap();
+        assert_eq!(vectors.allocate(0), Some(0x20));
+        // Case 1: MSI blocks are powers of 2 aligned to their size
+        assert_eq!(vectors.allocate_msi(0, 3), Ok((0x24, 4)));
+        assert_eq!(vectors.allocate_msi(0, 32), Ok((0x40, 32)));
+        assert_eq!(vectors.allocate_msi(0, 64), Ok((0xc0, 64)));
+        assert_eq!(vectors.allocate_msi(0, 64), Err(RegionError::NoFit));
+        // Case 2: the CPU with the most free vectors is tried first
+        assert_eq!(vectors.allocate_msi_any(64), Ok((1, 0x40, 64)));
+        assert_eq!(vectors.free(0, 0xc0, 64), Ok(()));
+        assert_eq!(vectors.allocate_msi_any(128), Ok((1, 0x80, 128)));
+        assert_eq!(vectors.free_vectors(1), 0x20);
+        assert_eq!(vectors.allocate_msi_any(64), Ok((0, 0xc0, 64)));
+    }
+}
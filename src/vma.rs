@@ -0,0 +1,251 @@
+//! An address space of mapped areas, as a kernel keeps per process.
+
+use crate::{RegionAllocator, RegionError};
+use alloc::collections::BTreeMap;
+use core::ops::Range;
+
+/// The object an area maps, at an offset into it.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Backing {
+    pub id: u64,
+    pub offset: usize,
+}
+
+/// The attributes of a mapped area.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Area {
+    /// Protection and other bits, opaque to the address space.
+    pub flags: u32,
+    /// The object mapped, or `None` for anonymous memory.
+    pub backing: Option<Backing>,
+}
+
+impl Area {
+    /// Return the attributes of the part of the area `delta` bytes in.
+    fn advance(mut self, delta: usize) -> Self {
+        if let Some(backing) = &mut self.backing {
+            backing.offset += delta;
+        }
+        self
+    }
+    /// Check whether an area of `size` bytes with these attributes can absorb the area
+    /// right after it.
+    fn continues_into(&self, size: usize, next: &Area) -> bool {
+        self.flags == next.flags && self.advance(size).backing == next.backing
+    }
+}
+
+/// A manager of the mapped areas of an address space.
+///
+/// Unmapped addresses are kept as a [`RegionAllocator`], which finds room for new
+/// mappings. Unmapping or changing part of an area splits it, and neighbouring areas
+/// that map the same object contiguously with the same flags are merged, so the area
+/// count stays what it would be had the mappings been made at once.
+#[derive(Clone, Debug)]
+pub struct AddressSpace {
+    free: RegionAllocator,
+    /// Sizes and attributes of the areas, by base.
+    areas: BTreeMap<usize, (usize, Area)>,
+}
+
+impl AddressSpace {
+    /// Create an address space of `size` bytes from `base`, nothing mapped.
+    pub fn new(base: usize, size: usize) -> Self {
+        let mut free = RegionAllocator::new();
+        free.add(base, size);
+        AddressSpace {
+            free,
+            areas: BTreeMap::new(),
+        }
+    }
+    /// Return the unmapped ranges.
+    pub fn free(&self) -> &RegionAllocator {
+        &self.free
+    }
+    /// Return number of areas.
+    pub fn len(&self) -> usize {
+        self.areas.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.areas.is_empty()
+    }
+    /// Return the area covering `addr`, if any.
+    pub fn find(&self, addr: usize) -> Option<(Range<usize>, Area)> {
+        let (&base, &(size, area)) = self.areas.range(..=addr).next_back()?;
+        (addr - base < size).then_some((base..base + size, area))
+    }
+    /// Iterate over the areas in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (Range<usize>, Area)> + '_ {
+        let areas = self.areas.iter();
+        areas.map(|(&base, &(size, area))| (base..base + size, area))
+    }
+    /// Map `size` bytes aligned to `alignment` wherever there is room, returning the base.
+    pub fn map(&mut self, size: usize, alignment: usize, area: Area) -> Result<usize, RegionError> {
+        if size == 0 {
+            return Err(RegionError::EmptyRange);
+        }
+        let (base, _) = self.free.allocate_by_size(size, alignment)?;
+        self.insert(base, size, area);
+        Ok(base)
+    }
+    /// Map `size` bytes at `base`, failing as [`RegionAllocator::subtract_checked`] does
+    /// if any of them is mapped or outside the address space.
+    pub fn map_fixed(&mut self, base: usize, size: usize, area: Area) -> Result<(), RegionError> {
+        if size == 0 {
+            return Err(RegionError::EmptyRange);
+        }
+        self.free.subtract_checked(base, size)?;
+        self.insert(base, size, area);
+        Ok(())
+    }
+    /// Unmap whatever is mapped in the `size` bytes from `base`, splitting the areas
+    /// that straddle either end.
+    pub fn unmap(&mut self, base: usize, size: usize) -> Result<(), RegionError> {
+        let end = base.checked_add(size).ok_or(RegionError::Overflow)?;
+        self.split(base);
+        self.split(end);
+        while let Some((&start, &(size, _))) = self.areas.range(base..end).next() {
+            self.areas.remove(&start);
+            self.free.add(start, size);
+        }
+        Ok(())
+    }
+    /// Replace the flags of the `size` bytes from `base`, which must all be mapped,
+    /// splitting and merging areas as needed.
+    ///
+    /// Fail with [`RegionError::NotCovered`] if any of them is not mapped.
+    pub fn protect(&mut self, base: usize, size: usize, flags: u32) -> Result<(), RegionError> {
+        let end = base.checked_add(size).ok_or(RegionError::Overflow)?;
+        if !self.covered(base, end) {
+            return Err(RegionError::NotCovered);
+        }
+        self.split(base);
+        self.split(end);
+        for (_, (_, area)) in self.areas.range_mut(base..end) {
+            area.flags = flags;
+        }
+        let mut addr = base;
+        while addr < end {
+            addr = self.merge(addr);
+        }
+        Ok(())
+    }
+
+    /// Check whether every byte from `base` to `end` is mapped.
+    fn covered(&self, base: usize, end: usize) -> bool {
+        let mut addr = base;
+        while addr < end {
+            match self.find(addr) {
+                Some((range, _)) => addr = range.end,
+                None => return false,
+            }
+        }
+        true
+    }
+    /// Split the area straddling `addr` in two at `addr`.
+    fn split(&mut self, addr: usize) {
+        if let Some((range, area)) = self.find(addr).filter(|(r, _)| r.start < addr) {
+            let left = addr - range.start;
+            self.areas.insert(range.start, (left, area));
+            self.areas
+                .insert(addr, (range.end - addr, area.advance(left)));
+        }
+    }
+    fn insert(&mut self, base: usize, size: usize, area: Area) {
+        self.areas.insert(base, (size, area));
+        self.merge(base);
+    }
+    /// Merge the area at `base` with compatible neighbours, returning the end of the
+    /// merged area.
+    fn merge(&mut self, mut base: usize) -> usize {
+        let (size, area) = self.areas[&base];
+        if let Some((&prev, &(prev_size, prev_area))) = self.areas.range(..base).next_back() {
+            if prev + prev_size == base && prev_area.continues_into(prev_size, &area) {
+                self.areas.remove(&base);
+                self.areas.insert(prev, (prev_size + size, prev_area));
+                base = prev;
+            }
+        }
+        let (size, area) = self.areas[&base];
+        if let Some(&(next_size, next_area)) = self.areas.get(&(base + size)) {
+            if area.continues_into(size, &next_area) {
+                self.areas.remove(&(base + size));
+                self.areas.insert(base, (size + next_size, area));
+                return base + size + next_size;
+            }
+        }
+        base + size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AddressSpace, Area, Backing};
+    use crate::RegionError;
+    use alloc::vec::Vec;
+
+    const ANON: Area = Area {
+        flags: 3,
+        backing: None,
+    };
+
+    fn file(offset: usize) -> Area {
+        Area {
+            flags: 1,
+            backing: Some(Backing { id: 7, offset }),
+        }
+    }
+
+    #[test]
+    fn vma_test() {
+        let mut space = AddressSpace::new(0x1000, 0xf000);
+        // Case 1: compatible neighbours merge, others stay apart
+        assert_eq!(space.map(0x2000, 0x1000, ANON), Ok(0x1000));
+        assert_eq!(space.map(0x1000, 0x1000, ANON), Ok(0x3000));
+        assert_eq!(space.map_fixed(0x4000, 0x2000, file(0x1000)), Ok(()));
+        assert_eq!(space.map_fixed(0x6000, 0x1000, file(0x4000)), Ok(()));
+        assert_eq!(
+            space.map_fixed(0x7000, 0x1000, file(0x4000 + 0x1000)),
+            Ok(())
+        );
+        assert_eq!(space.len(), 3);
+        assert_eq!(
+            space.map_fixed(0x7000, 0x1000, ANON),
+            Err(RegionError::NotCovered)
+        );
+        // Case 2: partial unmaps split areas, keeping the backing offsets right
+        assert_eq!(space.unmap(0x2000, 0x3000), Ok(()));
+        let areas: Vec<_> = space.iter().collect();
+        assert_eq!(
+            areas,
+            [
+                (0x1000..0x2000, ANON),
+                (0x5000..0x6000, file(0x2000)),
+                (0x6000..0x8000, file(0x4000))
+            ]
+        );
+        assert!(space.free().check_region(0x2000, 0x3000));
+        // Case 3: protecting splits and merges again
+        assert_eq!(space.protect(0x6000, 0x1000, 3), Ok(()));
+        assert_eq!(space.find(0x7000), Some((0x7000..0x8000, file(0x5000))));
+        assert_eq!(space.protect(0x6000, 0x1000, 1), Ok(()));
+        assert_eq!(space.find(0x7000), Some((0x6000..0x8000, file(0x4000))));
+        assert_eq!(space.protect(0x1000, 0x1000, 3), Ok(()));
+        assert_eq!(space.len(), 3);
+        assert_eq!(
+            space.protect(0x7000, 0x2000, 1),
+            Err(RegionError::NotCovered)
+        );
+        // Case 4: areas protected alike merge with each other
+        let mut space = AddressSpace::new(0x1000, 0xf000);
+        let ro = Area { flags: 1, ..ANON };
+        assert_eq!(space.map_fixed(0x1000, 0x1000, ro), Ok(()));
+        assert_eq!(space.map_fixed(0x2000, 0x1000, ANON), Ok(()));
+        assert_eq!(space.map_fixed(0x3000, 0x1000, ro), Ok(()));
+        assert_eq!(space.protect(0x1000, 0x2000, 3), Ok(()));
+        assert_eq!(space.find(0x1000), Some((0x1000..0x3000, ANON)));
+        assert_eq!(space.protect(0x2000, 0x1000, 1), Ok(()));
+        assert_eq!(space.find(0x2000), Some((0x2000..0x4000, ro)));
+        assert_eq!(space.len(), 2);
+    }
+}